@@ -2,7 +2,7 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 use regex::Regex;
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
 
 // 模拟器的引用
 // 注意：运行此测试时，需要将src\cpu\cpu.rs中的reset函数中的self.registers.pc = self.read_u16(0xFFFC);注释掉，从特定地址直接运行
@@ -20,7 +20,7 @@ pub fn run_test() {
     let log_path = "rom/nestest.log";
 
     let mut emulator =Emulator::new();
-    emulator.load_rom(rom_path);
+    emulator.load_rom(rom_path).expect("无法加载 ROM 文件");
  
     let log_file = File::open(&Path::new(log_path)).expect("Unable to open log file");
 
@@ -71,5 +71,32 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 }
-criterion_group!(benches, criterion_benchmark);
+
+// 每秒执行的指令数：每次迭代从复位开始执行固定条数的指令，criterion 按吞吐量报告 elem/s
+// 对比改动前后：先 cargo bench --bench cpu -- --save-baseline before，改动后再 cargo bench --bench cpu -- --baseline before
+const INSTRUCTIONS: u64 = 100_000;
+
+fn instructions_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function("instructions", |b| {
+        b.iter_batched(
+            || {
+                let mut emulator = Emulator::new();
+                emulator.load_rom("rom/nestest.nes").expect("无法加载 ROM 文件");
+                emulator
+            },
+            |mut emulator| {
+                for _ in 0..INSTRUCTIONS {
+                    emulator.cpu.step();
+                }
+                black_box(emulator.cpu.cpu_cycle)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark, instructions_benchmark);
 criterion_main!(benches);
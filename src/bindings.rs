@@ -0,0 +1,206 @@
+// 键盘按键设置，通过 eframe 的 persistence 保存，下次启动时恢复
+
+use std::collections::HashSet;
+
+use egui::Key;
+
+use crate::gamepad::GamepadMapping;
+
+// 保存在 eframe 存储中的键名
+pub const STORAGE_KEY: &str = "key_bindings";
+
+// 手柄按键的名称，顺序与按键字节的位相同
+pub const BUTTON_NAMES: [&str; 8] = ["A", "B", "Select", "Start", "Up", "Down", "Left", "Right"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    SaveState,
+    LoadState,
+    NextSlot,
+    FastForward,
+    Rewind,
+    Screenshot,
+    RecordMacro,
+    PlayMacro,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 10] = [
+        Hotkey::Pause,
+        Hotkey::Reset,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::NextSlot,
+        Hotkey::FastForward,
+        Hotkey::Rewind,
+        Hotkey::Screenshot,
+        Hotkey::RecordMacro,
+        Hotkey::PlayMacro,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::Pause => "暂停",
+            Hotkey::Reset => "复位",
+            Hotkey::SaveState => "保存存档",
+            Hotkey::LoadState => "读取存档",
+            Hotkey::NextSlot => "切换存档槽",
+            Hotkey::FastForward => "快进",
+            Hotkey::Rewind => "倒放",
+            Hotkey::Screenshot => "截图",
+            Hotkey::RecordMacro => "录制宏",
+            Hotkey::PlayMacro => "播放宏",
+        }
+    }
+}
+
+// 等待用户按键的设置项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingTarget {
+    Button { port: usize, button: usize },
+    Hotkey(Hotkey),
+    GamepadButton(usize), // 手柄上对应 A~Right 的按键
+    PowerPad(usize), // Power Pad 的第 n+1 个按键
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub controllers: [[Key; 8]; 2], // 两个手柄，顺序是A, B, Select, Start, Up, Down, Left, Right
+    pub pause: Key,
+    pub reset: Key,
+    pub save_state: Key,
+    pub load_state: Key,
+    pub next_slot: Key,
+    pub fast_forward: Key,
+    pub rewind: Key,
+    pub screenshot: Key,
+    pub record_macro: Key, // 开始/停止录制宏
+    pub play_macro: Key,
+    pub gamepad: GamepadMapping,
+    pub power_pad: [Key; 12], // Power Pad / Family Trainer 的 1~12 号按键，按垫子上的位置排成 3x4
+    pub turbo: [[u8; 8]; 4], // 1P~4P 每个按键的连发间隔 (帧)，0 为不连发
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            controllers: [
+                [Key::J, Key::K, Key::Space, Key::Enter, Key::W, Key::S, Key::A, Key::D],
+                [
+                    Key::Num1,
+                    Key::Num2,
+                    Key::Num3,
+                    Key::Num4,
+                    Key::ArrowUp,
+                    Key::ArrowDown,
+                    Key::ArrowLeft,
+                    Key::ArrowRight,
+                ],
+            ],
+            pause: Key::P,
+            reset: Key::R,
+            save_state: Key::F5,
+            load_state: Key::F7,
+            next_slot: Key::F6,
+            fast_forward: Key::Tab,
+            rewind: Key::Backspace,
+            screenshot: Key::F12,
+            record_macro: Key::F9,
+            play_macro: Key::F10,
+            gamepad: GamepadMapping::default(),
+            power_pad: [
+                Key::Num5,
+                Key::Num6,
+                Key::Num7,
+                Key::Num8,
+                Key::T,
+                Key::Y,
+                Key::U,
+                Key::I,
+                Key::V,
+                Key::B,
+                Key::N,
+                Key::M,
+            ],
+            turbo: [[0; 8]; 4],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn hotkey(&self, hotkey: Hotkey) -> Key {
+        match hotkey {
+            Hotkey::Pause => self.pause,
+            Hotkey::Reset => self.reset,
+            Hotkey::SaveState => self.save_state,
+            Hotkey::LoadState => self.load_state,
+            Hotkey::NextSlot => self.next_slot,
+            Hotkey::FastForward => self.fast_forward,
+            Hotkey::Rewind => self.rewind,
+            Hotkey::Screenshot => self.screenshot,
+            Hotkey::RecordMacro => self.record_macro,
+            Hotkey::PlayMacro => self.play_macro,
+        }
+    }
+
+    // 设置项当前的名称
+    pub fn name(&self, target: BindingTarget) -> String {
+        match target {
+            BindingTarget::Button { port, button } => self.controllers[port][button].name().to_string(),
+            BindingTarget::Hotkey(hotkey) => self.hotkey(hotkey).name().to_string(),
+            BindingTarget::GamepadButton(button) => format!("{:?}", self.gamepad.buttons[button]),
+            BindingTarget::PowerPad(button) => self.power_pad[button].name().to_string(),
+        }
+    }
+
+    pub fn set_gamepad_button(&mut self, button: usize, gamepad_button: gilrs::Button) {
+        self.gamepad.buttons[button] = gamepad_button;
+    }
+
+    // 设置键盘按键，手柄按键用 set_gamepad_button
+    pub fn set(&mut self, target: BindingTarget, key: Key) {
+        let slot = match target {
+            BindingTarget::GamepadButton(_) => return,
+            BindingTarget::Button { port, button } => &mut self.controllers[port][button],
+            BindingTarget::PowerPad(button) => &mut self.power_pad[button],
+            BindingTarget::Hotkey(Hotkey::Pause) => &mut self.pause,
+            BindingTarget::Hotkey(Hotkey::Reset) => &mut self.reset,
+            BindingTarget::Hotkey(Hotkey::SaveState) => &mut self.save_state,
+            BindingTarget::Hotkey(Hotkey::LoadState) => &mut self.load_state,
+            BindingTarget::Hotkey(Hotkey::NextSlot) => &mut self.next_slot,
+            BindingTarget::Hotkey(Hotkey::FastForward) => &mut self.fast_forward,
+            BindingTarget::Hotkey(Hotkey::Rewind) => &mut self.rewind,
+            BindingTarget::Hotkey(Hotkey::Screenshot) => &mut self.screenshot,
+            BindingTarget::Hotkey(Hotkey::RecordMacro) => &mut self.record_macro,
+            BindingTarget::Hotkey(Hotkey::PlayMacro) => &mut self.play_macro,
+        };
+        *slot = key;
+    }
+
+    // 按下的键转换为两个手柄的按键字节
+    pub fn controller_buttons(&self, keys: &HashSet<Key>) -> [u8; 2] {
+        let mut buttons = [0; 2];
+        for (port, keymap) in self.controllers.iter().enumerate() {
+            for (button, key) in keymap.iter().enumerate() {
+                if keys.contains(key) {
+                    buttons[port] |= 1 << button;
+                }
+            }
+        }
+        buttons
+    }
+
+    // 按下的键转换为 Power Pad 的按键，第 n-1 位为按键 n
+    pub fn mat_buttons(&self, keys: &HashSet<Key>) -> u16 {
+        let mut buttons = 0;
+        for (button, key) in self.power_pad.iter().enumerate() {
+            if keys.contains(key) {
+                buttons |= 1 << button;
+            }
+        }
+        buttons
+    }
+}
@@ -1,14 +1,21 @@
-use std::collections::HashSet;
-use std::collections::VecDeque;
+use crate::bus::dmc::Dmc;
+use crate::bus::frame_counter::FrameCounter;
+use crate::input::{Expansion, ExpansionDevice, InputDevice, PortDevice, StandardController};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::{NesError, NesResult};
 
-use egui::Key;
+// 读取 $4016 的指令最后一个字节是 $40，没有驱动的高位保持这个值
+const OPEN_BUS: u8 = 0x40;
 
 pub struct ApuIoRegisters{
     pub ram: [u8; 0x20],
-    pub inpur_reg:HashSet<egui::Key>, // 当前的输入状态，随时发生变化
-    pub current_input: VecDeque<u8>, // 0x4016 顺序是A, B, Select, Start, Up, Down, Left, Right
-    pub input_enable: bool,
+    pub port1: Box<dyn InputDevice>, // $4016 上的设备
+    pub port2: Box<dyn InputDevice>, // $4017 上的设备
+    pub expansion: Option<Box<dyn ExpansionDevice>>, // Famicom 扩展口上的设备
     pub input_history: u8, //用于debug
+    pub polled: bool, // 本帧是否读取过手柄，没读取的帧为延迟帧
+    pub frame_counter: FrameCounter,
+    pub dmc: Dmc,
 }
 
 
@@ -16,23 +23,42 @@ impl ApuIoRegisters {
     pub fn new() -> Self {
         ApuIoRegisters {
             ram: [0; 0x20],
-            current_input: VecDeque::new(),
-            input_enable: false,
+            port1: Box::new(StandardController::new()),
+            port2: Box::new(StandardController::new()),
+            expansion: None,
             input_history: 0,
-            inpur_reg: HashSet::new(),
+            polled: false,
+            frame_counter: FrameCounter::new(),
+            dmc: Dmc::new(),
         }
     }
 
     pub fn read(&mut self, addr: u16) -> u8 {
         let ram_addr = addr & 0x1F;
         let mut data = self.ram[ram_addr as usize];
+        if ram_addr == 0x16 || ram_addr == 0x17 {
+            self.polled = true;
+        }
         match ram_addr {
-            0x16 => {
-                if self.input_enable == true {
-                    // print!("当前输入{:?}",self.current_input);
-                    data = self.current_input.pop_front().unwrap_or(0);
-                    // println!("当前输出：{:?}",data);
+            0x15 => {
+                // 声道还没有实现，只有第7位的 DMC 中断标志、第6位的帧中断标志和第4位的 DMC 活动状态
+                // 开路总线的第5位为0，读取只应答帧中断
+                data = 0;
+                if self.dmc.irq_pending() {
+                    data |= 0x80;
+                }
+                if self.frame_counter.read_irq_flag() {
+                    data |= 0x40;
                 }
+                if self.dmc.active() {
+                    data |= 0x10;
+                }
+            },
+            0x16 => {
+                data = OPEN_BUS | (self.port1.read() & 0x1F) | self.read_expansion(0);
+            },
+            0x17 => {
+                data = OPEN_BUS | (self.port2.read() & 0x1F) | self.read_expansion(1);
             },
             _ => {}
         }
@@ -49,41 +75,141 @@ impl ApuIoRegisters {
         self.ram[ram_addr as usize]= data;
         match ram_addr {
             0x16 => {
-                if data & 0x01 == 0x01 {
-                    // println!("重置输入");
-                    self.input_enable = false;
-                    self.current_input = VecDeque::new();
-                }else if data & 0x01 == 0 {
-                    self.input_enable = true;
-                    if self.inpur_reg.is_empty(){
-                        self.current_input = VecDeque::new();
-                    }else {
-                        let mut current_input = VecDeque::from([0; 8].to_vec());
-                        for key in &self.inpur_reg {
-                            match key {
-                                Key::J => current_input[0] = 1,
-                                Key::K => current_input[1] = 1,
-                                Key::Space => current_input[2] = 1,
-                                Key::Enter => current_input[3] = 1,
-                                Key::W => current_input[4] = 1,
-                                Key::S => current_input[5] = 1,
-                                Key::A => current_input[6] = 1,
-                                Key::D => current_input[7] = 1,
-                                _ => {}
-                            }
-                        }
-                        self.current_input =  current_input;
-                    }
-                    // print!("接收到输入{:?}",self.current_input);
+                // 两个端口共用 $4016 的 strobe
+                self.port1.write_strobe(data & 0x01 == 0x01);
+                self.port2.write_strobe(data & 0x01 == 0x01);
+                if let Some(expansion) = &mut self.expansion {
+                    expansion.write(data & 0x07);
                 }
             },
+            0x10..=0x13 => self.dmc.write(ram_addr - 0x10, data),
+            0x15 => self.dmc.set_enabled(data & 0x10 != 0),
+            0x17 => self.frame_counter.write(data),
+            _ => {}
+        }
+    }
+
+    // 按键字节，顺序是A, B, Select, Start, Up, Down, Left, Right，player 为0~3
+    // 3P/4P 只在接了四人适配器时有效
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        match player {
+            0 => self.port1.set_buttons(buttons),
+            1 => self.port2.set_buttons(buttons),
+            2 => self.port1.set_extra_buttons(buttons),
+            3 => self.port2.set_extra_buttons(buttons),
             _ => {}
         }
     }
 
+    fn read_expansion(&mut self, port: usize) -> u8 {
+        self.expansion.as_mut().map_or(0, |expansion| expansion.read(port) & 0x1E)
+    }
+
+    pub fn expansion_kind(&self) -> Expansion {
+        self.expansion.as_ref().map_or(Expansion::None, |expansion| expansion.kind())
+    }
+
+    pub fn set_expansion(&mut self, expansion: Expansion) {
+        if self.expansion_kind() != expansion {
+            self.expansion = expansion.create();
+        }
+    }
+
+    pub fn set_pointer(&mut self, x: i32, y: i32, trigger: bool) {
+        self.port1.set_pointer(x, y, trigger);
+        self.port2.set_pointer(x, y, trigger);
+        if let Some(expansion) = &mut self.expansion {
+            expansion.set_pointer(x, y, trigger);
+        }
+    }
+
+    pub fn clock(&mut self) {
+        self.frame_counter.clock();
+        self.dmc.clock();
+        if let Some(expansion) = &mut self.expansion {
+            expansion.clock();
+        }
+    }
+
+    pub fn set_mat_buttons(&mut self, buttons: u16) {
+        self.port1.set_mat_buttons(buttons);
+        self.port2.set_mat_buttons(buttons);
+        if let Some(expansion) = &mut self.expansion {
+            expansion.set_mat_buttons(buttons);
+        }
+    }
+
+    pub fn port(&self, port: usize) -> &dyn InputDevice {
+        match port {
+            0 => self.port1.as_ref(),
+            _ => self.port2.as_ref(),
+        }
+    }
+
+    pub fn port_mut(&mut self, port: usize) -> &mut dyn InputDevice {
+        match port {
+            0 => self.port1.as_mut(),
+            _ => self.port2.as_mut(),
+        }
+    }
+
+    // 更换端口上的设备，设备相同时保留原来的状态
+    pub fn set_port_device(&mut self, port: usize, device: PortDevice) {
+        if self.port(port).kind() != device {
+            match port {
+                0 => self.port1 = device.create(0),
+                _ => self.port2 = device.create(1),
+            }
+        }
+    }
+
     pub fn reset(&mut self) {
         self.ram = [0; 0x20];
-        self.current_input =  VecDeque::new();
-        self.input_enable = false;
+        self.port1.reset();
+        self.port2.reset();
+        if let Some(expansion) = &mut self.expansion {
+            expansion.reset();
+        }
+        self.frame_counter.reset();
+        self.dmc.reset();
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_u8(self.port1.kind() as u8);
+        self.port1.save_state(writer);
+        writer.write_u8(self.port2.kind() as u8);
+        self.port2.save_state(writer);
+        writer.write_u8(self.expansion_kind() as u8);
+        if let Some(expansion) = &self.expansion {
+            expansion.save_state(writer);
+        }
+        writer.write_u8(self.input_history);
+        self.frame_counter.save_state(writer);
+        self.dmc.save_state(writer);
     }
-}
\ No newline at end of file
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.ram)?;
+        for port in 0..2 {
+            // 设备不同时状态的格式也不同，不能读取
+            let kind = reader.read_u8()?;
+            let device = self.port(port).kind();
+            if kind != device as u8 {
+                return Err(NesError::InvalidState(format!("存档中{}P接的设备与当前的{}不同", port + 1, device.name())));
+            }
+            self.port_mut(port).load_state(reader)?;
+        }
+        let kind = reader.read_u8()?;
+        let expansion = self.expansion_kind();
+        if kind != expansion as u8 {
+            return Err(NesError::InvalidState(format!("存档中扩展口接的设备与当前的{}不同", expansion.name())));
+        }
+        if let Some(expansion) = &mut self.expansion {
+            expansion.load_state(reader)?;
+        }
+        self.input_history = reader.read_u8()?;
+        self.frame_counter.load_state(reader)?;
+        self.dmc.load_state(reader)
+    }
+}
@@ -1,9 +1,12 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::VecDeque;
 use std::{default, thread};
 use crossbeam::channel::{bounded, select, Receiver, Sender};
-use egui::Key;
-use crate::mapper::{Mapper, create_mapper};
-use crate::bus::{nametable,registers,palettes,apu_io_registers};
+use crate::mapper::{Mapper, RomHeader, create_mapper, parse_rom};
+use crate::mapper::gamedb::{GameDb, GameInfo};
+use crate::mapper::fds::{FdsMapper, fds_rom_header};
+use crate::NesResult;
+use crate::utils::state::{StateReader, StateWriter};
+use crate::bus::{nametable,registers,palettes,apu_io_registers,mixer};
 
 use super::{cpu_ram, oam};
 
@@ -27,9 +30,18 @@ pub struct RWResult {
     pub is_success: bool,
 }
 
+// IRQ 线上的中断源
+// 各中断源并联在同一根线上 (线或)，任何一个有效时 IRQ 线就有效，由各自的寄存器分别应答
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqSource {
+    External = 0x01, // 卡带 (mapper、FDS)
+    FrameCounter = 0x02, // APU 帧计数器
+    Dmc = 0x04, // APU DMC 声道
+}
+
 pub struct Bus {
     // 0b0000_0xxx
-    //   |||| |||+-- IRQ
+    //   |||| |||+-- 未使用，IRQ 见 irq_sources
     //   |||| ||+--- VBlank/NMI
     //   |||| |+---- Reset
     pub interrupt_status: u8, 
@@ -43,12 +55,17 @@ pub struct Bus {
     palettes: palettes::Palettes,
     pub apu_io_registers: apu_io_registers::ApuIoRegisters,
     mapper: Box<dyn Mapper>,
+    pub rom_header: RomHeader, // 当前卡带的描述信息
+    pub game_info: Option<GameInfo>, // 游戏数据库中匹配到的条目
+    trainer: Option<Vec<u8>>, // 512字节的 trainer，每次复位后重新装入 $7000
     cpu_ram: cpu_ram::CpuRam, // debug
+    mixer: mixer::Mixer,
 }
 
 impl Bus {
-    pub fn new(input_stream:Receiver<HashSet<egui::Key>>) -> Self {
-        let default_mapper = Box::new(crate::mapper::mapper000::NromMapper::new(vec![0,0], vec![0,0], 1));
+    pub fn new() -> Self {
+        let rom_header = RomHeader { mirroring_type: 1, ..Default::default() };
+        let default_mapper = Box::new(crate::mapper::mapper000::NromMapper::new(vec![0,0], vec![0,0], &rom_header));
         Bus {
             interrupt_status: 0b0000_0000,
             registers: registers::Registers::new(),
@@ -60,12 +77,17 @@ impl Bus {
             palettes: palettes::Palettes::new(),
             apu_io_registers: apu_io_registers::ApuIoRegisters::new(),
             mapper: default_mapper,
+            rom_header,
+            game_info: None,
+            trainer: None,
             cpu_ram: cpu_ram::CpuRam::new(), // debug
+            mixer: mixer::Mixer::new(),
         }
     }
 
-    pub fn refresh_input(&mut self, new_input: HashSet<egui::Key>) {
-        self.apu_io_registers.inpur_reg = new_input;         
+    // 前端映射好的手柄按键，player 为0~3
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.apu_io_registers.set_buttons(player, buttons);
     }
 
     pub fn reset(&mut self) {
@@ -77,6 +99,7 @@ impl Bus {
         self.apu_io_registers.reset(); // debug
         self.cpu_ram.reset(); // debug
         self.mapper.reset(); 
+        self.load_trainer();
         self.interrupt_status = 0b0000_0000;
         self.vram_buffer=0;
         self.vram_addr=0;
@@ -88,8 +111,116 @@ impl Bus {
 
     }
 
-    pub fn load_rom(&mut self, rom: Vec<u8>) {
-        self.mapper= create_mapper(&rom);
+    // 解析失败时保留当前卡带不变
+    pub fn load_rom(&mut self, rom: Vec<u8>, game_db: &GameDb) -> NesResult<()> {
+        let mut rom_image = parse_rom(&rom)?;
+        // 数据库中有记录时，以数据库为准修正头
+        let game_info = game_db.lookup(&rom_image.rom_body()).cloned();
+        if let Some(game_info) = &game_info {
+            println!("游戏数据库: {} ({}, {:?})", game_info.title, game_info.board, game_info.region);
+            game_info.apply(&mut rom_image.rom_header);
+        }
+        self.mapper = create_mapper(&rom_image.rom_header, rom_image.prg_rom, rom_image.chr_rom)?;
+        self.trainer = rom_image.trainer;
+        self.rom_header = rom_image.rom_header;
+        self.game_info = game_info;
+        self.load_trainer();
+        Ok(())
+    }
+
+    // 加载 FDS 磁盘镜像，save 为之前保存的磁盘写入差异
+    pub fn load_fds(&mut self, image: Vec<u8>, bios: Vec<u8>, save: Option<&[u8]>) -> NesResult<()> {
+        let mapper = FdsMapper::new(image, bios, save)?;
+        self.rom_header = fds_rom_header(mapper.side_count());
+        self.mapper = Box::new(mapper);
+        self.trainer = None;
+        self.game_info = None;
+        Ok(())
+    }
+
+    // 每个 CPU 周期调用一次
+    pub fn clock(&mut self) {
+        self.mapper.cpu_clock();
+        self.apu_io_registers.clock();
+        self.mixer.clock(self.mapper.audio_output());
+    }
+
+    // 取走混合好的声音采样，采样率为 mixer::SAMPLE_RATE
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.mixer.take_samples()
+    }
+
+    // 当前有效的 IRQ 中断源，每一位对应一个 IrqSource，不为0时 IRQ 线有效
+    // 中断源自己保持电平直到被应答，CPU 响应中断不会清除它们
+    pub fn irq_sources(&self) -> u8 {
+        let mut sources = 0;
+        if self.mapper.irq_pending() {
+            sources |= IrqSource::External as u8;
+        }
+        if self.apu_io_registers.frame_counter.irq_pending() {
+            sources |= IrqSource::FrameCounter as u8;
+        }
+        if self.apu_io_registers.dmc.irq_pending() {
+            sources |= IrqSource::Dmc as u8;
+        }
+        sources
+    }
+
+    pub fn disk_side_count(&self) -> usize {
+        self.mapper.disk_side_count()
+    }
+
+    pub fn inserted_disk_side(&self) -> Option<usize> {
+        self.mapper.inserted_disk_side()
+    }
+
+    pub fn insert_disk_side(&mut self, side: Option<usize>) {
+        self.mapper.insert_disk_side(side);
+    }
+
+    pub fn disk_diff(&self) -> Option<Vec<u8>> {
+        self.mapper.disk_diff()
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.section(b"BUS ");
+        writer.write_u8(self.interrupt_status);
+        writer.write_bool(self.ppustatus_racing);
+        self.registers.save_state(writer);
+        self.nametable.save_state(writer);
+        writer.write_u8(self.vram_buffer);
+        writer.write_u16(self.vram_addr);
+        self.oam.save_state(writer);
+        self.palettes.save_state(writer);
+        self.apu_io_registers.save_state(writer);
+        self.cpu_ram.save_state(writer);
+        writer.section(b"MAPR");
+        self.mapper.save_state(writer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.section(b"BUS ")?;
+        self.interrupt_status = reader.read_u8()?;
+        self.ppustatus_racing = reader.read_bool()?;
+        self.registers.load_state(reader)?;
+        self.nametable.load_state(reader)?;
+        self.vram_buffer = reader.read_u8()?;
+        self.vram_addr = reader.read_u16()?;
+        self.oam.load_state(reader)?;
+        self.palettes.load_state(reader)?;
+        self.apu_io_registers.load_state(reader)?;
+        self.cpu_ram.load_state(reader)?;
+        reader.section(b"MAPR")?;
+        self.mapper.load_state(reader)
+    }
+
+    // 把 trainer 写入 PRG-RAM 的 $7000-$71FF
+    fn load_trainer(&mut self) {
+        if let Some(trainer) = &self.trainer {
+            for (i, &data) in trainer.iter().enumerate() {
+                self.mapper.write_prg_ram(0x7000 + i as u16, data);
+            }
+        }
     }
 
     // 无副作用的读，用于调试
@@ -131,8 +262,8 @@ impl Bus {
                 out_data
             }
             0x4020..=0x5FFF => {
-                //高三位为3:  扩展 ROM
-                todo!()
+                //高三位为3:  扩展 ROM，读有副作用（如 FDS 状态寄存器），调试时不读
+                0
             }
             0x6000..=0x7FFF => {
                 //高三位为4: 存档 SRAM
@@ -213,12 +344,11 @@ impl Bus {
             }
             0x4020..=0x5FFF => {
                 //高三位为3:  扩展 ROM
-                todo!()
+                self.mapper.read_expansion(addr)
             }
             0x6000..=0x7FFF => {
                 //高三位为4: 存档 SRAM
-                println!("read SRAM:{:04X}",addr);
-                todo!()
+                self.mapper.read_prg_ram(addr)
             }
             0x8000..=0xFFFF => {
                 //高三位为5:  PRG-ROM
@@ -286,12 +416,8 @@ impl Bus {
             0x4000..=0x401F => {
                 match addr {
                     0x4014 => {
-                        // 写入 OAMDMA 寄存器，进行 OAMDMA 操作
-                        let start_addr = (data as u16) << 8;
-                        for i in 0..0xff {
-                            let data = self.cpu_read(start_addr + i);
-                            self.oam.write(i, data);
-                        }
+                        // 写入 OAMDMA 寄存器，DMA 需要占用 CPU 周期，由 CPU 逐字节写入 $2004
+                        self.registers.oamdma = data;
                     },
                     _ => {
                         self.apu_io_registers.write(addr, data);
@@ -301,7 +427,7 @@ impl Bus {
             }
             0x4020..=0x5FFF => {
                 //高三位为3:  扩展 ROM
-                todo!()
+                self.mapper.write_expansion(addr, data);
             }
             0x6000..=0x7FFF => {
                 //高三位为4: 存档 SRAM
@@ -1,3 +1,6 @@
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
 pub struct CpuRam{
     ram: [u8; 0x800],
 }
@@ -28,4 +31,12 @@ impl CpuRam {
     pub fn reset(&mut self) {
         self.ram = [0; 0x800];
     }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.ram)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,134 @@
+// APU DMC 声道 ($4010~$4013)，目前只模拟样本的读取进度和它产生的 IRQ
+// 样本按 $4010 的速率逐位输出，每输出完8位取下一个字节；最后一个字节取出后
+// 如果没有循环且允许中断，就置位 DMC 中断标志，在 $4015 的第7位读出，写 $4015 或关闭中断应答
+// 声音输出和 DMA 占用的 CPU 周期没有模拟
+// https://www.nesdev.org/wiki/APU_DMC
+
+use crate::utils::state::{StateReader, StateWriter};
+use crate::{NesError, NesResult};
+
+// NTSC 各速率下输出一位的 CPU 周期数
+const RATE_TABLE: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+
+pub struct Dmc {
+    irq_enabled: bool, // $4010 第7位
+    looping: bool, // $4010 第6位：样本结束后从头开始
+    rate: u16, // $4010 低4位对应的周期数
+    sample_length: u16, // $4013 对应的样本字节数
+    bytes_remaining: u16, // 还没有读取的样本字节数，不为0时声道处于活动状态
+    buffer_full: bool, // 样本缓冲区里是否有字节
+    bits_remaining: u8, // 输出单元中还没有输出的位数
+    timer: u16, // 距离输出下一位的 CPU 周期数
+    irq_flag: bool, // DMC 中断标志
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            looping: false,
+            rate: RATE_TABLE[0],
+            sample_length: 1,
+            bytes_remaining: 0,
+            buffer_full: false,
+            bits_remaining: 8,
+            timer: RATE_TABLE[0],
+            irq_flag: false,
+        }
+    }
+
+    // 复位时相当于写 $4015 = 0，并关闭中断
+    pub fn reset(&mut self) {
+        self.irq_enabled = false;
+        self.irq_flag = false;
+        self.bytes_remaining = 0;
+    }
+
+    pub fn clock(&mut self) {
+        // 缓冲区空了就读取下一个字节
+        if !self.buffer_full && self.bytes_remaining > 0 {
+            self.buffer_full = true;
+            self.bytes_remaining -= 1;
+            if self.bytes_remaining == 0 {
+                if self.looping {
+                    self.bytes_remaining = self.sample_length;
+                } else if self.irq_enabled {
+                    self.irq_flag = true;
+                }
+            }
+        }
+        self.timer -= 1;
+        if self.timer == 0 {
+            self.timer = self.rate;
+            self.bits_remaining -= 1;
+            if self.bits_remaining == 0 {
+                // 8位输出完，缓冲区的字节移入输出单元
+                self.bits_remaining = 8;
+                self.buffer_full = false;
+            }
+        }
+    }
+
+    // $4010~$4013，只有 $4010 和 $4013 影响读取进度
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                self.looping = data & 0x40 != 0;
+                self.rate = RATE_TABLE[(data & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq_flag = false;
+                }
+            }
+            3 => self.sample_length = data as u16 * 16 + 1,
+            _ => {}
+        }
+    }
+
+    // 写 $4015：第4位开关声道，同时应答 DMC 中断
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.bytes_remaining = self.sample_length;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.irq_flag
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.looping);
+        writer.write_u16(self.rate);
+        writer.write_u16(self.sample_length);
+        writer.write_u16(self.bytes_remaining);
+        writer.write_bool(self.buffer_full);
+        writer.write_u8(self.bits_remaining);
+        writer.write_u16(self.timer);
+        writer.write_bool(self.irq_flag);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.irq_enabled = reader.read_bool()?;
+        self.looping = reader.read_bool()?;
+        self.rate = reader.read_u16()?;
+        self.sample_length = reader.read_u16()?;
+        self.bytes_remaining = reader.read_u16()?;
+        self.buffer_full = reader.read_bool()?;
+        self.bits_remaining = reader.read_u8()?;
+        self.timer = reader.read_u16()?;
+        self.irq_flag = reader.read_bool()?;
+        // 计数为0会在 clock 中下溢；改写速率后计时器要到下次重装才变，所以只限制在最长周期内
+        if !RATE_TABLE.contains(&self.rate) || self.timer == 0 || self.timer > RATE_TABLE[0] || !(1..=8).contains(&self.bits_remaining) {
+            return Err(NesError::InvalidState("DMC 计时器状态无效".to_string()));
+        }
+        Ok(())
+    }
+}
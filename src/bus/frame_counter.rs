@@ -0,0 +1,106 @@
+// APU 帧计数器 ($4017)，目前只模拟它产生的 IRQ
+// 四步模式下每 29830 个 CPU 周期在序列末尾连续三个周期置位帧中断标志，五步模式不产生中断
+// 读 $4015 或写 $4017 (第6位为1) 应答
+// https://www.nesdev.org/wiki/APU_Frame_Counter
+
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
+// NTSC 四步模式置位中断标志的周期，最后一个周期同时是下一个序列的开始
+const IRQ_CYCLES: [u32; 3] = [29828, 29829, 29830];
+const FOUR_STEP_PERIOD: u32 = 29830;
+const FIVE_STEP_PERIOD: u32 = 37282;
+
+pub struct FrameCounter {
+    five_step: bool, // 第7位：五步模式
+    irq_inhibit: bool, // 第6位：禁止中断
+    irq_flag: bool, // 帧中断标志，在 $4015 的第6位读出
+    cycle: u32, // 当前序列中的 CPU 周期
+    odd_cycle: bool, // 当前 CPU 周期的奇偶
+    pending_write: Option<(u8, u8)>, // 写 $4017 后等待生效的 (值, 剩余周期)
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter {
+            five_step: false,
+            irq_inhibit: false,
+            irq_flag: false,
+            cycle: 0,
+            odd_cycle: false,
+            pending_write: None,
+        }
+    }
+
+    // 复位时序列重新开始，模式保持不变
+    pub fn reset(&mut self) {
+        self.irq_flag = false;
+        self.cycle = 0;
+        self.pending_write = None;
+    }
+
+    pub fn clock(&mut self) {
+        self.cycle += 1;
+        if !self.five_step && IRQ_CYCLES.contains(&self.cycle) && !self.irq_inhibit {
+            self.irq_flag = true;
+        }
+        let period = if self.five_step { FIVE_STEP_PERIOD } else { FOUR_STEP_PERIOD };
+        if self.cycle >= period {
+            self.cycle = 0;
+        }
+        // 写入的模式在 APU 周期边界才生效：偶数周期写入等3个周期，奇数周期写入等4个周期
+        if let Some((value, delay)) = self.pending_write {
+            if delay <= 1 {
+                self.five_step = value & 0x80 != 0;
+                self.cycle = 0;
+                self.pending_write = None;
+            } else {
+                self.pending_write = Some((value, delay - 1));
+            }
+        }
+        self.odd_cycle = !self.odd_cycle;
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.irq_inhibit = data & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq_flag = false;
+        }
+        let delay = if self.odd_cycle { 4 } else { 3 };
+        self.pending_write = Some((data, delay));
+    }
+
+    // 读 $4015 得到中断标志并清除它
+    pub fn read_irq_flag(&mut self) -> bool {
+        let flag = self.irq_flag;
+        self.irq_flag = false;
+        flag
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.irq_flag
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.five_step);
+        writer.write_bool(self.irq_inhibit);
+        writer.write_bool(self.irq_flag);
+        writer.write_u32(self.cycle);
+        writer.write_bool(self.odd_cycle);
+        let (value, delay) = self.pending_write.unwrap_or((0, 0));
+        writer.write_u8(value);
+        writer.write_u8(delay);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.five_step = reader.read_bool()?;
+        self.irq_inhibit = reader.read_bool()?;
+        self.irq_flag = reader.read_bool()?;
+        self.cycle = reader.read_u32()?;
+        self.odd_cycle = reader.read_bool()?;
+        let value = reader.read_u8()?;
+        let delay = reader.read_u8()?;
+        self.pending_write = if delay == 0 { None } else { Some((value, delay)) };
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+// 把每个 CPU 周期的声音输出混合后降采样到 SAMPLE_RATE
+// APU 的方波/三角波/噪声/DMC 还没有实现，目前只混入卡带的扩展音频 (FDS)
+// 每个输出采样取这段时间内所有周期的平均值，前端用 take_samples 取走
+
+use std::collections::VecDeque;
+
+// NTSC CPU 频率
+const CPU_FREQUENCY: u32 = 1_789_773;
+pub const SAMPLE_RATE: u32 = 44_100;
+// 前端不取走时最多保留1秒，超出的旧采样丢弃
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE as usize;
+// 扩展音频满幅时的音量，与 APU 声道的总和同一量级
+const EXPANSION_VOLUME: f32 = 0.5;
+
+pub struct Mixer {
+    phase: u32, // 每个 CPU 周期加 SAMPLE_RATE，达到 CPU_FREQUENCY 时输出一个采样
+    sum: f32,
+    count: u32,
+    samples: VecDeque<f32>,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Mixer {
+            phase: 0,
+            sum: 0.0,
+            count: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    // 每个 CPU 周期调用一次，expansion 为卡带扩展音频的输出，范围 0.0..=1.0
+    pub fn clock(&mut self, expansion: f32) {
+        self.sum += expansion * EXPANSION_VOLUME;
+        self.count += 1;
+        self.phase += SAMPLE_RATE;
+        if self.phase >= CPU_FREQUENCY {
+            self.phase -= CPU_FREQUENCY;
+            if self.samples.len() >= MAX_BUFFERED_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(self.sum / self.count as f32);
+            self.sum = 0.0;
+            self.count = 0;
+        }
+    }
+
+    // 取走目前为止的采样
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+}
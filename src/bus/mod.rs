@@ -3,7 +3,10 @@ pub mod nametable;
 pub mod bus;
 mod palettes;
 mod apu_io_registers;
+mod frame_counter;
+mod dmc;
+pub mod mixer;
 mod cpu_ram;
 mod oam;
 
-pub use bus::{RWMessage,RWResult,RWType,Bus};
+pub use bus::{RWMessage,RWResult,RWType,Bus,IrqSource};
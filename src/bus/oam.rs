@@ -1,3 +1,6 @@
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
 pub struct Oam{
     oam: [u8; 0x100],
     pub oam_addr: u16,
@@ -42,4 +45,15 @@ impl Oam {
         self.oam = [0; 0x100];
         self.oam_addr = 0;
     }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.oam);
+        writer.write_u16(self.oam_addr);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.oam)?;
+        self.oam_addr = reader.read_u16()?;
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -1,3 +1,6 @@
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
 pub struct Palettes{
     palettes_ram : [u8; 0x0020],
 }
@@ -30,4 +33,12 @@ impl Palettes {
     pub fn reset(&mut self) {
         self.palettes_ram = [0; 0x0020];
     }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.palettes_ram);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.palettes_ram)
+    }
 }
\ No newline at end of file
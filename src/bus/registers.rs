@@ -1,3 +1,6 @@
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
 pub struct Registers {
     // PPUCTRL 寄存器，用于控制 PPU 的一些行为。
     // 7 6 5 4 3 2 1 0
@@ -120,5 +123,36 @@ impl Registers {
         self.ppudata = 0;
         self.oamdma = 0;
     }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[
+            self.ppuctrl,
+            self.ppumask,
+            self.ppustatus,
+            self.oamaddr,
+            self.oamdata,
+            self.ppuscroll,
+            self.ppuaddr,
+            self.ppudata,
+            self.oamdma,
+        ]);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        let mut registers = [0; 9];
+        reader.read_into(&mut registers)?;
+        [
+            self.ppuctrl,
+            self.ppumask,
+            self.ppustatus,
+            self.oamaddr,
+            self.oamdata,
+            self.ppuscroll,
+            self.ppuaddr,
+            self.ppudata,
+            self.oamdma,
+        ] = registers;
+        Ok(())
+    }
 }
 
@@ -2,11 +2,14 @@
 // src/cpu/cpu.rs
 
 use crate::cpu::instructions::Instruction;
-use crate::cpu::opcodes::decode_opcode;
+use crate::cpu::opcodes::{decode_opcode, OPCODE_TABLE};
 use crate::cpu::addressing_modes::AddressingMode;
 use crate::cpu::registers::{Registers,StatusFlags};
 use crate::bus::{RWMessage,RWResult,RWType, Bus};
+use crate::ppu::Ppu;
 use crate::utils::GlobalSignal;
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
 
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -15,7 +18,7 @@ use std::sync::{Arc, Mutex};
 use crossbeam::channel::{bounded, select, Receiver, Sender};
 
 // use super::cpu_ram::CpuRam;
-use super::instructions::InstructionInfo;
+use super::instructions::{InstructionInfo, InstructionType};
 
 
 /// 6502 CPU 的结构体
@@ -24,11 +27,13 @@ pub struct Cpu {
     interrupt: Interrupt, // 中断类型
     pub cpu_cycle: u64, // CPU 周期
     pub instruction_info: InstructionInfo, // 当前指令信息
-    pub cpu_cycle_wait: u64,
+    pub xaa_magic: u8, // XAA 的魔数，随芯片和温度不同，常见为 0xEE、0xFF
+    jammed: Option<u16>, // 执行 JAM 指令后停机，记录该指令的地址
     // cpu_ram: CpuRam, // CPU 内存
     // pub channels: CpuChannels,
     log: String,
     bus: Rc<RefCell<Bus>>,
+    ppu: Rc<RefCell<Ppu>>, // 每次总线访问时推进 PPU
 }
 
 pub struct CpuChannels {
@@ -36,18 +41,24 @@ pub struct CpuChannels {
     mem2cpu_out: Receiver<RWResult>,
 }
 
+// 中断线在每个周期结束时采样，指令结束后按倒数第二个周期的采样结果决定是否进入中断
+// https://www.nesdev.org/wiki/CPU_interrupts
 pub struct Interrupt {
-    nmi: (bool,bool), // (当前nmi状态,上一次nmi状态)
-    irq: bool,
-    reset: bool,
+    nmi_line: bool, // 上一个周期的 NMI 线，NMI 为边沿触发
+    need_nmi: bool, // 检测到 NMI 边沿，进入中断序列后清除
+    prev_need_nmi: bool, // 上一个周期的 need_nmi
+    run_irq: bool, // IRQ 线有效且 I 标志为0，IRQ 为电平触发，由中断源自己应答
+    prev_run_irq: bool, // 上一个周期的 run_irq
 }
 
 impl Default for Interrupt {
     fn default() -> Self {
         Interrupt {
-            nmi: (false,false),
-            irq: false,
-            reset: false,
+            nmi_line: false,
+            need_nmi: false,
+            prev_need_nmi: false,
+            run_irq: false,
+            prev_run_irq: false,
         }
     }
 }
@@ -95,13 +106,14 @@ impl Default for Interrupt {
 
 
 impl Cpu {
-    pub fn new(bus:Rc<RefCell<Bus>>) -> Self {
+    pub fn new(bus:Rc<RefCell<Bus>>, ppu: Rc<RefCell<Ppu>>) -> Self {
         Cpu {
             registers: Registers::default(),
             interrupt: Interrupt::default(),
             cpu_cycle: 0,
             instruction_info: InstructionInfo::default(),
-            cpu_cycle_wait: 1,
+            xaa_magic: 0xEE,
+            jammed: None,
             // cpu_ram: CpuRam::new(),
             // channels: CpuChannels{
             //     cpu2mem_in,
@@ -109,61 +121,136 @@ impl Cpu {
             // },
             log: String::new(),
             bus,
+            ppu,
         }
     }
     
     //https://www.nesdev.org/wiki/CPU_power_up_state ,待优化    
+    // 复位序列的7个周期不推进 PPU，PPU 复位时直接从第21个点开始
     pub fn reset(&mut self) {
         self.registers.sp = 0xFD; 
-        self.registers.pc = self.read_u16(0xFFFC); // 从内存中读取复位向量
+        // 从内存中读取复位向量
+        let low_byte = self.bus.borrow_mut().cpu_read(0xFFFC) as u16;
+        let high_byte = self.bus.borrow_mut().cpu_read(0xFFFD) as u16;
+        self.registers.pc = (high_byte << 8) | low_byte;
         self.registers.set_flag(StatusFlags::InterruptDisable, true);
         self.cpu_cycle=7;
-        self.cpu_cycle_wait=1;
+        self.interrupt = Interrupt::default();
+        self.jammed = None;
         self.instruction_info=InstructionInfo::default();
         self.log=String::new();
     }
 
+    // 停机时返回 JAM 指令的地址
+    pub fn jammed(&self) -> Option<u16> {
+        self.jammed
+    }
+
     pub fn hard_reset(&mut self) {
         self.reset();
         self.registers.p = 0x34;
-        self.interrupt = Interrupt::default();
     }
 
-    fn read(&self, address: u16) -> u8 {
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.section(b"CPU ");
+        writer.write_u8(self.registers.a);
+        writer.write_u8(self.registers.x);
+        writer.write_u8(self.registers.y);
+        writer.write_u16(self.registers.pc);
+        writer.write_u8(self.registers.sp);
+        writer.write_u8(self.registers.p);
+        writer.write_u64(self.cpu_cycle);
+        writer.write_u8(self.instruction_info.operand_code);
+        writer.write_bool(self.interrupt.nmi_line);
+        writer.write_bool(self.interrupt.need_nmi);
+        writer.write_bool(self.interrupt.prev_need_nmi);
+        writer.write_bool(self.interrupt.run_irq);
+        writer.write_bool(self.interrupt.prev_run_irq);
+        writer.write_bool(self.jammed.is_some());
+        writer.write_u16(self.jammed.unwrap_or(0));
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.section(b"CPU ")?;
+        self.registers.a = reader.read_u8()?;
+        self.registers.x = reader.read_u8()?;
+        self.registers.y = reader.read_u8()?;
+        self.registers.pc = reader.read_u16()?;
+        self.registers.sp = reader.read_u8()?;
+        self.registers.p = reader.read_u8()?;
+        self.cpu_cycle = reader.read_u64()?;
+        self.instruction_info = OPCODE_TABLE[reader.read_u8()? as usize];
+        self.interrupt.nmi_line = reader.read_bool()?;
+        self.interrupt.need_nmi = reader.read_bool()?;
+        self.interrupt.prev_need_nmi = reader.read_bool()?;
+        self.interrupt.run_irq = reader.read_bool()?;
+        self.interrupt.prev_run_irq = reader.read_bool()?;
+        let jammed = reader.read_bool()?;
+        let jam_address = reader.read_u16()?;
+        self.jammed = if jammed { Some(jam_address) } else { None };
+        Ok(())
+    }
+
+    // 一个 CPU 周期：PPU 走3个点，卡带和输入设备走1个周期
+    // 每次总线访问之前调用，读写因此发生在指令中真正的那个周期
+    fn tick(&mut self) {
+        {
+            let mut ppu = self.ppu.borrow_mut();
+            for _ in 0..3 {
+                ppu.step();
+            }
+        }
+        self.bus.borrow_mut().clock();
+        self.cpu_cycle += 1;
+    }
+
+    // 周期结束时采样 NMI 和 IRQ 线，这时本周期的读写已经完成
+    fn poll_interrupts(&mut self) {
+        let (nmi, irq) = {
+            let bus = self.bus.borrow();
+            (bus.interrupt_status & 2 == 2, bus.irq_sources() != 0)
+        };
+        self.interrupt.prev_need_nmi = self.interrupt.need_nmi;
+        if nmi && !self.interrupt.nmi_line {
+            self.interrupt.need_nmi = true;
+        }
+        self.interrupt.nmi_line = nmi;
+        self.interrupt.prev_run_irq = self.interrupt.run_irq;
+        self.interrupt.run_irq = irq && !self.registers.get_flag(StatusFlags::InterruptDisable);
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
         let read_result = self.bus.borrow_mut().cpu_read(address);
+        self.poll_interrupts();
         read_result
     }
 
     fn write(&mut self, address: u16, data: u8) {
+        self.tick();
         self.bus.borrow_mut().cpu_write(address, data);
+        self.poll_interrupts();
         if address == 0x4014 {
-            self.cpu_cycle+= if self.cpu_cycle&1==1{514}else{513};
+            self.oam_dma(data);
         }
     }
 
-    fn read_interrupt_status(&mut self) {
-        let interrupt_status = self.bus.borrow_mut().interrupt_status;
-        self.interrupt.irq = interrupt_status & 1 == 1;
-        self.interrupt.nmi.0 = interrupt_status & 2 == 2;
-        self.interrupt.reset = interrupt_status & 4 == 4;
-    }
-    
-    fn write_interrupt_status(&mut self) {
-        let mut interrupt_status = 0;
-        interrupt_status|=if self.interrupt.irq {1} else {0};
-        interrupt_status|=if self.interrupt.nmi.0 {2} else {0};
-        interrupt_status|=if self.interrupt.reset {4} else {0};
-        self.bus.borrow_mut().interrupt_status = interrupt_status;
-    }
-
-
-    fn read_u16(&self, address: u16) -> u16 {
-        let lo = self.read(address) as u16;
-        let hi = self.read(address + 1) as u16;
-        (hi << 8) | lo
+    // OAM DMA：CPU 在下一次读取时暂停1个周期 (奇数周期再多等1个)，然后把整页256字节逐个写入 $2004
+    // 暂停的周期重复读取下一条指令的地址
+    // https://www.nesdev.org/wiki/PPU_registers#OAMDMA
+    fn oam_dma(&mut self, page: u8) {
+        self.read(self.registers.pc);
+        if self.cpu_cycle & 1 == 1 {
+            self.read(self.registers.pc);
+        }
+        let start_address = (page as u16) << 8;
+        for i in 0..0x100 {
+            let data = self.read(start_address + i);
+            self.write(0x2004, data);
+        }
     }
 
-    fn read_u16_z(&self, address:u8 ) -> u16 {
+    fn read_u16_z(&mut self, address:u8 ) -> u16 {
         let low_byte = self.read(address as u16) as u16;
         let high_byte = self.read(address.wrapping_add(1) as u16) as u16;
         (high_byte << 8) | low_byte
@@ -172,27 +259,24 @@ impl Cpu {
 
     /// 执行一条指令
     pub fn step(&mut self) {
-        
+        // 停机后地址线停在 $FFFF，时钟照常走，只响应复位
+        if self.jammed.is_some() {
+            self.read(0xFFFF);
+            self.handle_interrupt();
+            return;
+        }
         // self.write_interrupt_status();
         // 解码操作码为指令和寻址模式
         let opcode = self.read(self.registers.pc);
-        self.instruction_info = decode_opcode(opcode);
+        self.instruction_info = OPCODE_TABLE[opcode as usize];
         // if (self.cpu_cycle==236203)
         //  {
         //     println!("before:{},CYC:{} ,$2002:{:02X}", self.get_current_log(),self.cpu_cycle,self.read_debug(0x2002));
         // }
 
-        if ((self.instruction_info.instruction == Instruction::STA) && (self.instruction_info.addressing_mode == AddressingMode::Absolute) 
-        && (self.read_debug(self.registers.pc+1)==0x00)&&(self.read_debug(self.registers.pc+2)==0x20)||
-        (self.instruction_info.instruction == Instruction::LDA) && (self.instruction_info.addressing_mode == AddressingMode::Immediate)
-        && (self.read_debug(self.registers.pc+1)==0x80))
-        {
-            println!("{},CYC:{},$2000:{:02X} ,$2002:{:02X}", self.get_current_log(),self.cpu_cycle,self.read_debug(0x2000),self.read_debug(0x2002));
-        }
-
-        let current_cyc = self.cpu_cycle;
+        // 取完操作码后 PC 指向操作数，指令按周期依次读取
+        self.registers.pc = self.registers.pc.wrapping_add(1);
         self.execute();  
-        self.cpu_cycle_wait = self.cpu_cycle-current_cyc; 
         // if (self.cpu_cycle==236203)
         //  {
         //     println!("after:{},CYC:{} ,$2002:{:02X}", self.get_current_log(),self.cpu_cycle,self.read_debug(0x2002));
@@ -200,26 +284,21 @@ impl Cpu {
         self.handle_interrupt()
     }
 
+    // 指令结束时检查倒数第二个周期的采样结果
+    // 所以 CLI/SEI/PLP 改变 I 标志要到下一条指令之后才生效，而 RTI 在指令中途恢复 P，立即生效
     fn handle_interrupt(&mut self) {
-        self.read_interrupt_status();
         // 如果有复位信号，执行复位
-        if self.interrupt.reset {
+        if self.bus.borrow().interrupt_status & 4 == 4 {
+            self.bus.borrow_mut().interrupt_status &= !4;
             self.reset();
-            // println!("reset");
-            self.interrupt.reset = false;
+            return;
         }
-        // 执行nmi，边缘触发
-        if (self.interrupt.nmi.0==true) && (self.interrupt.nmi.1==false)  {
-            self.nmi();
-            println!("nmi!,cycle:{}",self.cpu_cycle);
+        if self.jammed.is_some() {
+            return;
         }
-        // 如果有irq信号，执行irq
-        if self.interrupt.irq && !self.registers.get_flag(StatusFlags::InterruptDisable) {
-            self.irq();
-            // println!("irq");
-            self.interrupt.irq = false;
+        if self.interrupt.prev_need_nmi || self.interrupt.prev_run_irq {
+            self.interrupt_sequence();
         }
-        self.interrupt.nmi.1 = self.interrupt.nmi.0;
     }
 
         // 反汇编当前结果
@@ -251,7 +330,7 @@ impl Cpu {
         use crate::cpu::instructions::Instruction::*;
         let address = self.registers.pc;
         let opcode = self.read_debug(address);
-        let instruction_info = decode_opcode(opcode);
+        let instruction_info = OPCODE_TABLE[opcode as usize];
 
         // 开始的地址
         let mut output = "".to_string(); 
@@ -389,7 +468,7 @@ impl Cpu {
         use crate::cpu::instructions::Instruction::*;
 
         let opcode = self.read_debug(address);
-        let instruction_info = decode_opcode(opcode);
+        let instruction_info = OPCODE_TABLE[opcode as usize];
 
         // 开始的地址
         let mut output = format!("{:04X}  ", address); 
@@ -527,180 +606,142 @@ impl Cpu {
     }
 
 
-    pub fn get_operand_address(&self)-> (u16,bool) {
-        fn check_page_boundary_crossed(addr1: u16, addr2: u16) -> bool {
-            (addr1 & 0xFF00) != (addr2 & 0xFF00)
-        }
-        let address = self.registers.pc+1;
-        let mut operand_address = 0;
-        let mut page_crossed = false;
+    /// 取指令流中的下一个字节，PC 加1
+    fn fetch(&mut self) -> u8 {
+        let value = self.read(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch_u16(&mut self) -> u16 {
+        let low_byte = self.fetch() as u16;
+        let high_byte = self.fetch() as u16;
+        (high_byte << 8) | low_byte
+    }
+
+    // 没有操作数的指令在第2个周期仍然会读一次下一个字节，PC 不变
+    fn implied(&mut self) {
+        self.read(self.registers.pc);
+    }
+
+    /// 按寻址模式逐周期取出操作数地址，包括途中的伪读
+    /// 立即数寻址返回 PC 本身，真正的读取由调用者完成
+    fn operand_address(&mut self) -> u16 {
         match self.instruction_info.addressing_mode {
-            AddressingMode::Implied|AddressingMode::Accumulator => (),
             AddressingMode::Immediate => {
-                operand_address = address;
-            }
-            AddressingMode::Absolute => {
-                operand_address = self.read_u16(address);
-            }
-            AddressingMode::AbsoluteX => {
-                let base_address = self.read_u16(address);
-                operand_address = base_address + self.registers.x as u16;
-                // 页面交叉判断
-                if check_page_boundary_crossed(base_address ,operand_address){
-                    page_crossed = true;
-                }
-            }
-            AddressingMode::AbsoluteY => {
-                let base_address = self.read_u16(address);
-                operand_address = base_address.wrapping_add(self.registers.y as u16);
-                // 页面交叉判断
-                if check_page_boundary_crossed(base_address ,operand_address){
-                    page_crossed = true;
-                }
-            }
-            AddressingMode::ZeroPage => {
-                operand_address = self.read(address) as u16;
+                let address = self.registers.pc;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
+                address
             }
+            AddressingMode::ZeroPage => self.fetch() as u16,
             AddressingMode::ZeroPageX => {
-                let base_address = self.read(address) as u16;
-                operand_address = (base_address + self.registers.x as u16)& 0x00FF;
+                // 先读一次未加变址的零页地址
+                let base_address = self.fetch();
+                self.read(base_address as u16);
+                base_address.wrapping_add(self.registers.x) as u16
             }
             AddressingMode::ZeroPageY => {
-                let base_address = self.read(address) as u16;
-                operand_address = (base_address + self.registers.y as u16)& 0x00FF;
+                let base_address = self.fetch();
+                self.read(base_address as u16);
+                base_address.wrapping_add(self.registers.y) as u16
+            }
+            AddressingMode::Absolute => self.fetch_u16(),
+            AddressingMode::AbsoluteX => {
+                let base_address = self.fetch_u16();
+                self.indexed(base_address, self.registers.x)
             }
-            AddressingMode::Indirect => {
-                let operand_address_address = self.read_u16(address);
-                let low_byte = self.read(operand_address_address);
-                let high_byte = if operand_address_address & 0x00FF == 0x00FF {
-                    self.read(operand_address_address & 0xFF00)
-                } else {
-                    self.read(operand_address_address + 1)
-                };
-                operand_address = (high_byte as u16) << 8 | low_byte as u16;
+            AddressingMode::AbsoluteY => {
+                let base_address = self.fetch_u16();
+                self.indexed(base_address, self.registers.y)
             }
             AddressingMode::IndirectX => {
-                let base_address = self.read(address);
-                operand_address = self.read_u16_z(base_address.wrapping_add(self.registers.x));
+                let base_address = self.fetch();
+                self.read(base_address as u16);
+                let pointer = base_address.wrapping_add(self.registers.x);
+                self.read_u16_z(pointer)
             }
             AddressingMode::IndirectY => {
-                let base_address_address = self.read(address);
-                let base_address = self.read_u16_z(base_address_address);
-                operand_address = base_address.wrapping_add(self.registers.y as u16) ;
-                // 页面交叉判断
-                if check_page_boundary_crossed(base_address ,operand_address){
-                    page_crossed = true;
-                }
-            }
-            AddressingMode::Relative => {
-                let init_offset = self.read(address);
-                let offset = init_offset as i8; // 读取当前地址的值作为偏移量（有符号数）
-                operand_address = (address as i32 + 1 + (offset as i32)) as u16;
-                // 页面交叉判断
-                if check_page_boundary_crossed(address+1 ,operand_address){
-                    page_crossed = true;
-                }
+                let pointer = self.fetch();
+                let base_address = self.read_u16_z(pointer);
+                self.indexed(base_address, self.registers.y)
             }
-        };
-        (operand_address,page_crossed)
+            _ => panic!("当前指令:{:?} 不存在寻址模式{:?}", self.instruction_info.instruction, self.instruction_info.addressing_mode),
+        }
     }
 
-    /// 执行指令
-    fn execute(&mut self) {
-        // 根据寻址模式获取操作数所在的地址
-        match self.instruction_info.instruction {
-            Instruction::JMP => self.jmp(),
-            Instruction::LDX => self.ldx(),
-            Instruction::STX => self.stx(),
-            Instruction::JSR => self.jsr(),
-            Instruction::NOP => self.nop(),
-            Instruction::SEC => self.sec(),
-            Instruction::BCS => self.bcs(),
-            Instruction::CLC => self.clc(),
-            Instruction::BCC => self.bcc(),
-            Instruction::LDA => self.lda(),
-            Instruction::BEQ => self.beq(),
-            Instruction::BNE => self.bne(),
-            Instruction::STA => self.sta(),
-            Instruction::BIT => self.bit(),
-            Instruction::BVS => self.bvs(),
-            Instruction::BVC => self.bvc(),
-            Instruction::BPL => self.bpl(),
-            Instruction::RTS => self.rts(),
-            Instruction::SEI => self.sei(),
-            Instruction::SED => self.sed(),
-            Instruction::PHP => self.php(),
-            Instruction::PLA => self.pla(),
-            Instruction::AND => self.and(),
-            Instruction::CMP => self.cmp(),
-            Instruction::CLD => self.cld(),
-            Instruction::PHA => self.pha(),
-            Instruction::PLP => self.plp(),
-            Instruction::BMI => self.bmi(),
-            Instruction::ORA => self.ora(),
-            Instruction::CLV => self.clv(),
-            Instruction::EOR => self.eor(),
-            Instruction::ADC => self.adc(),
-            Instruction::LDY => self.ldy(),
-            Instruction::CPY => self.cpy(),
-            Instruction::CPX => self.cpx(),
-            Instruction::SBC => self.sbc(),
-            Instruction::INY => self.iny(),
-            Instruction::INX => self.inx(),
-            Instruction::DEY => self.dey(),
-            Instruction::DEX => self.dex(),
-            Instruction::TAY => self.tay(),
-            Instruction::TAX => self.tax(),
-            Instruction::TYA => self.tya(),
-            Instruction::TXA => self.txa(),
-            Instruction::TSX => self.tsx(),
-            Instruction::TXS => self.txs(),
-            Instruction::RTI => self.rti(),
-            Instruction::LSR => self.lsr(),
-            Instruction::ASL => self.asl(),
-            Instruction::ROR => self.ror(),
-            Instruction::ROL => self.rol(),
-            Instruction::STY => self.sty(),
-            Instruction::INC => self.inc(),
-            Instruction::DEC => self.dec(),
-            Instruction::DOP => self.nop(),
-            Instruction::TOP => self.nop(),
-            Instruction::LAX => self.lax(),
-            Instruction::SAX => self.sax(),
-            Instruction::DCP => self.dcp(),
-            Instruction::ISC => self.isc(),
-            Instruction::SLO => self.slo(),
-            Instruction::RLA => self.rla(),
-            Instruction::SRE => self.sre(),
-            Instruction::RRA => self.rra(),
-            Instruction::BRK => self.brk(),
-            Instruction::CLI => self.cli(),
-            Instruction::ANC => self.anc(),
-            Instruction::ALR => self.alr(),
-            Instruction::ARR => self.arr(),
-            Instruction::XAA => self.xaa(),
-            Instruction::AHX => self.ahx(),
-            Instruction::TAS => self.tas(),
-            Instruction::SHY => self.shy(),
-            Instruction::SHX => self.shx(),
-            Instruction::LAS => self.las(),
-            Instruction::AXS => self.axs(),
-            // ... 处理其他指令
-            _ => panic!("{:?}指令暂未实现",self.instruction_info.instruction), // 如果尚未实现的指令，触发未实现错误
+    // 变址时 CPU 先用未进位的高字节读一次
+    // 读指令没有跨页时这次读取就是真正的读取，所以只在跨页时补一次伪读；写和读-改-写总是伪读
+    // 是哪一种由操作码表中的 instruction_type 决定
+    fn indexed(&mut self, base_address: u16, index: u8) -> u16 {
+        let address = base_address.wrapping_add(index as u16);
+        let page_crossed = (base_address & 0xFF00) != (address & 0xFF00);
+        if page_crossed || self.instruction_info.instruction_type != InstructionType::CrossingPage {
+            self.read((base_address & 0xFF00) | (address & 0x00FF));
         }
+        address
+    }
+
+    fn read_operand(&mut self) -> u8 {
+        let address = self.operand_address();
+        self.read(address)
+    }
+
+    fn write_operand(&mut self, value: u8) {
+        let address = self.operand_address();
+        self.write(address, value);
+    }
+
+    // 读-改-写：读出原值后先把原值写回一次，再写入新值
+    // MMC1 靠这次连续写入忽略 INC 对寄存器的第二次写
+    fn modify_operand(&mut self, operation: impl FnOnce(&mut Self, u8) -> u8) -> u8 {
+        if self.instruction_info.addressing_mode == AddressingMode::Accumulator {
+            self.implied();
+            let result = operation(self, self.registers.a);
+            self.registers.a = result;
+            return result;
+        }
+        let address = self.operand_address();
+        let value = self.read(address);
+        self.write(address, value);
+        let result = operation(self, value);
+        self.write(address, result);
+        result
+    }
+
+    // 条件成立时多一个周期，跳转跨页时再用未进位的地址读一次
+    fn branch(&mut self, condition: bool) {
+        let offset = self.fetch() as i8;
+        if !condition {
+            return;
+        }
+        // 不跨页的跳转在最后一个周期不检测 IRQ，刚出现的 IRQ 要等下一条指令执行完
+        if self.interrupt.run_irq && !self.interrupt.prev_run_irq {
+            self.interrupt.run_irq = false;
+        }
+        self.read(self.registers.pc);
+        let target = self.registers.pc.wrapping_add(offset as u16);
+        if (target & 0xFF00) != (self.registers.pc & 0xFF00) {
+            self.read((self.registers.pc & 0xFF00) | (target & 0x00FF));
+        }
+        self.registers.pc = target;
+    }
+
+    /// 执行指令：按操作码查分派表，寻址由各指令通过 operand_address 按操作码表完成
+    fn execute(&mut self) {
+        OPERATIONS[self.instruction_info.operand_code as usize](self);
     }
 
     fn stack_push(&mut self, value: u8) {
 
         // 堆栈基地址是 0x0100
         const STACK_BASE: u16 = 0x0100;
-    
+
         // 计算堆栈当前位置的地址，将堆栈指针（SP）加上基地址
-        let stack_address = 0x0100 + self.registers.sp as u16;
-    
+        let stack_address = STACK_BASE + self.registers.sp as u16;
+
         // 将值写入当前堆栈位置
         self.write(stack_address, value);
-    
+
         // 更新堆栈指针（SP），将其减 1，指向下一个可用位置
         self.registers.sp = self.registers.sp.wrapping_sub(1);
     }
@@ -708,11 +749,11 @@ impl Cpu {
     fn stack_push_16(&mut self, value: u16) {
         // 将 16 位值的高 8 位压入堆栈
         self.stack_push(((value >> 8) & 0xFF) as u8);
-    
+
         // 将 16 位值的低 8 位压入堆栈
         self.stack_push((value & 0xFF) as u8);
     }
-    
+
     fn stack_pop(&mut self) -> u8 {
 
         // 堆栈基地址是 0x0100
@@ -720,37 +761,43 @@ impl Cpu {
 
         // 堆栈指针（SP）加 1
         self.registers.sp = self.registers.sp.wrapping_add(1);
-    
+
         // 堆栈的基地址为 0x0100
         let stack_address = STACK_BASE + self.registers.sp as u16;
-    
+
         // 从内存中读取位于 stack_address 的值
         let value = self.read(stack_address);
-    
+
         // 返回弹出的值
         value
     }
 
-    fn nmi(&mut self) {
-        // 保存当前 PC 寄存器的值
-        self.stack_push_16(self.registers.pc);
-    
-        // 保存当前状态寄存器的值
-        self.stack_push(self.registers.p);
-    
-        // 将 PC 寄存器设置为 NMI 中断处理程序的地址
-        self.registers.pc = self.read_u16(0xFFFA);
+    // 出栈前 CPU 先读一次当前栈顶，之后才加 SP
+    fn stack_dummy_read(&mut self) {
+        self.read(0x0100 + self.registers.sp as u16);
+    }
+
+    // 压入 P 之前检测到 NMI 时改用 NMI 的向量，IRQ 和 BRK 因此会被 NMI 劫持
+    fn interrupt_vector(&mut self) -> u16 {
+        if self.interrupt.need_nmi {
+            self.interrupt.need_nmi = false;
+            0xFFFA
+        } else {
+            0xFFFE
+        }
     }
 
-    fn irq(&mut self) {
-        // 保存当前 PC 寄存器的值
+    // NMI/IRQ 的7个周期：两次伪读、压入 PC 和 P (B 标志为0)、读取向量
+    fn interrupt_sequence(&mut self) {
+        self.read(self.registers.pc);
+        self.read(self.registers.pc);
         self.stack_push_16(self.registers.pc);
-    
-        // 保存当前状态寄存器的值
-        self.stack_push(self.registers.p);
-    
-        // 将 PC 寄存器设置为 IRQ 中断处理程序的地址
-        self.registers.pc = self.read_u16(0xFFFE);
+        let vector = self.interrupt_vector();
+        self.stack_push((self.registers.p & !(StatusFlags::BreakCommand as u8)) | StatusFlags::Unused as u8);
+        self.registers.set_flag(StatusFlags::InterruptDisable, true);
+        let low_byte = self.read(vector) as u16;
+        let high_byte = self.read(vector + 1) as u16;
+        self.registers.pc = (high_byte << 8) | low_byte;
     }
 
     fn check_zsflag(&mut self, register: u8) {
@@ -760,7 +807,7 @@ impl Cpu {
         } else {
             self.registers.set_flag(StatusFlags::Zero, false);
         }
-    
+
         // 检查负数标志（Sign 或 Negative flag）
         if register & 0x80 != 0 {
             self.registers.set_flag(StatusFlags::Negative, true);
@@ -770,106 +817,74 @@ impl Cpu {
     }
 
     fn cld(&mut self) {
+        self.implied();
         // 清除十进制模式标志（Decimal flag）
         self.registers.set_flag(StatusFlags::DecimalMode, false);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
     }
 
     fn cmp(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
         // 从内存中读取操作数
-        let operand = self.read(operand_address);
-    
+        let operand = self.read_operand();
+
         // 将操作数与寄存器 A 进行比较
         let result = self.registers.a.wrapping_sub(operand);
-    
+
         // 检查零标志（Zero flag）和负数标志（Sign 或 Negative flag）
         self.check_zsflag(result);
-    
+
         // 检查进位标志（Carry flag）
         if self.registers.a >= operand {
             self.registers.set_flag(StatusFlags::Carry, true);
         } else {
             self.registers.set_flag(StatusFlags::Carry, false);
         }
-    
-        // 增加所需的 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed{
-            self.cpu_cycle += 1;
-        }
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn and(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
         // 从内存中读取操作数
-        let operand = self.read(operand_address);
-    
+        let operand = self.read_operand();
+
         // 将操作数与寄存器 A 进行 AND 运算
         self.registers.a &= operand;
-    
+
         // 检查零标志（Zero flag）和负数标志（Sign 或 Negative flag）
         self.check_zsflag(self.registers.a);
-        
-        // 增加所需的 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        // 如果跨页了，增加一个周期
-        if page_crossed {
-            self.cpu_cycle += 1;
-        }
-    
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
 
     fn pla(&mut self) {
+        self.implied();
+        self.stack_dummy_read();
         // 从堆栈中弹出值
         let value = self.stack_pop();
-    
+
         // 将值写入寄存器 A
         self.registers.a = value;
-    
+
         self.check_zsflag(self.registers.a);
-    
-        // 增加所需的 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-    
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn php(&mut self) {
+        self.implied();
         // 将状态寄存器的值压入堆栈
         self.stack_push(self.registers.p|StatusFlags::BreakCommand as u8|StatusFlags::Unused as u8);
-    
-        // 增加所需的 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-    
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn sed(&mut self) {
+        self.implied();
         // 设置状态寄存器的 D 标志位
         self.registers.set_flag(StatusFlags::DecimalMode, true);
-
-        // 增加所需的 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-    
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn sei(&mut self) {
+        self.implied();
         // 设置状态寄存器的 I 标志位
         self.registers.set_flag(StatusFlags::InterruptDisable, true);
-
-        // 增加所需的 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-    
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn rts(&mut self) {
+        self.implied();
+        self.stack_dummy_read();
         // 从堆栈中弹出返回地址的低字节（低 8 位）
         let low_byte = self.stack_pop() as u16;
 
@@ -880,995 +895,672 @@ impl Cpu {
         let return_address = (high_byte << 8) | low_byte;
 
         // 将程序计数器（PC）设置为返回地址 + 1，以返回调用 JSR 指令之前的指令
+        // 加1之前 CPU 还会读一次返回地址
+        self.read(return_address);
         self.registers.pc = return_address.wrapping_add(1);
-
-        // 增加所需的 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
     }
 
     fn bpl(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if !self.registers.get_flag(StatusFlags::Negative) {
-            self.cpu_cycle += 1;
-            self.registers.pc = operand_address;
-            if page_crossed {
-                self.cpu_cycle += 1;
-            }
-        } else {
-            self.registers.pc += self.instruction_info.operand_size as u16;
-        }
+        self.branch(!self.registers.get_flag(StatusFlags::Negative));
     }
 
     fn bvc(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if !self.registers.get_flag(StatusFlags::Overflow) {
-            self.cpu_cycle += 1;
-            self.registers.pc = operand_address;
-            if page_crossed {
-                self.cpu_cycle += 1;
-            }
-        } else {
-            self.registers.pc += self.instruction_info.operand_size as u16;
-        }
+        self.branch(!self.registers.get_flag(StatusFlags::Overflow));
     }
 
     fn bvs(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        if self.registers.get_flag(StatusFlags::Overflow) {
-            self.cpu_cycle += 1;
-            self.registers.pc = operand_address;
-            if page_crossed {
-                self.cpu_cycle += 1;
-            }
-        } else {
-            self.registers.pc += self.instruction_info.operand_size as u16;
-        }
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.branch(self.registers.get_flag(StatusFlags::Overflow));
     }
 
     fn bit(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         let value = self.registers.a & operand;
         self.registers.set_flag(StatusFlags::Zero, value==0);
         self.registers.set_flag(StatusFlags::Overflow, operand & 0x40 != 0);
         self.registers.set_flag(StatusFlags::Negative, operand & 0x80 != 0);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
     }
 
     fn sta(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let value = self.registers.a;
-        self.write(operand_address, value);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.write_operand(self.registers.a);
     }
-    
+
     fn bne(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        if !self.registers.get_flag(StatusFlags::Zero) {
-            self.registers.pc = operand_address;
-            self.cpu_cycle += 1;
-            if page_crossed {
-                self.cpu_cycle += 1;
-            }
-        } else {
-            self.registers.pc += self.instruction_info.operand_size as u16;
-        }
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.branch(!self.registers.get_flag(StatusFlags::Zero));
     }
 
     // BEQ 指令实现
     fn beq(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        if self.registers.get_flag(StatusFlags::Zero) {
-            self.registers.pc = operand_address;
-            self.cpu_cycle += 1;
-            if page_crossed {
-                self.cpu_cycle += 1;
-            }
-        } else {
-            self.registers.pc += self.instruction_info.operand_size as u16;
-        }
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.branch(self.registers.get_flag(StatusFlags::Zero));
     }
 
     // LDA 指令实现
     fn lda(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.check_zsflag(operand);
         self.registers.a = operand;
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed {
-            self.cpu_cycle += 1;
-        }
     }
 
     // BCC 指令实现
     fn bcc(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
         // 如果进位标志（Carry）为 0，则跳转到指定地址
-            if !self.registers.get_flag(StatusFlags::Carry) {
-                self.registers.pc = operand_address;
-                self.cpu_cycle += 1;
-                //在页面边界交叉时 +1s
-                if page_crossed {
-                    self.cpu_cycle += 1;
-                }
-            } else {
-                self.registers.pc += self.instruction_info.operand_size as u16;
-            }
-            self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        }
+        self.branch(!self.registers.get_flag(StatusFlags::Carry));
+    }
 
     // CLC 指令实现
     fn clc(&mut self) {
+        self.implied();
         self.registers.set_flag(StatusFlags::Carry, false);
-        self.registers.pc += 1;
-        self.cpu_cycle += 2;
     }
 
     // BCS 指令实现
     fn bcs(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
         // 如果进位标志（Carry）为 1，则跳转到指定地址
-            if self.registers.get_flag(StatusFlags::Carry) {
-                self.registers.pc = operand_address;
-                self.cpu_cycle += 1;
-                //在页面边界交叉时 +1s
-                if page_crossed {
-                    self.cpu_cycle += 1;
-                }
-            } else {
-                self.registers.pc += self.instruction_info.operand_size as u16;
-            }
-            self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        }
-        
+        self.branch(self.registers.get_flag(StatusFlags::Carry));
+    }
+
     /// SEC 指令实现
     fn sec(&mut self) {
+        self.implied();
         self.registers.set_flag(StatusFlags::Carry, true);
-        self.registers.pc += 1;
-        self.cpu_cycle += 2;
     }
 
     /// NOP
+    /// 带操作数的非官方 NOP 会照常读取操作数
     fn nop(&mut self) {
-        let (_,page_crossed) = self.get_operand_address();
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed {
-            self.cpu_cycle += 1;
+        if self.instruction_info.addressing_mode == AddressingMode::Implied {
+            self.implied();
+        } else {
+            self.read_operand();
         }
     }
 
-
     /// JSR 指令实现
     fn jsr(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-
-        // JSR 操作的目标地址是绝对地址
-        let target_address = operand_address;
-
-        // 获取当前程序计数器（PC）值，并将其减 1，以指向 JSR 指令后的下一条指令
-        let return_address = self.registers.pc.wrapping_add(2);
-
-        // 将 return_address 的高字节（高 8 位）和低字节（低 8 位）分开
-        let high_byte = ((return_address >> 8) & 0xFF) as u8;
-        let low_byte = (return_address & 0xFF) as u8;
-
-        // 将 return_address 的高字节和低字节推入堆栈
-        self.stack_push(high_byte);
-        self.stack_push(low_byte);
-
+        // 先取目标地址的低字节，此时 PC 指向高字节
+        let low_byte = self.fetch() as u16;
+        self.stack_dummy_read();
+        // 压入的返回地址是 JSR 指令的最后一个字节，RTS 时再加1
+        self.stack_push_16(self.registers.pc);
+        let high_byte = self.read(self.registers.pc) as u16;
         // 将程序计数器（PC）设置为目标地址，以开始执行子程序
-        self.registers.pc = target_address;
-
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-
+        self.registers.pc = (high_byte << 8) | low_byte;
     }
 
     /// STX 指令实现
     fn stx(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        self.write(operand_address, self.registers.x);
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
+        self.write_operand(self.registers.x);
     }
 
     /// LDX 指令实现
     fn ldx(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        
-        self.cpu_cycle += self.instruction_info.instruction_cycle as u64;
-        self.registers.pc+=self.instruction_info.operand_size as u16;
-
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.registers.x = operand;
         self.check_zsflag(operand);
-        if page_crossed{
-            self.cpu_cycle += 1;
-        }
     }
 
-
     /// JMP 指令实现
     fn jmp(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        self.registers.pc = operand_address;
-        self.cpu_cycle += self.instruction_info.instruction_cycle as u64;
+        let address = self.fetch_u16();
+        if self.instruction_info.addressing_mode == AddressingMode::Indirect {
+            // 间接跳转不会跨页：($xxFF) 的高字节从 $xx00 读取
+            let low_byte = self.read(address) as u16;
+            let high_byte = self.read((address & 0xFF00) | (address.wrapping_add(1) & 0x00FF)) as u16;
+            self.registers.pc = (high_byte << 8) | low_byte;
+        } else {
+            self.registers.pc = address;
+        }
     }
 
     fn pha (&mut self) {
+        self.implied();
         self.stack_push(self.registers.a);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
     }
 
     fn plp (&mut self) {
+        self.implied();
+        self.stack_dummy_read();
         self.registers.p = self.stack_pop();
         self.registers.set_flag(StatusFlags::BreakCommand, false);
         self.registers.set_flag(StatusFlags::Unused, true);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
     }
 
     fn bmi(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        if self.registers.get_flag(StatusFlags::Negative) {
-            self.registers.pc = operand_address;
-            self.cpu_cycle += 1;   
-            if page_crossed {
-                self.cpu_cycle += 1;
-            }
-        } else {
-            self.registers.pc += self.instruction_info.operand_size as u16;
-        }
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.branch(self.registers.get_flag(StatusFlags::Negative));
     }
 
     fn ora (&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.registers.a |= operand;
-        self.registers.pc += self.instruction_info.operand_size as u16;
         self.check_zsflag(self.registers.a);
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed{
-            self.cpu_cycle+=1;
-        }
     }
 
     fn clv(&mut self) {
+        self.implied();
         self.registers.set_flag(StatusFlags::Overflow, false);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
     }
 
     fn eor(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.registers.a ^= operand;
-        self.registers.pc += self.instruction_info.operand_size as u16;
         self.check_zsflag(self.registers.a);
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed{
-            self.cpu_cycle+=1;
-        }
     }
 
-    fn adc(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        // 根据寻址模式获取操作数值
-        let operand = self.read(operand_address);
-    
+    // 加法，ADC 和 RRA 共用
+    fn add_with_carry(&mut self, operand: u8) {
         // 获取累加器的当前值
         let a = self.registers.a;
-    
         // 获取当前进位标志（Carry）的值
         let carry = self.registers.get_flag(StatusFlags::Carry) as u8;
-    
         // 计算 ADC 操作的结果
         let result = a.wrapping_add(operand).wrapping_add(carry);
-    
         // 更新标志寄存器
         self.registers.set_flag(StatusFlags::Carry, (a as u16 + operand as u16 + carry as u16) > 0xFF);
         self.registers.set_flag(
             StatusFlags::Overflow,
             (((a ^ result) & (operand ^ result)) & 0x80) != 0
         );
-        self.check_zsflag(result as u8);
-    
+        self.check_zsflag(result);
         // 将计算结果存入累加器
-        self.registers.a = result as u8;
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed{
-            self.cpu_cycle+=1;
-        }
-        self.registers.pc += self.instruction_info.operand_size as u16;
+        self.registers.a = result;
     }
-    
+
+    // 减法，SBC 和 ISC 共用
+    fn subtract_with_borrow(&mut self, operand: u8) {
+        let acc = self.registers.a;
+        // 获取借位标志
+        let borrow = if self.registers.get_flag(StatusFlags::Carry) { 0 } else { 1 };
+        // 执行减法操作
+        let result = acc.wrapping_sub(operand).wrapping_sub(borrow);
+        // 更新状态寄存器
+        self.registers.set_flag(StatusFlags::Carry, (acc as i16 - operand as i16 - borrow as i16) >= 0);
+        self.check_zsflag(result);
+        self.registers.set_flag(StatusFlags::Overflow, (((acc ^ operand) & (acc ^ result)) & 0x80) != 0);
+        // 更新累加器寄存器
+        self.registers.a = result;
+    }
+
+    fn adc(&mut self) {
+        // 根据寻址模式获取操作数值
+        let operand = self.read_operand();
+        self.add_with_carry(operand);
+    }
+
     fn ldy(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
         // 根据寻址模式获取操作数值
-        let operand = self.read(operand_address);
-    
+        let operand = self.read_operand();
         // 将操作数存入 Y 寄存器
         self.registers.y = operand;
-
         // 更新标志寄存器
         self.check_zsflag(self.registers.y);
-    
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed{
-            self.cpu_cycle+=1;
-        }
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn cpy(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
         // 根据寻址模式获取操作数值
-        let operand = self.read(operand_address);
-    
+        let operand = self.read_operand();
         // 获取 Y 寄存器的当前值
         let y = self.registers.y;
-    
         // 计算 CPY 操作的结果
         let result = y.wrapping_sub(operand);
-    
         // 更新标志寄存器
         self.registers.set_flag(StatusFlags::Carry, y >= operand);
         self.check_zsflag(result);
-    
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn cpx(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
         // 根据寻址模式获取操作数值
-        let operand = self.read(operand_address);
-    
+        let operand = self.read_operand();
         // 获取 X 寄存器的当前值
         let x = self.registers.x;
-    
         // 计算 CPX 操作的结果
         let result = x.wrapping_sub(operand);
-    
         // 更新标志寄存器
         self.registers.set_flag(StatusFlags::Carry, x >= operand);
         self.check_zsflag(result);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn sbc(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let acc = self.registers.a;
-        let operand = self.read(operand_address);
-
-        // 获取借位标志
-        let borrow = if self.registers.get_flag(StatusFlags::Carry) { 0 } else { 1 };
-
-        // 执行减法操作
-        let result = acc.wrapping_sub(operand).wrapping_sub(borrow);
-
-        // 更新状态寄存器
-        self.registers.set_flag(StatusFlags::Carry, (acc as i16 - operand as i16 - borrow as i16) >= 0);
-        self.registers.set_flag(StatusFlags::Zero, result == 0);
-        self.registers.set_flag(StatusFlags::Negative, result & 0x80 != 0);
-        self.registers.set_flag(StatusFlags::Overflow, (((acc ^ operand) & (acc ^ result)) & 0x80) != 0);
-
-        // 更新累加器寄存器
-        self.registers.a = result;
-    
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed{
-            self.cpu_cycle+=1;
-        }
-        self.registers.pc += self.instruction_info.operand_size as u16;
+        let operand = self.read_operand();
+        self.subtract_with_borrow(operand);
     }
 
     fn iny(&mut self) {
+        self.implied();
         // 更新 Y 寄存器
         self.registers.y =self.registers.y.wrapping_add(1);
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.y);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn inx(&mut self) {
+        self.implied();
         // 更新 X 寄存器
         self.registers.x = self.registers.x.wrapping_add(1);
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.x);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn dey(&mut self) {
+        self.implied();
         // 更新 Y 寄存器
         self.registers.y= self.registers.y.wrapping_sub(1);
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.y);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn dex(&mut self) {
+        self.implied();
         // 更新 X 寄存器
         self.registers.x = self.registers.x.wrapping_sub(1);
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.x);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn tay (&mut self) {
+        self.implied();
         // 更新 Y 寄存器
         self.registers.y = self.registers.a;
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.y);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn tax (&mut self) {
+        self.implied();
         // 更新 X 寄存器
         self.registers.x = self.registers.a;
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.x);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn tya (&mut self) {
+        self.implied();
         // 更新 A 寄存器
         self.registers.a = self.registers.y;
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.a);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn txa (&mut self) {
+        self.implied();
         // 更新 A 寄存器
         self.registers.a = self.registers.x;
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.a);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn tsx (&mut self) {
+        self.implied();
         // 更新 X 寄存器
         self.registers.x = self.registers.sp;
-    
         // 更新标志寄存器
         self.check_zsflag(self.registers.x);
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn txs (&mut self) {
+        self.implied();
         // 更新 SP 寄存器
         self.registers.sp = self.registers.x;
-    
-        // 根据寻址模式和是否跨页来更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        self.registers.pc += self.instruction_info.operand_size as u16;
     }
 
     fn rti (&mut self) {
+        self.implied();
+        self.stack_dummy_read();
         // 从栈中弹出标志寄存器
         let status = self.stack_pop();
         // 恢复处理器状态寄存器，注意：B(4)和U(5)标志位不会被恢复
         self.registers.p = status ;
         self.registers.set_flag(StatusFlags::BreakCommand, false);
         self.registers.set_flag(StatusFlags::Unused, true);
-    
         // 从栈中弹出程序计数器的低字节和高字节
         let low_byte = self.stack_pop() as u16;
         let high_byte = self.stack_pop() as u16;
-    
         // 恢复程序计数器
         self.registers.pc = (high_byte << 8) | low_byte;
-    
-        // 更新 CPU 周期
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+    }
+
+    // 移位和循环移位，读-改-写指令共用
+    fn shift_left(&mut self, value: u8) -> u8 {
+        self.registers.set_flag(StatusFlags::Carry, value & 0x80 == 0x80);
+        let result = value << 1;
+        self.check_zsflag(result);
+        result
+    }
+
+    fn shift_right(&mut self, value: u8) -> u8 {
+        self.registers.set_flag(StatusFlags::Carry, value & 0x01 == 0x01);
+        let result = value >> 1;
+        self.check_zsflag(result);
+        result
+    }
+
+    fn rotate_left(&mut self, value: u8) -> u8 {
+        // 将操作数最高位旋转到C标志位
+        let result = (value << 1) | (self.registers.get_flag(StatusFlags::Carry) as u8);
+        self.registers.set_flag(StatusFlags::Carry, value & 0x80 != 0);
+        self.check_zsflag(result);
+        result
+    }
+
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        // 将操作数最低位旋转到C标志位
+        let result = (value >> 1) | (self.registers.get_flag(StatusFlags::Carry) as u8) << 7;
+        self.registers.set_flag(StatusFlags::Carry, value & 0x01 != 0);
+        self.check_zsflag(result);
+        result
     }
 
     fn lsr(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        match self.instruction_info.addressing_mode {
-            AddressingMode::Accumulator =>{
-                let mut value = self.registers.a;
-                self.registers.set_flag(StatusFlags::Carry, value & 0x01 == 0x01);
-                value >>= 1;
-                self.check_zsflag(value);
-                self.registers.a = value;
-            }
-            AddressingMode::ZeroPage|AddressingMode::ZeroPageX|AddressingMode::Absolute|AddressingMode::AbsoluteX =>{
-                let mut value = self.read(operand_address);
-                self.registers.set_flag(StatusFlags::Carry, value & 0x01 == 0x01);
-                value >>= 1;
-                self.check_zsflag(value);
-                self.write(operand_address, value);
-            }
-            _=>panic!("当前指令:{:?} 不存在寻址模式{:?}", Instruction::LSR, self.instruction_info.addressing_mode),
-        }
+        self.modify_operand(Self::shift_right);
     }
 
     fn asl (&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        match self.instruction_info.addressing_mode {
-            AddressingMode::Accumulator =>{
-                let mut value = self.registers.a;
-                self.registers.set_flag(StatusFlags::Carry, value & 0x80 == 0x80);
-                value <<= 1;
-                self.check_zsflag(value);
-                self.registers.a = value;
-            }
-            AddressingMode::ZeroPage|AddressingMode::ZeroPageX|AddressingMode::Absolute|AddressingMode::AbsoluteX =>{
-                let mut value = self.read(operand_address);
-                self.registers.set_flag(StatusFlags::Carry, value & 0x80 == 0x80);
-                value <<= 1;
-                self.check_zsflag(value);
-                self.write(operand_address, value);
-            }
-            _=>panic!("当前指令:{:?} 不存在寻址模式{:?}", Instruction::ASL, self.instruction_info.addressing_mode),
-        }
+        self.modify_operand(Self::shift_left);
     }
 
     fn ror(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = match self.instruction_info.addressing_mode {
-            AddressingMode::Accumulator => self.registers.a,
-            _ => self.read(operand_address),
-        };
-    
-        // 将操作数最低位旋转到C标志位
-        let carry = operand & 1;
-    
-        // 向右旋转操作数
-        let result = (operand >> 1) | (self.registers.get_flag(StatusFlags::Carry) as u8) << 7;
-    
-        match self.instruction_info.addressing_mode {
-            AddressingMode::Accumulator => self.registers.a = result,
-            _ => self.write(operand_address, result),
-        };
-    
-        // 更新 C 和 Z 标志位
-        self.registers.set_flag(StatusFlags::Carry, carry != 0);
-        self.check_zsflag(result);
-    
-        // 更新 CPU 周期
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.modify_operand(Self::rotate_right);
     }
 
     fn rol(&mut self) {
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = match self.instruction_info.addressing_mode {
-            AddressingMode::Accumulator => self.registers.a,
-            _ => self.read(operand_address),
-        };
-    
-        // 将操作数最高位旋转到C标志位
-        let carry = operand & 0x80;
-    
-        // 向左旋转操作数
-        let result = (operand << 1) | (self.registers.get_flag(StatusFlags::Carry) as u8);
-    
-        match self.instruction_info.addressing_mode {
-            AddressingMode::Accumulator => self.registers.a = result,
-            _ => self.write(operand_address, result),
-        };
-    
-        // 更新 C 和 Z 标志位
-        self.registers.set_flag(StatusFlags::Carry, carry != 0);
-        self.check_zsflag(result);
-    
-        // 更新 CPU 周期
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.modify_operand(Self::rotate_left);
     }
-    
+
     fn sty(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-        self.write(operand_address, self.registers.y);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.write_operand(self.registers.y);
     }
 
     fn inc(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
-        let result = operand.wrapping_add(1);
-        self.check_zsflag(result);
-        self.write(operand_address, result);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.modify_operand(|cpu, value| {
+            let result = value.wrapping_add(1);
+            cpu.check_zsflag(result);
+            result
+        });
     }
 
     fn dec(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
-        let result = operand.wrapping_sub(1);
-        self.check_zsflag(result);
-        self.write(operand_address, result);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.modify_operand(|cpu, value| {
+            let result = value.wrapping_sub(1);
+            cpu.check_zsflag(result);
+            result
+        });
     }
 
     fn lax(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.check_zsflag(operand);
         self.registers.a = operand;
         self.registers.x = operand;
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-        if page_crossed {
-            self.cpu_cycle += 1;
-        }
     }
 
     fn sax(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         let value = self.registers.x & self.registers.a;
-        self.write(operand_address, value);
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.write_operand(value);
     }
 
     fn dcp(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
-
-        let value = operand.wrapping_sub(1);
-        self.write(operand_address,value);
-
+        let value = self.modify_operand(|_, value| value.wrapping_sub(1));
         let result16 = (self.registers.a as u16).wrapping_sub(value as u16);
         self.registers.set_flag(StatusFlags::Carry, result16<0x100);
         self.check_zsflag(result16 as u8);
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
-
     }
 
     fn isc(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-        let operand = self.read(operand_address);
-
-
         // INC
-        let tmp = operand.wrapping_add(1);
-        self.write(operand_address, tmp);
-
-
+        let value = self.modify_operand(|_, value| value.wrapping_add(1));
         // SBC
-        let acc = self.registers.a;
-        // 获取借位标志
-        let borrow = if self.registers.get_flag(StatusFlags::Carry) { 0 } else { 1 };
-
-        // 执行减法操作
-        let result = acc.wrapping_sub(tmp).wrapping_sub(borrow);
-
-        // 更新状态寄存器
-        self.registers.set_flag(StatusFlags::Carry, (acc as i16 - tmp as i16 - borrow as i16) >= 0);
-        self.check_zsflag(result);
-        self.registers.set_flag(StatusFlags::Overflow, (((acc ^ tmp) & (acc ^ result)) & 0x80) != 0);
-
-        // 更新累加器寄存器
-        self.registers.a = result;
-
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+        self.subtract_with_borrow(value);
     }
 
     fn slo(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         // ASL
-        let mut value = self.read(operand_address);
-        self.registers.set_flag(StatusFlags::Carry, value & 0x80 == 0x80);
-        value <<= 1;
-        self.write(operand_address, value);
-
+        let value = self.modify_operand(Self::shift_left);
         self.registers.a|=value;
         self.check_zsflag(self.registers.a);
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
     }
 
     fn rla(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         // rol
-        let operand = self.read(operand_address);
-    
-        // 将操作数最高位旋转到C标志位
-        let carry = operand & 0x80;
-    
-        // 向左旋转操作数
-        let result = (operand << 1) | (self.registers.get_flag(StatusFlags::Carry) as u8);
-    
-        self.write(operand_address, result);
-    
-        // 更新 C 和 Z 标志位
-        self.registers.set_flag(StatusFlags::Carry, carry != 0);
-
-        // and 
-        self.registers.a&=result;
+        let value = self.modify_operand(Self::rotate_left);
+        // and
+        self.registers.a&=value;
         self.check_zsflag(self.registers.a);
-
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
     }
 
     fn sre(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         // LSR
-        let mut value = self.read(operand_address);
-        self.registers.set_flag(StatusFlags::Carry, value & 0x01 == 0x01);
-        value >>= 1;
-        self.write(operand_address, value);
-
+        let value = self.modify_operand(Self::shift_right);
         // EOR
         self.registers.a^=value;
         self.check_zsflag(self.registers.a);
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
     }
 
     fn rra(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         // ROR
-        let operand = self.read(operand_address);
-    
-        // 将操作数最低位旋转到C标志位
-        let carry = operand & 1;
-    
-        // 向右旋转操作数
-        let result = (operand >> 1) | (self.registers.get_flag(StatusFlags::Carry) as u8) << 7;
-    
-        self.write(operand_address, result);
-    
-        // 更新 C  标志位
-        self.registers.set_flag(StatusFlags::Carry, carry != 0);
-
+        let value = self.modify_operand(Self::rotate_right);
         // ADC
-        // 根据寻址模式获取操作数值
-        let operand = result;
-    
-        // 获取累加器的当前值
-        let a = self.registers.a;
-    
-        // 获取当前进位标志（Carry）的值
-        let carry = self.registers.get_flag(StatusFlags::Carry) as u8;
-    
-        // 计算 ADC 操作的结果
-        let result = a.wrapping_add(operand).wrapping_add(carry);
-    
-        // 更新标志寄存器
-        self.registers.set_flag(StatusFlags::Carry, (a as u16 + operand as u16 + carry as u16) > 0xFF);
-        self.registers.set_flag(
-            StatusFlags::Overflow,
-            (((a ^ result) & (operand ^ result)) & 0x80) != 0
-        );
-        self.check_zsflag(result as u8);
-    
-        // 将计算结果存入累加器
-        self.registers.a = result as u8;
-        
-        self.check_zsflag(self.registers.a);
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
-
+        self.add_with_carry(value);
     }
 
     fn brk (&mut self){
-        self.registers.pc+=2;
+        // BRK 后面有一个填充字节，返回地址跳过它
+        self.fetch();
         self.stack_push_16(self.registers.pc);
-        self.stack_push(self.registers.p);
+        let vector = self.interrupt_vector();
+        self.stack_push(self.registers.p | StatusFlags::BreakCommand as u8 | StatusFlags::Unused as u8);
         self.registers.set_flag(StatusFlags::InterruptDisable, true);
-        self.registers.set_flag(StatusFlags::BreakCommand, true);
-        self.registers.pc = self.read_u16(0xFFFE);
-        self.cpu_cycle+=7;
+        let low_byte = self.read(vector) as u16;
+        let high_byte = self.read(vector + 1) as u16;
+        self.registers.pc = (high_byte << 8) | low_byte;
+        // 中断处理程序的第一条指令执行之前不响应 NMI
+        self.interrupt.prev_need_nmi = false;
     }
 
     fn cli (&mut self){
+        self.implied();
         self.registers.set_flag(StatusFlags::InterruptDisable, false);
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
     }
-    // ... 实现其他指令
 
+    // ... 实现其他指令
     fn anc(&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         // AND
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.registers.a &= operand;
         self.check_zsflag(self.registers.a);
-
         // ANC
         self.registers.set_flag(StatusFlags::Carry, self.registers.get_flag(StatusFlags::Negative));
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
     }
 
     fn alr (&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         // AND
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.registers.a &= operand;
-        self.check_zsflag(self.registers.a);
-
         // LSR
-        self.registers.set_flag(StatusFlags::Carry, self.registers.a & 0x01 == 0x01);
-        self.registers.a >>= 1;
-        self.check_zsflag(self.registers.a);
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
+        self.registers.a = self.shift_right(self.registers.a);
     }
 
     fn arr (&mut self){
-        let (operand_address,page_crossed) = self.get_operand_address();
-
         // AND
-        let operand = self.read(operand_address);
+        let operand = self.read_operand();
         self.registers.a &= operand;
-        self.check_zsflag(self.registers.a);
-
         // ROR
-        let operand = self.registers.a;
-    
-        // 将操作数最低位旋转到C标志位
-        let carry = operand & 1;
-    
-        // 向右旋转操作数
-        let result = (operand >> 1) | (self.registers.get_flag(StatusFlags::Carry) as u8) << 7;
-    
+        let result = (self.registers.a >> 1) | (self.registers.get_flag(StatusFlags::Carry) as u8) << 7;
         self.registers.a = result;
-    
         // 更新 C  标志位
         self.registers.set_flag(StatusFlags::Carry, result & 0x40 != 0);
-
         // 更新 V 标志位
         self.registers.set_flag(StatusFlags::Overflow, ((result & 0x40) != 0) ^ ((result & 0x20) == 0));
-
         self.check_zsflag(self.registers.a);
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
-
     }
-    fn xaa (&mut self){
-        //println!("xaa指令不稳定！");
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
 
+    // A = (A | 魔数) & X & 立即数
+    // https://www.nesdev.org/wiki/Visual6502wiki/6502_Opcode_8B_(XAA,_ANE)
+    fn xaa (&mut self){
+        let operand = self.read_operand();
+        self.registers.a = (self.registers.a | self.xaa_magic) & self.registers.x & operand;
+        self.check_zsflag(self.registers.a);
     }
-    fn ahx (&mut self){
-        //println!("ahx指令不稳定！");
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
 
+    // SHA/SHX/SHY/TAS 写入 value & (基址高字节 + 1)
+    // 变址跨页时进位后的高字节也被这个值替换，最终写到 (value << 8) | 低字节
+    // https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+    fn unstable_store(&mut self, value: u8, index: u8) {
+        let base_address = match self.instruction_info.addressing_mode {
+            AddressingMode::IndirectY => {
+                let pointer = self.fetch();
+                self.read_u16_z(pointer)
+            }
+            _ => self.fetch_u16(),
+        };
+        let address = self.indexed(base_address, index);
+        let value = value & ((base_address >> 8) as u8).wrapping_add(1);
+        let address = if (base_address & 0xFF00) != (address & 0xFF00) {
+            ((value as u16) << 8) | (address & 0x00FF)
+        } else {
+            address
+        };
+        self.write(address, value);
     }
-    fn tas (&mut self){
-        //println!("tas指令不稳定！");
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
 
+    fn ahx (&mut self){
+        self.unstable_store(self.registers.a & self.registers.x, self.registers.y);
     }
-    fn shy (&mut self){
-        //println!("shy指令不稳定！");
-
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
 
+    // SP = A & X，再按 AHX 的方式写入 SP
+    fn tas (&mut self){
+        self.registers.sp = self.registers.a & self.registers.x;
+        self.unstable_store(self.registers.sp, self.registers.y);
     }
-    fn shx (&mut self){
-        //println!("shx指令不稳定！");
 
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
+    fn shy (&mut self){
+        self.unstable_store(self.registers.y, self.registers.x);
+    }
 
+    fn shx (&mut self){
+        self.unstable_store(self.registers.x, self.registers.y);
     }
+
+    // A = X = SP = 内存 & SP
     fn las (&mut self){
-        //println!("las指令不稳定！");
-        let (operand_address, page_crossed) = self.get_operand_address();
+        let operand = self.read_operand();
+        let value = operand & self.registers.sp;
+        self.registers.a = value;
+        self.registers.x = value;
+        self.registers.sp = value;
+        self.check_zsflag(value);
+    }
 
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64; 
-        if page_crossed {
-            self.cpu_cycle += 1;
-        }
+    // JAM：第2个周期读一次下一个字节后停机，PC 不再前进
+    fn stp (&mut self){
+        self.implied();
+        self.jammed = Some(self.registers.pc.wrapping_sub(1));
     }
-    fn axs (&mut self){
-         // 获取操作数地址
-        let (operand_address, page_crossed) = self.get_operand_address();
 
+    fn axs (&mut self){
         // 获取操作数
-        let operand = self.read(operand_address);
-
+        let operand = self.read_operand();
         // AND
         // 将A寄存器和X寄存器进行AND操作
         let temp = self.registers.a & self.registers.x;
-
         // 计算结果
         let result = temp.wrapping_sub(operand);
-
         // 更新标志位
         self.registers.set_flag(StatusFlags::Carry, temp >= operand);
         self.check_zsflag(result);
-
         // 将计算结果存入X寄存器
         self.registers.x = result;
+    }
+}
 
-        self.registers.pc += self.instruction_info.operand_size as u16;
-        self.cpu_cycle+=self.instruction_info.instruction_cycle as u64;
+// 操作码到执行函数的分派表，编译期生成
+static OPERATIONS: [fn(&mut Cpu); 256] = build_operation_table();
+
+const fn build_operation_table() -> [fn(&mut Cpu); 256] {
+    let mut table = [Cpu::nop as fn(&mut Cpu); 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        table[opcode] = operation(decode_opcode(opcode as u8).instruction);
+        opcode += 1;
     }
+    table
 }
 
- 
\ No newline at end of file
+const fn operation(instruction: Instruction) -> fn(&mut Cpu) {
+    match instruction {
+        Instruction::JMP => Cpu::jmp,
+        Instruction::LDX => Cpu::ldx,
+        Instruction::STX => Cpu::stx,
+        Instruction::JSR => Cpu::jsr,
+        Instruction::NOP => Cpu::nop,
+        Instruction::SEC => Cpu::sec,
+        Instruction::BCS => Cpu::bcs,
+        Instruction::CLC => Cpu::clc,
+        Instruction::BCC => Cpu::bcc,
+        Instruction::LDA => Cpu::lda,
+        Instruction::BEQ => Cpu::beq,
+        Instruction::BNE => Cpu::bne,
+        Instruction::STA => Cpu::sta,
+        Instruction::BIT => Cpu::bit,
+        Instruction::BVS => Cpu::bvs,
+        Instruction::BVC => Cpu::bvc,
+        Instruction::BPL => Cpu::bpl,
+        Instruction::RTS => Cpu::rts,
+        Instruction::SEI => Cpu::sei,
+        Instruction::SED => Cpu::sed,
+        Instruction::PHP => Cpu::php,
+        Instruction::PLA => Cpu::pla,
+        Instruction::AND => Cpu::and,
+        Instruction::CMP => Cpu::cmp,
+        Instruction::CLD => Cpu::cld,
+        Instruction::PHA => Cpu::pha,
+        Instruction::PLP => Cpu::plp,
+        Instruction::BMI => Cpu::bmi,
+        Instruction::ORA => Cpu::ora,
+        Instruction::CLV => Cpu::clv,
+        Instruction::EOR => Cpu::eor,
+        Instruction::ADC => Cpu::adc,
+        Instruction::LDY => Cpu::ldy,
+        Instruction::CPY => Cpu::cpy,
+        Instruction::CPX => Cpu::cpx,
+        Instruction::SBC => Cpu::sbc,
+        Instruction::INY => Cpu::iny,
+        Instruction::INX => Cpu::inx,
+        Instruction::DEY => Cpu::dey,
+        Instruction::DEX => Cpu::dex,
+        Instruction::TAY => Cpu::tay,
+        Instruction::TAX => Cpu::tax,
+        Instruction::TYA => Cpu::tya,
+        Instruction::TXA => Cpu::txa,
+        Instruction::TSX => Cpu::tsx,
+        Instruction::TXS => Cpu::txs,
+        Instruction::RTI => Cpu::rti,
+        Instruction::LSR => Cpu::lsr,
+        Instruction::ASL => Cpu::asl,
+        Instruction::ROR => Cpu::ror,
+        Instruction::ROL => Cpu::rol,
+        Instruction::STY => Cpu::sty,
+        Instruction::INC => Cpu::inc,
+        Instruction::DEC => Cpu::dec,
+        Instruction::DOP => Cpu::nop,
+        Instruction::TOP => Cpu::nop,
+        Instruction::LAX => Cpu::lax,
+        Instruction::SAX => Cpu::sax,
+        Instruction::DCP => Cpu::dcp,
+        Instruction::ISC => Cpu::isc,
+        Instruction::SLO => Cpu::slo,
+        Instruction::RLA => Cpu::rla,
+        Instruction::SRE => Cpu::sre,
+        Instruction::RRA => Cpu::rra,
+        Instruction::BRK => Cpu::brk,
+        Instruction::CLI => Cpu::cli,
+        Instruction::ANC => Cpu::anc,
+        Instruction::ALR => Cpu::alr,
+        Instruction::ARR => Cpu::arr,
+        Instruction::XAA => Cpu::xaa,
+        Instruction::AHX => Cpu::ahx,
+        Instruction::TAS => Cpu::tas,
+        Instruction::SHY => Cpu::shy,
+        Instruction::SHX => Cpu::shx,
+        Instruction::LAS => Cpu::las,
+        Instruction::AXS => Cpu::axs,
+        Instruction::STP => Cpu::stp,
+    }
+}
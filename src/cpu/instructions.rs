@@ -1,15 +1,16 @@
 //包含一个枚举类型，用于表示所有可能的 6502 指令，以及与指令解码和执行相关的逻辑。例如，可以实现一个从操作码到指令的映射函数（from_opcode），以及一个执行指令的方法（execute）。
 
 use crate::cpu::AddressingMode;
-use crate::cpu::opcodes::decode_opcode;
+use crate::cpu::opcodes::OPCODE_TABLE;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum InstructionType {
     Common,
     CrossingPage,
     Branch,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct InstructionInfo {
     pub operand_code: u8,
     pub instruction: Instruction,
@@ -22,7 +23,7 @@ pub struct InstructionInfo {
 
 impl std::default::Default for InstructionInfo {
     fn default() -> Self {
-        decode_opcode(0xEA) //默认是nop
+        OPCODE_TABLE[0xEA] //默认是nop
     }
 }
 
@@ -41,7 +42,7 @@ pub enum Instruction {
     BPL, // 分支指令
     CLC, // 清除进位标志
     ORA, // 或操作
-    STP, // 停止
+    STP, // *JAM，停机直到复位
     ASL, // 算术左移
     SLO, // ASL + ORA
     ROL, // 循环左移
@@ -71,16 +72,16 @@ pub enum Instruction {
     DEY, // 减少 Y 寄存器
     BCC, // 分支指令
     TYA, // 将 Y 寄存器复制到累加器
-    SHY, // 未实现
+    SHY, // *存储 Y & (高字节+1)
     STA, // 存储累加器
     STX, // 存储 X 寄存器
     TXA, // 将 X 寄存器复制到累加器
     TXS, // 将 X 寄存器复制到堆栈指针
-    SHX, // 未实现
-    SAX, // 未实现
-    XAA, // 未实现
-    AHX, // 未实现
-    TAS, // 未实现
+    SHX, // *存储 X & (高字节+1)
+    SAX, // *存储 A & X
+    XAA, // *A = (A | 魔数) & X & 立即数
+    AHX, // *存储 A & X & (高字节+1)
+    TAS, // *SP = A & X，再按 AHX 存储
     TAY, // 将累加器复制到 Y 寄存器
     BCS, // 分支指令
     CLV, // 清除溢出标志
@@ -96,7 +97,7 @@ pub enum Instruction {
     DEC, // 减少内存值
     DEX, // 减少 X 寄存器
     DCP, // DEC + CMP
-    AXS, // 未实现
+    AXS, // *X = (A & X) - 立即数
     CPX, // 比较 X 寄存器
     INX, // 增加 X 寄存器
     BEQ, // 分支指令
@@ -3,8 +3,22 @@ use crate::cpu::addressing_modes::AddressingMode;
 use crate::cpu::instructions::{InstructionType,InstructionInfo};
 
 
-// 操作码到指令的映射
-pub fn decode_opcode(opcode: u8) -> InstructionInfo {
+// 操作码表，编译期由 decode_opcode 生成，运行时直接按操作码取
+pub static OPCODE_TABLE: [InstructionInfo; 256] = build_opcode_table();
+
+const fn build_opcode_table() -> [InstructionInfo; 256] {
+    let mut table = [decode_opcode(0xEA); 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        table[opcode] = decode_opcode(opcode as u8);
+        opcode += 1;
+    }
+    table
+}
+
+// 操作码到指令的映射，覆盖全部256个操作码
+// instruction_type 同时是变址寻址的跨页规则：CrossingPage 只在跨页时多一个周期，其余写和读-改-写总是多一个周期
+pub const fn decode_opcode(opcode: u8) -> InstructionInfo {
     use AddressingMode::*;
     use Instruction::*;
     use InstructionType::*;
@@ -2351,7 +2365,7 @@ pub fn decode_opcode(opcode: u8) -> InstructionInfo {
             operand_size:3,
             instruction_cycle: 4,
             unofficial: true,
-            instruction_type: Common,
+            instruction_type: CrossingPage,
         },
 
         // AXS
@@ -2366,7 +2380,15 @@ pub fn decode_opcode(opcode: u8) -> InstructionInfo {
         },
 
 
-        // ...其他操作码到指令的映射
-        _ => panic!("当前操作码{:02X}不存在", opcode),
+        // JAM (KIL/STP)：CPU 停止取指，只有复位能恢复
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => InstructionInfo {
+            operand_code: opcode,
+            instruction: STP,
+            addressing_mode: Implied,
+            operand_size:1,
+            instruction_cycle: 2,
+            unofficial: true,
+            instruction_type: Common,
+        },
     }
 }
@@ -1,22 +1,34 @@
-use std::collections::HashSet;
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::sync::{Arc, Mutex};
 
 use crossbeam::channel::{bounded, select, Receiver, Sender};
-use egui::Key;
 
 use crate::bus::{ RWMessage, RWResult,Bus};
 use crate::cpu::{Cpu};
+use crate::mapper::RomHeader;
+use crate::mapper::gamedb::{GameDb, GameInfo};
+use crate::mapper::fds::{is_fds_image, FDS_BIOS_SIZE};
+use crate::{NesError, NesResult};
+use crate::utils::patch::apply_patch;
+use crate::utils::archive;
+use crate::input::zapper::detect_light;
+use crate::input::{DataRecorder, Expansion, InputFilter, PortConfig, PortDevice, TapeMode, KEYBOARD_ROWS};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::utils::rewind::RewindBuffer;
+use crate::utils::movie::{self, Movie, MovieFrame, MovieMode, COMMAND_POWER, COMMAND_SOFT_RESET};
 use crate::ppu::{Ppu};
 use crate::utils::{Frame, GlobalSignal, Palettes};
 
 use crate::window::MyApp;
 
+// 自动加载的外部游戏数据库文件名，nes20db.xml 为 NES 2.0 数据库，gamedb.csv 为内置数据库的格式
+const GAME_DB_FILES: [&str; 2] = ["nes20db.xml", "gamedb.csv"];
+
 pub struct Emulator {
     pub pip_cpu2bus: (Sender<RWMessage>, Receiver<RWMessage>),
     pub pip_bus2cpu: (Sender<RWResult>, Receiver<RWResult>),
@@ -28,11 +40,27 @@ pub struct Emulator {
     pub pip_log: (Sender<String>, Receiver<String>),
     pub pip_ppu_frame: (Sender<Frame>, Receiver<Frame>),
     pub palettes: Palettes,
-    pub pip_input_stream: (Sender<HashSet<egui::Key>>, Receiver<HashSet<egui::Key>>),
     // pub window: Window,
     pub cpu: Cpu,
-    pub ppu: Ppu,
+    pub ppu: Rc<RefCell<Ppu>>, // CPU 每次访问总线时推进 PPU，两者共享
     pub bus: Rc<RefCell<Bus>>,
+    pub game_db: GameDb,
+    game_db_files: Vec<PathBuf>, // 已经自动加载过的外部数据库文件
+    fds_bios: Option<Vec<u8>>, // 用户提供的 FDS BIOS
+    disk_save_path: Option<String>, // 当前 FDS 磁盘写入差异的保存位置
+    rom_path: Option<String>, // 当前 ROM 的路径
+    save_prefix: Option<String>, // 即时存档和磁盘写入的文件名前缀，压缩包中的 ROM 带上条目名
+    rom_crc32: u32, // 应用补丁后的 ROM 文件 CRC32，用于检查存档是否属于当前游戏
+    rom_data: Vec<u8>, // 应用补丁后的 ROM 文件，上电时重新插入
+    rewind: RewindBuffer,
+    rewinding: bool, // 正在连续回溯，运行下一帧时结束
+    movie: Option<Movie>,
+    pending_commands: u8, // 录制时的复位/上电，在下一帧开始时执行并写入录像
+    buttons: [u8; 4], // 前端传入的四个手柄的按键，3P/4P 只在接了四人适配器时有效
+    input_filter: InputFilter, // 连发和宏，处理后的按键才送给手柄和录像
+    light_scanline: u16, // 上次为光枪检测亮光的扫描线
+    pub frame_count: u32, // 上电以来的帧数
+    pub lag_count: u32, // 没有读取手柄的帧数
     log: String,
 }
 
@@ -49,10 +77,9 @@ impl Emulator {
         let pip_rom = bounded(1);
         let pip_log = bounded(1);
         let pip_ppu_frame = bounded(1);
-        let pip_input_stream: (Sender<HashSet<Key>>, Receiver<HashSet<Key>>) = bounded(1);
-        let bus: Rc<RefCell<Bus>>  = Rc::new(RefCell::new(Bus::new(pip_input_stream.1.clone())));
-        let cpu = Cpu::new(Rc::clone(&bus));
-        let ppu = Ppu::new(Rc::clone(&bus),pip_ppu_frame.0.clone());
+        let bus: Rc<RefCell<Bus>>  = Rc::new(RefCell::new(Bus::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(Rc::clone(&bus),pip_ppu_frame.0.clone())));
+        let cpu = Cpu::new(Rc::clone(&bus), Rc::clone(&ppu));
         Emulator {
             pip_cpu2bus,
             pip_bus2cpu,
@@ -64,95 +91,702 @@ impl Emulator {
             pip_log,
             pip_ppu_frame,
             palettes: Palettes::new(),
-            pip_input_stream,
             cpu,
             ppu,
             bus,
+            game_db: GameDb::builtin(),
+            game_db_files: Vec::new(),
+            fds_bios: None,
+            disk_save_path: None,
+            rom_path: None,
+            save_prefix: None,
+            rom_crc32: 0,
+            rom_data: Vec::new(),
+            rewind: RewindBuffer::new(60, 2),
+            rewinding: false,
+            movie: None,
+            pending_commands: 0,
+            buttons: [0; 4],
+            input_filter: InputFilter::new(),
+            light_scanline: 0,
+            frame_count: 0,
+            lag_count: 0,
             log: String::new(),
         }
     }
 
-    pub fn load_rom(&mut self, path: &str) {
-        let mut file = File::open(Path::new(path)).expect("无法打开 ROM 文件");
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).expect("无法读取 ROM 文件");
-        self.bus.borrow_mut().load_rom(buffer);
+    // 加载 ROM，同目录下同名的 .ips/.ups/.bps 补丁会自动应用
+    pub fn load_rom(&mut self, path: &str) -> NesResult<()> {
+        self.load_rom_entry(path, None)
+    }
+
+    // 加载 ROM 或压缩包中的指定条目，entry 为 None 时取第一个 ROM 条目
+    pub fn load_rom_entry(&mut self, path: &str, entry: Option<&str>) -> NesResult<()> {
+        let patches = find_patches(path);
+        let patches: Vec<&str> = patches.iter().map(|patch| patch.as_str()).collect();
+        let (save_prefix, buffer) = read_rom_file(path, entry)?;
+        self.load_rom_data(path, &save_prefix, buffer, &patches)
+    }
+
+    // 加载 ROM 并按顺序在内存中应用补丁，原文件不会被修改
+    pub fn load_rom_with_patches(&mut self, path: &str, patches: &[&str]) -> NesResult<()> {
+        let (save_prefix, buffer) = read_rom_file(path, None)?;
+        self.load_rom_data(path, &save_prefix, buffer, patches)
+    }
+
+    fn load_rom_data(&mut self, path: &str, save_prefix: &str, mut buffer: Vec<u8>, patches: &[&str]) -> NesResult<()> {
+        for patch in patches {
+            println!("应用补丁: {}", patch);
+            buffer = apply_patch(&buffer, &std::fs::read(patch)?)?;
+        }
+        // 换卡前先保存上一张磁盘的写入
+        self.save_disk()?;
+        self.insert_cartridge(path, save_prefix, buffer.clone())?;
+        self.apply_default_expansion_device();
+        self.stop_movie();
+        self.rom_path = Some(path.to_string());
+        self.save_prefix = Some(save_prefix.to_string());
+        self.rom_crc32 = crc32fast::hash(&buffer);
+        self.rom_data = buffer;
+        self.rewind.clear();
+        self.frame_count = 0;
+        self.lag_count = 0;
         self.reset();
+        Ok(())
+    }
+
+    fn insert_cartridge(&mut self, path: &str, save_prefix: &str, buffer: Vec<u8>) -> NesResult<()> {
+        if is_fds_image(&buffer) {
+            let bios = self.find_fds_bios(path)?;
+            let save_path = format!("{}.sav", save_prefix);
+            let save = std::fs::read(&save_path).ok();
+            self.bus.borrow_mut().load_fds(buffer, bios, save.as_deref())?;
+            self.disk_save_path = Some(save_path);
+        } else {
+            self.load_external_game_db(path)?;
+            self.bus.borrow_mut().load_rom(buffer, &self.game_db)?;
+            self.disk_save_path = None;
+        }
+        Ok(())
+    }
+
+    // 模拟断电后重新上电：重新插入卡带，CPU/PPU 回到初始状态
+    fn power_on(&mut self) -> NesResult<()> {
+        let (Some(path), Some(save_prefix)) = (self.rom_path.clone(), self.save_prefix.clone()) else {
+            return Ok(());
+        };
+        self.save_disk()?;
+        self.insert_cartridge(&path, &save_prefix, self.rom_data.clone())?;
+        self.ppu = Rc::new(RefCell::new(Ppu::new(Rc::clone(&self.bus), self.pip_ppu_frame.0.clone())));
+        // XAA 魔数是用户的设置，不随上电恢复默认值
+        let xaa_magic = self.cpu.xaa_magic;
+        self.cpu = Cpu::new(Rc::clone(&self.bus), Rc::clone(&self.ppu));
+        self.cpu.xaa_magic = xaa_magic;
+        self.bus.borrow_mut().hard_reset();
+        self.cpu.hard_reset();
+        self.ppu.borrow_mut().hard_reset();
+        self.frame_count = 0;
+        self.lag_count = 0;
+        Ok(())
+    }
+
+    // 把整台机器的状态保存为即时存档
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_crc32);
+        writer.section(b"EMU ");
+        writer.write_u32(self.frame_count);
+        writer.write_u32(self.lag_count);
+        self.cpu.save_state(&mut writer);
+        self.ppu.borrow().save_state(&mut writer);
+        self.bus.borrow().save_state(&mut writer);
+        writer.finish()
+    }
+
+    // 读取即时存档，失败时恢复到读取前的状态
+    pub fn load_state(&mut self, data: &[u8]) -> NesResult<()> {
+        self.apply_state(data)?;
+        self.movie_state_loaded(true);
+        Ok(())
+    }
+
+    // 只恢复机器状态，不处理录像
+    fn apply_state(&mut self, data: &[u8]) -> NesResult<()> {
+        let mut reader = StateReader::new(data)?;
+        if reader.rom_crc32 != self.rom_crc32 {
+            return Err(NesError::InvalidState("存档属于另一个游戏".to_string()));
+        }
+        let backup = self.save_state();
+        if let Err(error) = self.restore_state(&mut reader) {
+            self.restore_state(&mut StateReader::new(&backup)?)?;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    fn restore_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.section(b"EMU ")?;
+        self.frame_count = reader.read_u32()?;
+        self.lag_count = reader.read_u32()?;
+        self.cpu.load_state(reader)?;
+        self.ppu.borrow_mut().load_state(reader)?;
+        self.bus.borrow_mut().load_state(reader)
+    }
+
+    // 录制中读档：读写模式下从存档的帧开始重新录制，只读模式下跳到该帧继续播放
+    // rerecord 为 false 时不增加重录次数
+    fn movie_state_loaded(&mut self, rerecord: bool) {
+        let frame_count = self.frame_count as usize;
+        if let Some(movie) = &mut self.movie {
+            if movie.read_only {
+                movie.mode = if frame_count < movie.frames.len() { MovieMode::Playing } else { MovieMode::Finished };
+            } else {
+                movie.frames.truncate(frame_count);
+                movie.mode = MovieMode::Recording;
+                if rerecord {
+                    movie.rerecord_count += 1;
+                }
+            }
+        }
+        self.pending_commands = 0;
+    }
+
+    // 运行一帧，录像的输入在帧开始时确定，整帧不变
+    pub fn run_frame(&mut self) {
+        self.rewinding = false;
+        self.begin_movie_frame();
+        self.bus.borrow_mut().apu_io_registers.polled = false;
+        while !self.ppu.borrow().new_frame {
+            self.cpu_step();
+        }
+        self.ppu.borrow_mut().new_frame = false;
+        if !self.bus.borrow().apu_io_registers.polled {
+            self.lag_count += 1;
+        }
+        self.frame_count += 1;
+        self.capture_rewind();
+    }
+
+    fn begin_movie_frame(&mut self) {
+        let frame_count = self.frame_count as usize;
+        // 回放录像时录像中已经是处理后的按键
+        let playing = self.movie.as_ref().map_or(false, |movie| movie.mode == MovieMode::Playing);
+        let buttons = if playing { self.buttons } else { self.input_filter.next_frame(self.buttons) };
+        let frame = match &mut self.movie {
+            Some(movie) if movie.mode == MovieMode::Recording => {
+                let frame = MovieFrame {
+                    commands: self.pending_commands,
+                    port0: buttons[0],
+                    port1: buttons[1],
+                    port2: buttons[2],
+                    port3: buttons[3],
+                };
+                movie.frames.truncate(frame_count);
+                movie.frames.push(frame);
+                Some(frame)
+            }
+            Some(movie) if movie.mode == MovieMode::Playing => {
+                let frame = movie.frames.get(frame_count).copied();
+                if frame.is_none() {
+                    movie.mode = MovieMode::Finished;
+                }
+                frame
+            }
+            _ => None,
+        };
+        self.pending_commands = 0;
+        if let Some(frame) = frame {
+            if frame.commands & COMMAND_POWER != 0 {
+                // 上电会清零帧计数，之后继续按录像中的位置播放
+                let frame_count = self.frame_count;
+                let _ = self.power_on();
+                self.frame_count = frame_count;
+            } else if frame.commands & COMMAND_SOFT_RESET != 0 {
+                self.reset_components();
+            }
+        }
+        let buttons = frame.map_or(buttons, |frame| [frame.port0, frame.port1, frame.port2, frame.port3]);
+        self.apply_buttons(buttons);
+    }
+
+    // 开始录像：from_savestate 为真时从当前状态开始，否则先重新上电
+    pub fn start_recording(&mut self, from_savestate: bool) -> NesResult<()> {
+        let rom_filename = self
+            .rom_path
+            .as_deref()
+            .map(|path| Path::new(path).file_stem().unwrap_or_default().to_string_lossy().to_string())
+            .ok_or_else(|| NesError::InvalidMovie("还没有加载游戏".to_string()))?;
+        self.stop_movie();
+        let savestate = if from_savestate {
+            self.frame_count = 0;
+            self.lag_count = 0;
+            Some(self.save_state())
+        } else {
+            self.power_on()?;
+            None
+        };
+        let mut movie = Movie::new(rom_filename, movie::rom_checksum(&self.rom_data), savestate);
+        movie.fds = self.disk_side_count() > 0;
+        movie.fourscore = self.port_config() != PortConfig::TwoPlayers;
+        self.movie = Some(movie);
+        self.rewind.clear();
+        Ok(())
+    }
+
+    // 播放录像，只读模式下读档不会修改录像
+    pub fn play_movie(&mut self, path: &str, read_only: bool) -> NesResult<()> {
+        let mut movie = Movie::parse(&std::fs::read_to_string(path)?)?;
+        if movie.rom_checksum != movie::rom_checksum(&self.rom_data) {
+            return Err(NesError::InvalidMovie(format!("录像属于另一个游戏: {}", movie.rom_filename)));
+        }
+        self.stop_movie();
+        if movie.fourscore && self.port_config() == PortConfig::TwoPlayers {
+            self.set_port_config(PortConfig::FourScore);
+        }
+        match &movie.savestate {
+            Some(state) => self.load_state(state)?,
+            None => self.power_on()?,
+        }
+        movie.read_only = read_only;
+        movie.mode = MovieMode::Playing;
+        self.movie = Some(movie);
+        self.rewind.clear();
+        Ok(())
+    }
+
+    pub fn save_movie(&self, path: &str) -> NesResult<()> {
+        if let Some(movie) = &self.movie {
+            std::fs::write(path, movie.to_fm2())?;
+        }
+        Ok(())
+    }
+
+    pub fn stop_movie(&mut self) {
+        self.movie = None;
+        self.pending_commands = 0;
+        self.apply_buttons(self.input_filter.current(self.buttons));
+    }
+
+    pub fn movie(&self) -> Option<&Movie> {
+        self.movie.as_ref()
+    }
+
+    pub fn set_movie_read_only(&mut self, read_only: bool) {
+        if let Some(movie) = &mut self.movie {
+            movie.read_only = read_only;
+        }
+    }
+
+    // XAA 指令的魔数，不同测试和机器使用的值不同
+    pub fn set_xaa_magic(&mut self, magic: u8) {
+        self.cpu.xaa_magic = magic;
+    }
+
+    // 回溯设置：保留 seconds 秒的历史，每 interval 帧保存一次
+    pub fn set_rewind_config(&mut self, seconds: u32, interval: u32) {
+        self.rewind.configure(seconds, interval);
+    }
+
+    // 每帧结束时调用，按间隔保存回溯快照
+    pub fn capture_rewind(&mut self) {
+        if self.rewind.frame_elapsed() {
+            let state = self.save_state();
+            self.rewind.push(state);
+        }
+    }
+
+    // 回退到上一个回溯快照，没有更早的历史时返回 false
+    // 按住回溯键的一次连续回溯只算一次重录
+    pub fn rewind_step(&mut self) -> bool {
+        let Some(state) = self.rewind.pop() else {
+            return false;
+        };
+        if self.apply_state(&state).is_err() {
+            return false;
+        }
+        self.movie_state_loaded(!self.rewinding);
+        self.rewinding = true;
+        true
+    }
+
+    // 编号存档保存在 ROM 旁边：game.nes.st0 ~ game.nes.st9，压缩包中的 ROM 为 game.zip#mario.nes.st0
+    fn state_slot_path(&self, slot: u8) -> NesResult<String> {
+        self.save_prefix
+            .as_ref()
+            .map(|path| format!("{}.st{}", path, slot))
+            .ok_or_else(|| NesError::InvalidState("还没有加载游戏".to_string()))
+    }
+
+    pub fn save_state_slot(&self, slot: u8) -> NesResult<()> {
+        std::fs::write(self.state_slot_path(slot)?, self.save_state())?;
+        Ok(())
+    }
+
+    pub fn load_state_slot(&mut self, slot: u8) -> NesResult<()> {
+        let data = std::fs::read(self.state_slot_path(slot)?)?;
+        self.load_state(&data)
+    }
+
+    // 设置 FDS BIOS，之后加载的 .fds 都使用它
+    pub fn load_fds_bios(&mut self, path: &str) -> NesResult<()> {
+        let bios = std::fs::read(path)?;
+        if bios.len() != FDS_BIOS_SIZE {
+            return Err(NesError::InvalidFds(format!("BIOS 应为{}字节, 实际{}字节", FDS_BIOS_SIZE, bios.len())));
+        }
+        self.fds_bios = Some(bios);
+        Ok(())
+    }
+
+    // 和 disksys.rom 一样，在 ROM 所在目录和当前目录查找外部数据库，每个文件只加载一次
+    fn load_external_game_db(&mut self, rom_path: &str) -> NesResult<()> {
+        let rom_dir = Path::new(rom_path).parent().unwrap_or(Path::new(""));
+        for dir in [rom_dir, Path::new("")] {
+            for name in GAME_DB_FILES {
+                let candidate = dir.join(name);
+                let Ok(canonical) = candidate.canonicalize() else {
+                    continue;
+                };
+                if self.game_db_files.contains(&canonical) {
+                    continue;
+                }
+                let count = self.game_db.load_file(&candidate.to_string_lossy())?;
+                println!("游戏数据库: 从{}加载{}条", candidate.display(), count);
+                self.game_db_files.push(canonical);
+            }
+        }
+        Ok(())
+    }
+
+    // 没有手动设置时，在 ROM 所在目录和当前目录查找 disksys.rom
+    fn find_fds_bios(&self, rom_path: &str) -> NesResult<Vec<u8>> {
+        if let Some(bios) = &self.fds_bios {
+            return Ok(bios.clone());
+        }
+        let rom_dir = Path::new(rom_path).parent().unwrap_or(Path::new(""));
+        [rom_dir.join("disksys.rom"), Path::new("disksys.rom").to_path_buf()]
+            .iter()
+            .find_map(|candidate| std::fs::read(candidate).ok())
+            .ok_or(NesError::MissingFdsBios)
+    }
+
+    // 磁盘被写入过时，以 IPS 格式把差异保存到 ROM 旁边的 .sav 文件，原镜像不修改
+    pub fn save_disk(&self) -> NesResult<()> {
+        if let (Some(path), Some(diff)) = (&self.disk_save_path, self.bus.borrow().disk_diff()) {
+            std::fs::write(path, diff)?;
+        }
+        Ok(())
     }
 
+    // FDS 磁盘面数，非 FDS 为0
+    pub fn disk_side_count(&self) -> usize {
+        self.bus.borrow().disk_side_count()
+    }
+
+    pub fn inserted_disk_side(&self) -> Option<usize> {
+        self.bus.borrow().inserted_disk_side()
+    }
+
+    // 插入指定的磁盘面，None 表示弹出
+    pub fn insert_disk_side(&mut self, side: Option<usize>) -> NesResult<()> {
+        self.save_disk()?;
+        self.bus.borrow_mut().insert_disk_side(side);
+        Ok(())
+    }
+
+    // 换到下一面（A 面 -> B 面 -> 下一张磁盘）
+    pub fn swap_disk_side(&mut self) -> NesResult<()> {
+        let count = self.disk_side_count();
+        if count == 0 {
+            return Ok(());
+        }
+        let next = self.inserted_disk_side().map_or(0, |side| (side + 1) % count);
+        self.insert_disk_side(Some(next))
+    }
+
+    // 当前 ROM 的路径，存档、截图等保存在旁边
+    pub fn rom_path(&self) -> Option<&str> {
+        self.rom_path.as_deref()
+    }
+
+    // 取走上次调用以来的声音采样，采样率为 bus::mixer::SAMPLE_RATE
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.bus.borrow_mut().take_audio_samples()
+    }
+
+    // 当前卡带的描述信息（NES 2.0 / iNES）
+    pub fn rom_header(&self) -> RomHeader {
+        self.bus.borrow().rom_header.clone()
+    }
+
+    // 游戏数据库中匹配到的条目（标题、地区、电路板）
+    pub fn game_info(&self) -> Option<GameInfo> {
+        self.bus.borrow().game_info.clone()
+    }
+
+    // 用 NES 2.0 数据库格式的 XML/CSV 文件扩充游戏数据库，返回新增条目数
+    pub fn load_game_db(&mut self, path: &str) -> NesResult<usize> {
+        self.game_db.load_file(path)
+    }
+
+    // 录制时复位记录到录像中，下一帧开始时执行
     pub fn reset(&mut self) {
+        if self.is_recording() {
+            self.pending_commands |= COMMAND_SOFT_RESET;
+            return;
+        }
+        self.reset_components();
+    }
+
+    fn reset_components(&mut self) {
         self.cpu.reset();
-        self.ppu.reset();
+        self.ppu.borrow_mut().reset();
         self.bus.borrow_mut().reset();
     }
 
+    fn is_recording(&self) -> bool {
+        self.movie.as_ref().map_or(false, |movie| movie.mode == MovieMode::Recording)
+    }
+
     pub fn hard_reset(&mut self) {
+        if self.is_recording() {
+            self.pending_commands |= COMMAND_POWER;
+            return;
+        }
         self.cpu.hard_reset();
-        self.ppu.hard_reset();
+        self.ppu.borrow_mut().hard_reset();
         self.bus.borrow_mut().hard_reset();
     }
 
     
 
-    pub fn refresh_input(&mut self,new_input:HashSet<egui::Key>) {
-        self.bus.borrow_mut().refresh_input(new_input);
+    // 设置 player 号手柄 (0~3) 的按键，顺序是A, B, Select, Start, Up, Down, Left, Right
+    // 录制时在下一帧开始时生效，播放录像时被录像覆盖；超出范围的 player 被忽略
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        if player >= self.buttons.len() {
+            return;
+        }
+        self.buttons[player] = buttons;
+        let movie_active = self.movie.as_ref().map_or(false, |movie| movie.mode != MovieMode::Finished);
+        if !movie_active {
+            let buttons = self.input_filter.current(self.buttons)[player];
+            self.bus.borrow_mut().set_buttons(player, buttons);
+        }
     }
 
-    pub fn cpu_clock(&mut self) {
-        for _ in 0..3 {
-            self.ppu.step();
-        }
-        self.cpu.cpu_cycle_wait -= 1;
-        if self.cpu.cpu_cycle_wait == 0 {
-            self.cpu.step();
+    // 连发和输入宏的设置
+    pub fn input_filter(&self) -> &InputFilter {
+        &self.input_filter
+    }
+
+    pub fn input_filter_mut(&mut self) -> &mut InputFilter {
+        &mut self.input_filter
+    }
+
+    fn apply_buttons(&mut self, buttons: [u8; 4]) {
+        let mut bus = self.bus.borrow_mut();
+        for (player, buttons) in buttons.iter().enumerate() {
+            bus.set_buttons(player, *buttons);
         }
     }
 
-    pub fn cpu_step(&mut self) {
-        while self.cpu.cpu_cycle_wait != 1 {
-            self.cpu_clock();
+    pub fn port_device(&self, port: usize) -> PortDevice {
+        self.bus.borrow().apu_io_registers.port(port).kind()
+    }
+
+    // 更换 port 号端口 (0或1) 上的设备
+    pub fn set_port_device(&mut self, port: usize, device: PortDevice) {
+        self.bus.borrow_mut().apu_io_registers.set_port_device(port, device);
+        self.apply_buttons(self.input_filter.current(self.buttons));
+    }
+
+    pub fn port_config(&self) -> PortConfig {
+        PortConfig::from_device(self.port_device(0))
+    }
+
+    // 切换双人/四人接法，从四人切回双人时两个端口都接手柄
+    pub fn set_port_config(&mut self, config: PortConfig) {
+        let device = match config {
+            PortConfig::TwoPlayers => PortDevice::StandardController,
+            PortConfig::FourScore => PortDevice::FourScore,
+            PortConfig::FamicomFourPlayers => PortDevice::FamicomFourPlayer,
+        };
+        if self.port_config() == config && config == PortConfig::TwoPlayers {
+            return;
         }
-        for _ in 0..3 {
-            self.ppu.step();
+        self.set_port_device(0, device);
+        self.set_port_device(1, device);
+    }
+
+    // 按 NES 2.0 头 (或游戏数据库) 中的默认扩展设备接好端口，未指定时保持不变
+    fn apply_default_expansion_device(&mut self) {
+        match self.rom_header().default_expansion_device {
+            0x01 => {
+                self.set_port_device(0, PortDevice::StandardController);
+                self.set_port_device(1, PortDevice::StandardController);
+                self.set_expansion(Expansion::None);
+            }
+            0x02 => self.set_port_config(PortConfig::FourScore),
+            0x03 => self.set_port_config(PortConfig::FamicomFourPlayers),
+            0x08 => {
+                // Zapper 接在 $4017
+                self.set_port_config(PortConfig::TwoPlayers);
+                self.set_port_device(1, PortDevice::Zapper);
+            }
+            // Power Pad A/B 面，按键编号不同，这里都按 B 面处理
+            0x0B | 0x0C => {
+                self.set_port_config(PortConfig::TwoPlayers);
+                self.set_port_device(1, PortDevice::PowerPad);
+            }
+            0x0D | 0x0E => self.set_expansion(Expansion::FamilyTrainer),
+            0x0F => {
+                self.set_port_config(PortConfig::TwoPlayers);
+                self.set_port_device(1, PortDevice::Arkanoid);
+            }
+            0x10 => self.set_expansion(Expansion::Arkanoid),
+            0x23 => self.set_expansion(Expansion::FamilyBasicKeyboard),
+            _ => {}
         }
-        self.cpu.step();
-        while self.cpu.cpu_cycle_wait != 1 {
-            self.cpu_clock();
+    }
+
+    pub fn expansion(&self) -> Expansion {
+        self.bus.borrow().apu_io_registers.expansion_kind()
+    }
+
+    // 更换 Famicom 扩展口上的设备
+    pub fn set_expansion(&mut self, expansion: Expansion) {
+        self.bus.borrow_mut().apu_io_registers.set_expansion(expansion);
+    }
+
+    // 光标在画面上的位置 (NES 坐标，屏幕外为负数) 和鼠标左键，传给光枪、Arkanoid 等设备
+    pub fn set_pointer(&mut self, x: i32, y: i32, trigger: bool) {
+        self.bus.borrow_mut().apu_io_registers.set_pointer(x, y, trigger);
+    }
+
+    // Power Pad / Family Trainer 的12个按键，第 n-1 位为按键 n
+    pub fn set_mat_buttons(&mut self, buttons: u16) {
+        self.bus.borrow_mut().apu_io_registers.set_mat_buttons(buttons);
+    }
+
+    // Family BASIC 键盘按下的键，每行低4位为第0列，高4位为第1列
+    pub fn set_keyboard(&mut self, matrix: [u8; KEYBOARD_ROWS]) {
+        if let Some(expansion) = &mut self.bus.borrow_mut().apu_io_registers.expansion {
+            expansion.set_keyboard(matrix);
         }
     }
 
-    pub fn cpu_clock_debug(&mut self) {
-        for _ in 0..3 {
-            println!("-{}",self.get_log());
-            self.ppu.step();
+    // 扩展口上的数据记录器，没有接时返回错误
+    fn with_data_recorder<T>(&mut self, f: impl FnOnce(&mut DataRecorder) -> NesResult<T>) -> NesResult<T> {
+        let mut bus = self.bus.borrow_mut();
+        let recorder = bus
+            .apu_io_registers
+            .expansion
+            .as_mut()
+            .and_then(|expansion| expansion.data_recorder_mut())
+            .ok_or_else(|| NesError::InvalidWav("扩展口没有接数据记录器".to_string()))?;
+        f(recorder)
+    }
+
+    // 数据记录器的状态和磁带进度 (当前秒数, 总秒数)，没有接时为 None
+    pub fn tape_status(&self) -> Option<(TapeMode, (f32, f32))> {
+        let bus = self.bus.borrow();
+        let recorder = bus.apu_io_registers.expansion.as_ref()?.data_recorder()?;
+        Some((recorder.mode(), recorder.progress()))
+    }
+
+    // 把 WAV 文件放入数据记录器并开始播放
+    pub fn play_tape(&mut self, path: &str) -> NesResult<()> {
+        let wav = std::fs::read(path)?;
+        self.with_data_recorder(|recorder| recorder.play(&wav))
+    }
+
+    pub fn record_tape(&mut self) -> NesResult<()> {
+        self.with_data_recorder(|recorder| {
+            recorder.record();
+            Ok(())
+        })
+    }
+
+    pub fn stop_tape(&mut self) -> NesResult<()> {
+        self.with_data_recorder(|recorder| {
+            recorder.stop();
+            Ok(())
+        })
+    }
+
+    // 磁带内容保存为 WAV 文件
+    pub fn save_tape(&mut self, path: &str) -> NesResult<()> {
+        let wav = self.with_data_recorder(|recorder| Ok(recorder.to_wav()))?;
+        std::fs::write(path, wav)?;
+        Ok(())
+    }
+
+    // 每条扫描线检测一次光枪是否看到亮光
+    // PPU 在每条可见扫描线结束时输出该行，这里只检查电子束已经扫过的行
+    // 在指令之间检测，最多晚几个 CPU 周期，对按扫描线计的感光没有影响
+    fn sense_light(&mut self) {
+        let scanline = self.ppu.borrow().scanline;
+        if scanline == self.light_scanline {
+            return;
         }
-        self.cpu.cpu_cycle_wait -= 1;
-        if self.cpu.cpu_cycle_wait == 0 {
-            println!("*{}",self.get_log());
-            self.cpu.step();
+        self.light_scanline = scanline;
+        let sensors = {
+            let bus = self.bus.borrow();
+            [bus.apu_io_registers.port1.light_sensor(), bus.apu_io_registers.port2.light_sensor()]
+        };
+        for (port, sensor) in sensors.iter().enumerate() {
+            let light = sensor.map_or(false, |(x, y)| {
+                detect_light(&self.ppu.borrow().frame_color_index_cache, &self.palettes, x, y, scanline as i32)
+            });
+            self.bus.borrow_mut().apu_io_registers.port_mut(port).set_light(light);
         }
     }
 
+    // 执行一条指令 (或一次中断)，CPU 在每次访问总线时推进 PPU 和卡带
+    pub fn cpu_step(&mut self) {
+        self.cpu.step();
+        self.sense_light();
+    }
+
     pub fn cpu_step_debug(&mut self) {
-        while self.cpu.cpu_cycle_wait != 1 {
-            self.cpu_clock();
-        }
-        for _ in 0..3 {
-            self.ppu.step();
-        }
         println!("*{}",self.get_log());
-        self.cpu.step();
-        while self.cpu.cpu_cycle_wait != 1 {
-            self.cpu_clock();
-        }
+        self.cpu_step();
     }
     
     pub fn get_log(&self) -> String {
         let mut emulator_log_line = self.cpu.get_current_log();
-        emulator_log_line.push_str(format!(" PPU:{:3},{:3} CYC:{}", self.ppu.scanline, self.ppu.dot, self.cpu.cpu_cycle).as_str());
+        let ppu = self.ppu.borrow();
+        emulator_log_line.push_str(format!(" PPU:{:3},{:3} CYC:{}", ppu.scanline, ppu.dot, self.cpu.cpu_cycle).as_str());
         emulator_log_line.push_str(format!(" ,$2002:{:02X}", self.cpu.read_debug(0x2002)).as_str());
         emulator_log_line.push_str(format!(" ,ppustatus_racing:{}",self.bus.borrow().ppustatus_racing).as_str());
         emulator_log_line
         // self.cpu.get_current_log()
     }
 }
+
+// 读取 ROM 文件，zip/gz 压缩包会先解压，同时返回存档文件名的前缀
+// 同一个压缩包里可能有多个游戏，前缀要带上条目名，子目录中的 / 换成 _
+fn read_rom_file(path: &str, entry: Option<&str>) -> NesResult<(String, Vec<u8>)> {
+    if archive::is_archive(path) {
+        let (entry, buffer) = archive::read_rom_entry(path, entry)?;
+        return Ok((format!("{}#{}", path, entry.replace(['/', '\\'], "_")), buffer));
+    }
+    let mut file = File::open(Path::new(path))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok((path.to_string(), buffer))
+}
+
+// 查找 ROM 旁边的补丁：game.ips 或 game.nes.ips
+fn find_patches(path: &str) -> Vec<String> {
+    let rom_path = Path::new(path);
+    let mut patches = Vec::new();
+    for extension in ["ips", "ups", "bps"] {
+        let candidates = [
+            rom_path.with_extension(extension),
+            Path::new(&format!("{}.{}", path, extension)).to_path_buf(),
+        ];
+        if let Some(patch) = candidates.iter().find(|candidate| candidate.is_file()) {
+            patches.push(patch.to_string_lossy().to_string());
+        }
+    }
+    patches
+}
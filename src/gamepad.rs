@@ -0,0 +1,175 @@
+// 手柄输入，通过 gilrs 读取 (Linux 下为 evdev)
+// 连接的手柄按顺序分配到 1P~4P，拔出后空出的位置分给其他已连接的手柄
+// 只读取按键和摇杆，不使用震动
+
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+
+use crate::input::standard_controller::{BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_UP};
+
+// 可以用作方向的轴
+pub const AXES: [Axis; 6] = [
+    Axis::LeftStickX,
+    Axis::LeftStickY,
+    Axis::RightStickX,
+    Axis::RightStickY,
+    Axis::DPadX,
+    Axis::DPadY,
+];
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GamepadMapping {
+    pub buttons: [Button; 8], // 顺序是A, B, Select, Start, Up, Down, Left, Right
+    pub axis_x: Axis, // 左右方向的轴，正值为右
+    pub axis_y: Axis, // 上下方向的轴，正值为上
+    pub threshold: f32, // 摇杆超过这个值时视为按下方向键
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        GamepadMapping {
+            buttons: [
+                Button::East,
+                Button::South,
+                Button::Select,
+                Button::Start,
+                Button::DPadUp,
+                Button::DPadDown,
+                Button::DPadLeft,
+                Button::DPadRight,
+            ],
+            axis_x: Axis::LeftStickX,
+            axis_y: Axis::LeftStickY,
+            threshold: 0.5,
+        }
+    }
+}
+
+pub struct GamepadInput {
+    gilrs: Option<Gilrs>, // 初始化失败时 (例如没有 udev) 只使用键盘
+    ports: [Option<GamepadId>; 4], // 分配到 1P~4P 的手柄，3P/4P 在接了四人适配器时使用
+    last_pressed: Option<Button>, // 最近按下的按键，用于按键设置
+}
+
+impl GamepadInput {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(error) => {
+                println!("无法初始化手柄: {}", error);
+                None
+            }
+        };
+        let mut input = GamepadInput { gilrs, ports: [None; 4], last_pressed: None };
+        // 启动前已经连接的手柄
+        let connected: Vec<GamepadId> = input
+            .gilrs
+            .as_ref()
+            .map(|gilrs| gilrs.gamepads().map(|(id, _)| id).collect())
+            .unwrap_or_default();
+        for id in connected {
+            input.assign(id);
+        }
+        input
+    }
+
+    // 每帧调用一次，处理插拔事件
+    pub fn update(&mut self) {
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+        let mut events = Vec::new();
+        while let Some(event) = gilrs.next_event() {
+            events.push((event.id, event.event));
+        }
+        for (id, event) in events {
+            match event {
+                EventType::Connected => self.assign(id),
+                EventType::Disconnected => self.unassign(id),
+                EventType::ButtonPressed(button, _) if button != Button::Unknown => self.last_pressed = Some(button),
+                _ => {}
+            }
+        }
+    }
+
+    // 分配到第一个空闲的端口
+    fn assign(&mut self, id: GamepadId) {
+        if self.ports.contains(&Some(id)) {
+            return;
+        }
+        if let Some(port) = self.ports.iter_mut().find(|port| port.is_none()) {
+            *port = Some(id);
+        }
+    }
+
+    fn unassign(&mut self, id: GamepadId) {
+        for port in self.ports.iter_mut() {
+            if *port == Some(id) {
+                *port = None;
+            }
+        }
+        let waiting: Vec<GamepadId> = self
+            .gilrs
+            .as_ref()
+            .map(|gilrs| gilrs.gamepads().map(|(id, _)| id).filter(|id| !self.ports.contains(&Some(*id))).collect())
+            .unwrap_or_default();
+        for id in waiting {
+            self.assign(id);
+        }
+    }
+
+    // 交换 1P 和 2P 的手柄
+    pub fn swap_ports(&mut self) {
+        self.ports.swap(0, 1);
+    }
+
+    // 各个位置上手柄的名字
+    pub fn port_names(&self) -> [Option<String>; 4] {
+        let name = |port: &Option<GamepadId>| {
+            let gilrs = self.gilrs.as_ref()?;
+            gilrs.connected_gamepad((*port)?).map(|gamepad| gamepad.name().to_string())
+        };
+        [name(&self.ports[0]), name(&self.ports[1]), name(&self.ports[2]), name(&self.ports[3])]
+    }
+
+    // 取出最近按下的按键
+    pub fn take_pressed(&mut self) -> Option<Button> {
+        self.last_pressed.take()
+    }
+
+    pub fn clear_pressed(&mut self) {
+        self.last_pressed = None;
+    }
+
+    // 1P~4P 的手柄按键字节，摇杆超过阈值时按方向键处理
+    pub fn buttons(&self, mapping: &GamepadMapping) -> [u8; 4] {
+        let mut buttons = [0; 4];
+        let Some(gilrs) = &self.gilrs else {
+            return buttons;
+        };
+        for (port, id) in self.ports.iter().enumerate() {
+            let Some(gamepad) = id.and_then(|id| gilrs.connected_gamepad(id)) else {
+                continue;
+            };
+            for (bit, button) in mapping.buttons.iter().enumerate() {
+                if gamepad.is_pressed(*button) {
+                    buttons[port] |= 1 << bit;
+                }
+            }
+            let (x, y) = (gamepad.value(mapping.axis_x), gamepad.value(mapping.axis_y));
+            if y > mapping.threshold {
+                buttons[port] |= BUTTON_UP;
+            }
+            if y < -mapping.threshold {
+                buttons[port] |= BUTTON_DOWN;
+            }
+            if x < -mapping.threshold {
+                buttons[port] |= BUTTON_LEFT;
+            }
+            if x > mapping.threshold {
+                buttons[port] |= BUTTON_RIGHT;
+            }
+        }
+        buttons
+    }
+}
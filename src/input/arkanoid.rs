@@ -0,0 +1,139 @@
+// Arkanoid 控制器 (Vaus)：旋钮接电位器，strobe 时采样为8位数值，取反后高位先移出
+// NES 版接在 $4017: D3 为按钮，D4 为串行数据
+// Famicom 版接在扩展口: $4016 D1 为按钮，$4017 D1 为串行数据
+// https://www.nesdev.org/wiki/Arkanoid_controller
+
+use crate::input::{Expansion, ExpansionDevice, InputDevice, PortDevice};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
+// 旋钮转到两端时的采样值，游戏只使用这个范围
+const MIN_VALUE: u8 = 0x62;
+const MAX_VALUE: u8 = 0xF2;
+
+pub struct Arkanoid {
+    value: u8, // 旋钮位置
+    button: bool,
+    shift: u8, // 采样后的移位寄存器，移出的位补0 (取反后为1)
+    strobe: bool,
+}
+
+impl Arkanoid {
+    pub fn new() -> Self {
+        Arkanoid {
+            value: MIN_VALUE + (MAX_VALUE - MIN_VALUE) / 2,
+            button: false,
+            shift: 0,
+            strobe: false,
+        }
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.shift = self.value;
+        }
+    }
+
+    // 移出一位，已取反
+    fn shift_bit(&mut self) -> u8 {
+        let data = self.peek_bit();
+        if !self.strobe {
+            self.shift <<= 1;
+        }
+        data
+    }
+
+    fn peek_bit(&self) -> u8 {
+        let shift = if self.strobe { self.value } else { self.shift };
+        (!shift >> 7) & 0x01
+    }
+
+    // 鼠标的横坐标 (0~255) 对应旋钮位置，离开画面时保持不动
+    fn set_pointer(&mut self, x: i32, trigger: bool) {
+        if (0..256).contains(&x) {
+            let range = (MAX_VALUE - MIN_VALUE) as i32;
+            self.value = MIN_VALUE + (x * range / 255) as u8;
+        }
+        self.button = trigger;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.shift);
+        writer.write_bool(self.strobe);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.shift = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl InputDevice for Arkanoid {
+    fn kind(&self) -> PortDevice {
+        PortDevice::Arkanoid
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        Arkanoid::write_strobe(self, strobe);
+    }
+
+    fn read(&mut self) -> u8 {
+        let button = if self.button { 0x08 } else { 0 };
+        button | (self.shift_bit() << 4)
+    }
+
+    fn read_debug(&self) -> u8 {
+        let button = if self.button { 0x08 } else { 0 };
+        button | (self.peek_bit() << 4)
+    }
+
+    fn set_pointer(&mut self, x: i32, _y: i32, trigger: bool) {
+        Arkanoid::set_pointer(self, x, trigger);
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        Arkanoid::save_state(self, writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        Arkanoid::load_state(self, reader)
+    }
+}
+
+impl ExpansionDevice for Arkanoid {
+    fn kind(&self) -> Expansion {
+        Expansion::Arkanoid
+    }
+
+    fn write(&mut self, data: u8) {
+        self.write_strobe(data & 0x01 == 0x01);
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        match port {
+            0 => if self.button { 0x02 } else { 0 },
+            _ => self.shift_bit() << 1,
+        }
+    }
+
+    fn read_debug(&self, port: usize) -> u8 {
+        match port {
+            0 => if self.button { 0x02 } else { 0 },
+            _ => self.peek_bit() << 1,
+        }
+    }
+
+    fn set_pointer(&mut self, x: i32, _y: i32, trigger: bool) {
+        Arkanoid::set_pointer(self, x, trigger);
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        Arkanoid::save_state(self, writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        Arkanoid::load_state(self, reader)
+    }
+}
@@ -0,0 +1,125 @@
+// Famicom 数据记录器 (磁带机)，接在 Family BASIC 键盘的音频插孔上
+// 写 $4016 的 D2 为输出到磁带的电平，读 $4016 的 D1 为从磁带读到的电平
+// 磁带内容用 WAV 文件保存，按 CPU 周期换算成采样位置
+// https://www.nesdev.org/wiki/Family_BASIC_Data_Recorder
+
+use crate::utils::wav::{read_wav, write_wav};
+use crate::NesResult;
+
+// NTSC CPU 频率
+const CPU_FREQUENCY: u32 = 1_789_773;
+// 录音时的采样率
+const RECORD_SAMPLE_RATE: u32 = 44100;
+// 录音时高低电平的幅度
+const RECORD_AMPLITUDE: i16 = 0x4000;
+// 放音时超过这个幅度才改变电平，避免噪声来回翻转
+const PLAY_THRESHOLD: i16 = 0x0800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapeMode {
+    Stopped,
+    Playing,
+    Recording,
+}
+
+pub struct DataRecorder {
+    mode: TapeMode,
+    samples: Vec<i16>, // 磁带内容
+    sample_rate: u32,
+    position: usize, // 当前采样
+    phase: u32, // 每个 CPU 周期加 sample_rate，达到 CPU_FREQUENCY 时前进一个采样
+    output: bool, // 写入磁带的电平
+    input: bool, // 从磁带读到的电平
+}
+
+impl DataRecorder {
+    pub fn new() -> Self {
+        DataRecorder {
+            mode: TapeMode::Stopped,
+            samples: Vec::new(),
+            sample_rate: RECORD_SAMPLE_RATE,
+            position: 0,
+            phase: 0,
+            output: false,
+            input: false,
+        }
+    }
+
+    pub fn mode(&self) -> TapeMode {
+        self.mode
+    }
+
+    // 磁带的长度和当前位置 (秒)
+    pub fn progress(&self) -> (f32, f32) {
+        let rate = self.sample_rate as f32;
+        (self.position as f32 / rate, self.samples.len() as f32 / rate)
+    }
+
+    // 从头播放 WAV 文件
+    pub fn play(&mut self, wav: &[u8]) -> NesResult<()> {
+        let (samples, sample_rate) = read_wav(wav)?;
+        self.samples = samples;
+        self.sample_rate = sample_rate.max(1);
+        self.position = 0;
+        self.phase = 0;
+        self.input = false;
+        self.mode = TapeMode::Playing;
+        Ok(())
+    }
+
+    // 清空磁带并开始录音
+    pub fn record(&mut self) {
+        self.samples.clear();
+        self.sample_rate = RECORD_SAMPLE_RATE;
+        self.position = 0;
+        self.phase = 0;
+        self.mode = TapeMode::Recording;
+    }
+
+    pub fn stop(&mut self) {
+        self.mode = TapeMode::Stopped;
+    }
+
+    // 磁带内容转换为 WAV 文件
+    pub fn to_wav(&self) -> Vec<u8> {
+        write_wav(&self.samples, self.sample_rate)
+    }
+
+    pub fn write(&mut self, output: bool) {
+        self.output = output;
+    }
+
+    pub fn read(&self) -> bool {
+        self.mode == TapeMode::Playing && self.input
+    }
+
+    // 每个 CPU 周期调用一次
+    pub fn clock(&mut self) {
+        if self.mode == TapeMode::Stopped {
+            return;
+        }
+        self.phase += self.sample_rate;
+        while self.phase >= CPU_FREQUENCY {
+            self.phase -= CPU_FREQUENCY;
+            match self.mode {
+                TapeMode::Playing => {
+                    let Some(&sample) = self.samples.get(self.position) else {
+                        self.mode = TapeMode::Stopped;
+                        return;
+                    };
+                    if sample > PLAY_THRESHOLD {
+                        self.input = true;
+                    } else if sample < -PLAY_THRESHOLD {
+                        self.input = false;
+                    }
+                    self.position += 1;
+                }
+                TapeMode::Recording => {
+                    self.samples.push(if self.output { RECORD_AMPLITUDE } else { -RECORD_AMPLITUDE });
+                    self.position += 1;
+                }
+                TapeMode::Stopped => {}
+            }
+        }
+    }
+}
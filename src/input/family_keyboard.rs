@@ -0,0 +1,106 @@
+// Family BASIC 键盘，接在 Famicom 扩展口，9行x2列，每列4个键，共72键
+// 写 $4016: D0 为1时回到第0行，D1 为列选择 (从1变为0时前进到下一行)，D2 为1时启用键盘
+// 读 $4017: D1~D4 为当前行列的4个键，0为按下；第9行之后全部为1
+// 数据记录器接在键盘上，见 data_recorder.rs
+// https://www.nesdev.org/wiki/Family_BASIC_Keyboard
+
+use crate::input::data_recorder::DataRecorder;
+use crate::input::{Expansion, ExpansionDevice};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
+pub const KEYBOARD_ROWS: usize = 9;
+
+pub struct FamilyKeyboard {
+    matrix: [u8; KEYBOARD_ROWS], // 按下的键，低4位为第0列，高4位为第1列，第 n 位对应 $4017 的 D(n%4+1)
+    row: usize,
+    column: usize,
+    enabled: bool,
+    data_recorder: DataRecorder,
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        FamilyKeyboard {
+            matrix: [0; KEYBOARD_ROWS],
+            row: 0,
+            column: 0,
+            enabled: false,
+            data_recorder: DataRecorder::new(),
+        }
+    }
+}
+
+impl ExpansionDevice for FamilyKeyboard {
+    fn kind(&self) -> Expansion {
+        Expansion::FamilyBasicKeyboard
+    }
+
+    fn write(&mut self, data: u8) {
+        let column = ((data >> 1) & 0x01) as usize;
+        self.enabled = data & 0x04 == 0x04;
+        if self.enabled {
+            if self.column == 1 && column == 0 {
+                self.row = (self.row + 1).min(KEYBOARD_ROWS);
+            }
+            if data & 0x01 == 0x01 {
+                self.row = 0;
+            }
+        }
+        self.column = column;
+        self.data_recorder.write(data & 0x04 == 0x04);
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        self.read_debug(port)
+    }
+
+    fn read_debug(&self, port: usize) -> u8 {
+        if port == 0 {
+            return if self.data_recorder.read() { 0x02 } else { 0 };
+        }
+        if !self.enabled {
+            return 0;
+        }
+        match self.matrix.get(self.row) {
+            Some(keys) => (!(keys >> (self.column * 4)) << 1) & 0x1E,
+            None => 0x1E,
+        }
+    }
+
+    fn set_keyboard(&mut self, matrix: [u8; KEYBOARD_ROWS]) {
+        self.matrix = matrix;
+    }
+
+    fn clock(&mut self) {
+        self.data_recorder.clock();
+    }
+
+    fn data_recorder(&self) -> Option<&DataRecorder> {
+        Some(&self.data_recorder)
+    }
+
+    fn data_recorder_mut(&mut self) -> Option<&mut DataRecorder> {
+        Some(&mut self.data_recorder)
+    }
+
+    fn reset(&mut self) {
+        self.row = 0;
+        self.column = 0;
+        self.enabled = false;
+    }
+
+    // 磁带内容不保存在存档中
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.row as u8);
+        writer.write_u8(self.column as u8);
+        writer.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.row = (reader.read_u8()? as usize).min(KEYBOARD_ROWS);
+        self.column = (reader.read_u8()? & 0x01) as usize;
+        self.enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
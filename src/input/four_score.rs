@@ -0,0 +1,118 @@
+// 四人适配器，每个端口接两个标准手柄
+// NES Four Score: 每个端口依次移出 1P/2P 的8位、3P/4P 的8位和8位签名，共24位
+// 签名按读取顺序为 $4016: 0,0,0,1,0,0,0,0，$4017: 0,0,1,0,0,0,0,0，24次之后返回1
+// Famicom 扩展口的四人适配器: 3P/4P 直接从 D1 读出，与 1P/2P 同时移位
+// https://www.nesdev.org/wiki/Four_player_adapters
+
+use crate::input::{InputDevice, PortDevice, StandardController};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
+pub struct FourScore {
+    famicom: bool, // Famicom 扩展口的四人适配器
+    controllers: [StandardController; 2], // 本端口的两个手柄，1P/3P 或 2P/4P
+    signature: u8, // 签名，低位先读出
+    count: u8, // strobe 之后已读取的位数
+    strobe: bool,
+}
+
+impl FourScore {
+    // port 为0或1
+    pub fn new(port: usize, famicom: bool) -> Self {
+        FourScore {
+            famicom,
+            controllers: [StandardController::new(), StandardController::new()],
+            signature: if port == 0 { 0x08 } else { 0x04 },
+            count: 0,
+            strobe: false,
+        }
+    }
+
+    fn signature_bit(&self) -> u8 {
+        match self.count {
+            0..=15 => 0,
+            16..=23 => (self.signature >> (self.count - 16)) & 0x01,
+            _ => 0x01,
+        }
+    }
+}
+
+impl InputDevice for FourScore {
+    fn kind(&self) -> PortDevice {
+        if self.famicom {
+            PortDevice::FamicomFourPlayer
+        } else {
+            PortDevice::FourScore
+        }
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.count = 0;
+        }
+        for controller in self.controllers.iter_mut() {
+            controller.write_strobe(strobe);
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if self.famicom {
+            let [first, second] = &mut self.controllers;
+            return (first.read() & 0x01) | ((second.read() & 0x01) << 1);
+        }
+        let data = match self.count {
+            0..=7 => self.controllers[0].read() & 0x01,
+            8..=15 => self.controllers[1].read() & 0x01,
+            _ => self.signature_bit(),
+        };
+        if !self.strobe && self.count < 24 {
+            self.count += 1;
+        }
+        data
+    }
+
+    fn read_debug(&self) -> u8 {
+        if self.famicom {
+            return (self.controllers[0].read_debug() & 0x01) | ((self.controllers[1].read_debug() & 0x01) << 1);
+        }
+        match self.count {
+            0..=7 => self.controllers[0].read_debug() & 0x01,
+            8..=15 => self.controllers[1].read_debug() & 0x01,
+            _ => self.signature_bit(),
+        }
+    }
+
+    fn set_buttons(&mut self, buttons: u8) {
+        self.controllers[0].set_buttons(buttons);
+    }
+
+    fn set_extra_buttons(&mut self, buttons: u8) {
+        self.controllers[1].set_buttons(buttons);
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.strobe = false;
+        for controller in self.controllers.iter_mut() {
+            controller.reset();
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        for controller in &self.controllers {
+            controller.save_state(writer);
+        }
+        writer.write_u8(self.count);
+        writer.write_bool(self.strobe);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        for controller in self.controllers.iter_mut() {
+            controller.load_state(reader)?;
+        }
+        self.count = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+// 接在 $4016/$4017 上的输入设备
+// 写 $4016 第0位 (strobe) 时所有设备锁存当前状态，之后每次读取移出一位
+// Famicom 的扩展口另外接一个设备，收到写 $4016 的 D0~D2，读取时提供 D1~D4
+// https://www.nesdev.org/wiki/Input_devices
+
+pub mod arkanoid;
+pub mod data_recorder;
+pub mod family_keyboard;
+pub mod four_score;
+pub mod power_pad;
+pub mod standard_controller;
+pub mod turbo;
+pub mod zapper;
+
+pub use arkanoid::Arkanoid;
+pub use data_recorder::{DataRecorder, TapeMode};
+pub use family_keyboard::{FamilyKeyboard, KEYBOARD_ROWS};
+pub use four_score::FourScore;
+pub use power_pad::PowerPad;
+pub use standard_controller::StandardController;
+pub use turbo::InputFilter;
+pub use zapper::Zapper;
+
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
+// 可以接到端口上的设备
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortDevice {
+    StandardController,
+    Zapper,
+    Arkanoid,
+    PowerPad,
+    FourScore, // 两个端口都接四人适配器，由 PortConfig 选择
+    FamicomFourPlayer,
+}
+
+impl PortDevice {
+    // 可以单独接到一个端口上的设备
+    pub const ALL: [PortDevice; 4] =
+        [PortDevice::StandardController, PortDevice::Zapper, PortDevice::Arkanoid, PortDevice::PowerPad];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PortDevice::StandardController => "手柄",
+            PortDevice::Zapper => "光枪",
+            PortDevice::Arkanoid => "Arkanoid",
+            PortDevice::PowerPad => "Power Pad",
+            PortDevice::FourScore => "Four Score",
+            PortDevice::FamicomFourPlayer => "四人适配器",
+        }
+    }
+
+    // port 为0或1，四人适配器两个端口的签名不同
+    pub fn create(self, port: usize) -> Box<dyn InputDevice> {
+        match self {
+            PortDevice::StandardController => Box::new(StandardController::new()),
+            PortDevice::Zapper => Box::new(Zapper::new()),
+            PortDevice::Arkanoid => Box::new(Arkanoid::new()),
+            PortDevice::PowerPad => Box::new(PowerPad::new()),
+            PortDevice::FourScore => Box::new(FourScore::new(port, false)),
+            PortDevice::FamicomFourPlayer => Box::new(FourScore::new(port, true)),
+        }
+    }
+}
+
+// 端口的接法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortConfig {
+    TwoPlayers, // 两个端口各接一个设备
+    FourScore, // NES Four Score
+    FamicomFourPlayers, // Famicom 扩展口的四人适配器
+}
+
+impl PortConfig {
+    pub const ALL: [PortConfig; 3] = [PortConfig::TwoPlayers, PortConfig::FourScore, PortConfig::FamicomFourPlayers];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PortConfig::TwoPlayers => "双人",
+            PortConfig::FourScore => "Four Score",
+            PortConfig::FamicomFourPlayers => "Famicom 四人",
+        }
+    }
+
+    // 从 1P 端口上的设备得到接法
+    pub fn from_device(device: PortDevice) -> Self {
+        match device {
+            PortDevice::FourScore => PortConfig::FourScore,
+            PortDevice::FamicomFourPlayer => PortConfig::FamicomFourPlayers,
+            _ => PortConfig::TwoPlayers,
+        }
+    }
+
+    pub fn players(self) -> usize {
+        match self {
+            PortConfig::TwoPlayers => 2,
+            _ => 4,
+        }
+    }
+}
+
+pub trait InputDevice {
+    fn kind(&self) -> PortDevice;
+    // 写入 $4016 的第0位
+    fn write_strobe(&mut self, strobe: bool);
+    // 读取端口，只返回 D0~D4，高位为开路总线由调用者补上
+    fn read(&mut self) -> u8;
+    // 无副作用的读，用于调试
+    fn read_debug(&self) -> u8;
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()>;
+
+    // 前端传入的按键字节，不使用按键的设备忽略
+    fn set_buttons(&mut self, _buttons: u8) {}
+    // 四人适配器上 3P/4P 的按键
+    fn set_extra_buttons(&mut self, _buttons: u8) {}
+    // Power Pad 的12个按键，第 n-1 位为按键 n
+    fn set_mat_buttons(&mut self, _buttons: u16) {}
+    // 前端传入的光标位置 (NES 坐标，屏幕外为负数) 和鼠标按键
+    fn set_pointer(&mut self, _x: i32, _y: i32, _trigger: bool) {}
+    // 需要感光的位置，没有感光元件时为 None
+    fn light_sensor(&self) -> Option<(i32, i32)> {
+        None
+    }
+    fn set_light(&mut self, _light: bool) {}
+    fn reset(&mut self) {}
+}
+
+// Famicom 扩展口上的设备
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expansion {
+    None,
+    Arkanoid,
+    FamilyTrainer,
+    FamilyBasicKeyboard, // 带数据记录器
+}
+
+impl Expansion {
+    pub const ALL: [Expansion; 4] =
+        [Expansion::None, Expansion::Arkanoid, Expansion::FamilyTrainer, Expansion::FamilyBasicKeyboard];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Expansion::None => "无",
+            Expansion::Arkanoid => "Arkanoid",
+            Expansion::FamilyTrainer => "Family Trainer",
+            Expansion::FamilyBasicKeyboard => "Family BASIC 键盘",
+        }
+    }
+
+    pub fn create(self) -> Option<Box<dyn ExpansionDevice>> {
+        match self {
+            Expansion::None => None,
+            Expansion::Arkanoid => Some(Box::new(Arkanoid::new())),
+            Expansion::FamilyTrainer => Some(Box::new(PowerPad::new())),
+            Expansion::FamilyBasicKeyboard => Some(Box::new(FamilyKeyboard::new())),
+        }
+    }
+}
+
+pub trait ExpansionDevice {
+    fn kind(&self) -> Expansion;
+    // 写入 $4016 的 D0~D2
+    fn write(&mut self, data: u8);
+    // 读取 $4016 (port 为0) 或 $4017 (port 为1)，只返回 D1~D4
+    fn read(&mut self, port: usize) -> u8;
+    fn read_debug(&self, port: usize) -> u8;
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()>;
+
+    fn set_pointer(&mut self, _x: i32, _y: i32, _trigger: bool) {}
+    fn set_mat_buttons(&mut self, _buttons: u16) {}
+    // 键盘矩阵，见 family_keyboard.rs
+    fn set_keyboard(&mut self, _matrix: [u8; KEYBOARD_ROWS]) {}
+    // 每个 CPU 周期调用一次
+    fn clock(&mut self) {}
+    fn data_recorder(&self) -> Option<&DataRecorder> {
+        None
+    }
+    fn data_recorder_mut(&mut self) -> Option<&mut DataRecorder> {
+        None
+    }
+    fn reset(&mut self) {}
+}
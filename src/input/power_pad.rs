@@ -0,0 +1,166 @@
+// Power Pad / Family Trainer 垫子，12个按键，编号如下 (B面):
+//  1  2  3  4
+//  5  6  7  8
+//  9 10 11 12
+// NES 版接在 $4017，两个移位寄存器在 strobe 时锁存:
+//   D3 依次为 2, 1, 5, 9, 6, 10, 11, 7，D4 依次为 4, 3, 12, 8，之后都返回1
+// Famicom 版 (Family Trainer) 接在扩展口，写 $4016 的 D0~D2 选择行 (0为选中，D2 对应第一行)，
+//   读 $4017 的 D1~D4 为选中行的4个按键 (0为按下，D4 对应最左边)
+// https://www.nesdev.org/wiki/Power_Pad
+
+use crate::input::{Expansion, ExpansionDevice, InputDevice, PortDevice};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
+// 按键 1~12 对应 buttons 的第 0~11 位
+const D3_ORDER: [usize; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const D4_ORDER: [usize; 4] = [4, 3, 12, 8];
+
+pub struct PowerPad {
+    buttons: u16, // 第 n-1 位为按键 n
+    shift_d3: u8, // 移位寄存器，移出的位补1
+    shift_d4: u8,
+    strobe: bool,
+    rows: u8, // Family Trainer 写入的行选择
+}
+
+impl PowerPad {
+    pub fn new() -> Self {
+        PowerPad {
+            buttons: 0,
+            shift_d3: 0,
+            shift_d4: 0,
+            strobe: false,
+            rows: 0x07,
+        }
+    }
+
+    fn pressed(&self, button: usize) -> bool {
+        self.buttons >> (button - 1) & 0x01 == 0x01
+    }
+
+    fn latch(&mut self) {
+        self.shift_d3 = 0;
+        for (bit, &button) in D3_ORDER.iter().enumerate() {
+            if self.pressed(button) {
+                self.shift_d3 |= 1 << bit;
+            }
+        }
+        self.shift_d4 = 0xF0;
+        for (bit, &button) in D4_ORDER.iter().enumerate() {
+            if self.pressed(button) {
+                self.shift_d4 |= 1 << bit;
+            }
+        }
+    }
+
+    fn peek(&self) -> u8 {
+        ((self.shift_d3 & 0x01) << 3) | ((self.shift_d4 & 0x01) << 4)
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.shift_d3);
+        writer.write_u8(self.shift_d4);
+        writer.write_bool(self.strobe);
+        writer.write_u8(self.rows);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.shift_d3 = reader.read_u8()?;
+        self.shift_d4 = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        self.rows = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn kind(&self) -> PortDevice {
+        PortDevice::PowerPad
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        let data = self.peek();
+        if !self.strobe {
+            self.shift_d3 = (self.shift_d3 >> 1) | 0x80;
+            self.shift_d4 = (self.shift_d4 >> 1) | 0x80;
+        }
+        data
+    }
+
+    fn read_debug(&self) -> u8 {
+        self.peek()
+    }
+
+    fn set_mat_buttons(&mut self, buttons: u16) {
+        self.buttons = buttons;
+    }
+
+    fn reset(&mut self) {
+        self.shift_d3 = 0;
+        self.shift_d4 = 0;
+        self.strobe = false;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        PowerPad::save_state(self, writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        PowerPad::load_state(self, reader)
+    }
+}
+
+impl ExpansionDevice for PowerPad {
+    fn kind(&self) -> Expansion {
+        Expansion::FamilyTrainer
+    }
+
+    fn write(&mut self, data: u8) {
+        self.rows = data & 0x07;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        ExpansionDevice::read_debug(self, port)
+    }
+
+    fn read_debug(&self, port: usize) -> u8 {
+        if port == 0 {
+            return 0;
+        }
+        let mut pressed = 0;
+        for row in 0..3 {
+            if self.rows >> (2 - row) & 0x01 == 0x01 {
+                continue;
+            }
+            for column in 0..4 {
+                if self.pressed(row * 4 + column + 1) {
+                    pressed |= 0x10 >> column;
+                }
+            }
+        }
+        !pressed & 0x1E
+    }
+
+    fn set_mat_buttons(&mut self, buttons: u16) {
+        self.buttons = buttons;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        PowerPad::save_state(self, writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        PowerPad::load_state(self, reader)
+    }
+}
@@ -0,0 +1,86 @@
+// 标准手柄：8位并入串出移位寄存器 (4021)
+// 读取顺序是A, B, Select, Start, Up, Down, Left, Right，8次之后一直返回1
+// https://www.nesdev.org/wiki/Standard_controller
+
+use crate::input::{InputDevice, PortDevice};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
+
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+pub struct StandardController {
+    buttons: u8, // 当前按下的按键
+    shift: u8, // 移位寄存器，移出的位补1
+    strobe: bool, // 为真时寄存器持续重新装入，读取总是返回A
+}
+
+impl StandardController {
+    pub fn new() -> Self {
+        StandardController {
+            buttons: 0,
+            shift: 0,
+            strobe: false,
+        }
+    }
+}
+
+impl InputDevice for StandardController {
+    fn kind(&self) -> PortDevice {
+        PortDevice::StandardController
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+        let data = self.shift & 0x01;
+        self.shift = (self.shift >> 1) | 0x80;
+        data
+    }
+
+    fn read_debug(&self) -> u8 {
+        if self.strobe {
+            self.buttons & 0x01
+        } else {
+            self.shift & 0x01
+        }
+    }
+
+    fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift = buttons;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.shift = 0;
+        self.strobe = false;
+    }
+
+    // 按键来自前端，不保存
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.shift);
+        writer.write_bool(self.strobe);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.shift = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+// 连发和输入宏，位于前端输入和手柄之间，每帧开始时处理一次
+// 处理后的按键才写入录像，所以回放录像时不再经过这里
+// 连发: 每个按键可以设置间隔 n 帧，按住时按下 n 帧、松开 n 帧交替，刚按下的第一帧总是按下
+// 宏: 录制期间逐帧记下前端的按键，播放时逐帧与当前按键合并，再经过连发处理
+
+pub const PLAYERS: usize = 4;
+
+pub struct InputFilter {
+    turbo: [[u8; 8]; PLAYERS], // 每个按键的连发间隔 (帧)，0 为不连发
+    held: [[u32; 8]; PLAYERS], // 每个按键已按住的帧数
+    macro_frames: Vec<[u8; PLAYERS]>,
+    macro_recording: bool,
+    macro_position: Option<usize>, // 正在播放的宏的下一帧
+    macro_buttons: [u8; PLAYERS], // 本帧宏提供的按键
+}
+
+impl InputFilter {
+    pub fn new() -> Self {
+        InputFilter {
+            turbo: [[0; 8]; PLAYERS],
+            held: [[0; 8]; PLAYERS],
+            macro_frames: Vec::new(),
+            macro_recording: false,
+            macro_position: None,
+            macro_buttons: [0; PLAYERS],
+        }
+    }
+
+    // 超出范围的 player/button 读出0，设置时被忽略
+    pub fn turbo(&self, player: usize, button: usize) -> u8 {
+        self.turbo.get(player).and_then(|rates| rates.get(button)).copied().unwrap_or(0)
+    }
+
+    pub fn set_turbo(&mut self, player: usize, button: usize, rate: u8) {
+        if let Some(turbo) = self.turbo.get_mut(player).and_then(|rates| rates.get_mut(button)) {
+            *turbo = rate;
+        }
+    }
+
+    // 开始录制宏，之前的宏被覆盖
+    pub fn start_macro_recording(&mut self) {
+        self.macro_frames.clear();
+        self.macro_position = None;
+        self.macro_recording = true;
+    }
+
+    pub fn stop_macro_recording(&mut self) {
+        self.macro_recording = false;
+    }
+
+    pub fn is_macro_recording(&self) -> bool {
+        self.macro_recording
+    }
+
+    // 从头播放宏，录制中时先停止录制
+    pub fn play_macro(&mut self) {
+        self.macro_recording = false;
+        if !self.macro_frames.is_empty() {
+            self.macro_position = Some(0);
+        }
+    }
+
+    pub fn is_macro_playing(&self) -> bool {
+        self.macro_position.is_some()
+    }
+
+    pub fn macro_frames(&self) -> &[[u8; PLAYERS]] {
+        &self.macro_frames
+    }
+
+    pub fn set_macro_frames(&mut self, frames: Vec<[u8; PLAYERS]>) {
+        self.macro_frames = frames;
+        self.macro_recording = false;
+        self.macro_position = None;
+    }
+
+    // 新的一帧开始，input 为前端的按键，返回送给手柄的按键
+    pub fn next_frame(&mut self, input: [u8; PLAYERS]) -> [u8; PLAYERS] {
+        if self.macro_recording {
+            self.macro_frames.push(input);
+        }
+        self.macro_buttons = [0; PLAYERS];
+        if let Some(position) = self.macro_position {
+            if let Some(frame) = self.macro_frames.get(position) {
+                self.macro_buttons = *frame;
+            }
+            self.macro_position = Some(position + 1).filter(|position| *position < self.macro_frames.len());
+        }
+        for player in 0..PLAYERS {
+            let buttons = input[player] | self.macro_buttons[player];
+            for button in 0..8 {
+                if buttons >> button & 0x01 == 0x01 {
+                    self.held[player][button] += 1;
+                } else {
+                    self.held[player][button] = 0;
+                }
+            }
+        }
+        self.current(input)
+    }
+
+    // 帧中间前端改变按键时，按当前的连发相位计算，不前进
+    pub fn current(&self, input: [u8; PLAYERS]) -> [u8; PLAYERS] {
+        let mut output = [0; PLAYERS];
+        for player in 0..PLAYERS {
+            let buttons = input[player] | self.macro_buttons[player];
+            for button in 0..8 {
+                if buttons >> button & 0x01 == 0 {
+                    continue;
+                }
+                let rate = self.turbo[player][button] as u32;
+                let held = self.held[player][button].saturating_sub(1);
+                if rate == 0 || (held / rate) % 2 == 0 {
+                    output[player] |= 1 << button;
+                }
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,99 @@
+// 光枪 (Zapper)
+// D3: 感光，0 表示看到亮光；D4: 扳机，1 表示扣下
+// 感光元件只对电子束刚扫过的亮像素有反应，大约持续20多条扫描线
+// https://www.nesdev.org/wiki/Zapper
+
+use crate::input::{InputDevice, PortDevice};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::utils::Palettes;
+use crate::NesResult;
+
+// 光标周围检查的像素半径
+const SENSE_RADIUS: i32 = 3;
+// 电子束扫过后还能感到光的扫描线数
+const SENSE_SCANLINES: i32 = 20;
+// 亮度 (0~255) 达到这个值才算亮光
+const BRIGHTNESS_THRESHOLD: u32 = 85;
+
+pub struct Zapper {
+    x: i32, // 光标位置，屏幕外为负数
+    y: i32,
+    trigger: bool,
+    light: bool,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper {
+            x: -1,
+            y: -1,
+            trigger: false,
+            light: false,
+        }
+    }
+}
+
+impl InputDevice for Zapper {
+    fn kind(&self) -> PortDevice {
+        PortDevice::Zapper
+    }
+
+    // 光枪没有移位寄存器，每次读取都是当前状态
+    fn write_strobe(&mut self, _strobe: bool) {}
+
+    fn read(&mut self) -> u8 {
+        self.read_debug()
+    }
+
+    fn read_debug(&self) -> u8 {
+        let light = if self.light { 0 } else { 0x08 };
+        let trigger = if self.trigger { 0x10 } else { 0 };
+        light | trigger
+    }
+
+    fn set_pointer(&mut self, x: i32, y: i32, trigger: bool) {
+        self.x = x;
+        self.y = y;
+        self.trigger = trigger;
+    }
+
+    fn light_sensor(&self) -> Option<(i32, i32)> {
+        if (0..256).contains(&self.x) && (0..240).contains(&self.y) {
+            Some((self.x, self.y))
+        } else {
+            None
+        }
+    }
+
+    fn set_light(&mut self, light: bool) {
+        self.light = light;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.light);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.light = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+// 电子束在 scanline 时，(x, y) 处的光枪能否看到亮光
+// frame 为 PPU 输出的颜色索引，只检查电子束已经扫过的行 (scanline 之前的行)
+pub fn detect_light(frame: &[u8], palettes: &Palettes, x: i32, y: i32, scanline: i32) -> bool {
+    if scanline < y || scanline - y > SENSE_SCANLINES {
+        return false;
+    }
+    let last_row = (y + SENSE_RADIUS).min(scanline - 1).min(239);
+    for row in (y - SENSE_RADIUS).max(0)..=last_row {
+        for column in (x - SENSE_RADIUS).max(0)..=(x + SENSE_RADIUS).min(255) {
+            let color = palettes.colors[(frame[(row * 256 + column) as usize] & 0x3F) as usize];
+            let brightness = (color[0] as u32 * 299 + color[1] as u32 * 587 + color[2] as u32 * 114) / 1000;
+            if brightness >= BRIGHTNESS_THRESHOLD {
+                return true;
+            }
+        }
+    }
+    false
+}
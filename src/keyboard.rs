@@ -0,0 +1,201 @@
+// 主机键盘到 Family BASIC 键盘矩阵的映射
+// 字母、数字和功能键按按下状态映射；egui 没有对应按键的符号 (; : @ [ ] , . / 等)
+// 从输入的字符得到，每个字符按下几帧后松开
+// 接了 Family BASIC 键盘时整个键盘都给模拟器使用，快捷键和手柄的键盘映射暂停
+
+use std::collections::VecDeque;
+
+use egui::Key;
+
+use crate::input::KEYBOARD_ROWS;
+
+// 键在矩阵中的位置: (行, 列, $4017 的位 1~4)
+type KeyPosition = (usize, usize, u8);
+
+const CTR: KeyPosition = (7, 0, 4);
+const GRPH: KeyPosition = (7, 1, 2);
+const LSHIFT: KeyPosition = (7, 1, 1);
+
+// 字符键按下和松开的帧数，Family BASIC 每帧扫描一次键盘
+const TEXT_PRESS_FRAMES: u8 = 3;
+const TEXT_RELEASE_FRAMES: u8 = 2;
+
+const KEY_MAP: &[(Key, KeyPosition)] = &[
+    (Key::Enter, (0, 0, 2)),
+    (Key::F8, (0, 0, 1)),
+    (Key::End, (0, 1, 4)), // STOP
+    (Key::PageDown, (0, 1, 1)), // カナ
+    (Key::F7, (1, 0, 1)),
+    (Key::Minus, (1, 1, 3)),
+    (Key::K, (2, 0, 4)),
+    (Key::L, (2, 0, 3)),
+    (Key::O, (2, 0, 2)),
+    (Key::F6, (2, 0, 1)),
+    (Key::Num0, (2, 1, 4)),
+    (Key::P, (2, 1, 3)),
+    (Key::J, (3, 0, 4)),
+    (Key::U, (3, 0, 3)),
+    (Key::I, (3, 0, 2)),
+    (Key::F5, (3, 0, 1)),
+    (Key::Num8, (3, 1, 4)),
+    (Key::Num9, (3, 1, 3)),
+    (Key::N, (3, 1, 2)),
+    (Key::M, (3, 1, 1)),
+    (Key::H, (4, 0, 4)),
+    (Key::G, (4, 0, 3)),
+    (Key::Y, (4, 0, 2)),
+    (Key::F4, (4, 0, 1)),
+    (Key::Num6, (4, 1, 4)),
+    (Key::Num7, (4, 1, 3)),
+    (Key::V, (4, 1, 2)),
+    (Key::B, (4, 1, 1)),
+    (Key::D, (5, 0, 4)),
+    (Key::R, (5, 0, 3)),
+    (Key::T, (5, 0, 2)),
+    (Key::F3, (5, 0, 1)),
+    (Key::Num4, (5, 1, 4)),
+    (Key::Num5, (5, 1, 3)),
+    (Key::C, (5, 1, 2)),
+    (Key::F, (5, 1, 1)),
+    (Key::A, (6, 0, 4)),
+    (Key::S, (6, 0, 3)),
+    (Key::W, (6, 0, 2)),
+    (Key::F2, (6, 0, 1)),
+    (Key::Num3, (6, 1, 4)),
+    (Key::E, (6, 1, 3)),
+    (Key::Z, (6, 1, 2)),
+    (Key::X, (6, 1, 1)),
+    (Key::Q, (7, 0, 3)),
+    (Key::Escape, (7, 0, 2)),
+    (Key::F1, (7, 0, 1)),
+    (Key::Num2, (7, 1, 4)),
+    (Key::Num1, (7, 1, 3)),
+    (Key::ArrowLeft, (8, 0, 4)),
+    (Key::ArrowRight, (8, 0, 3)),
+    (Key::ArrowUp, (8, 0, 2)),
+    (Key::Home, (8, 0, 1)), // CLR HOME
+    (Key::Insert, (8, 1, 4)),
+    (Key::Delete, (8, 1, 3)),
+    (Key::Backspace, (8, 1, 3)),
+    (Key::Space, (8, 1, 2)),
+    (Key::ArrowDown, (8, 1, 1)),
+];
+
+// 字符对应的键和是否需要 SHIFT，按 Family BASIC 键盘上印的符号
+fn text_key(c: char) -> Option<(KeyPosition, bool)> {
+    let key = match c {
+        ']' => ((0, 0, 4), false),
+        '[' => ((0, 0, 3), false),
+        '¥' | '\\' => ((0, 1, 3), false),
+        ';' => ((1, 0, 4), false),
+        '+' => ((1, 0, 4), true),
+        ':' => ((1, 0, 3), false),
+        '*' => ((1, 0, 3), true),
+        '@' => ((1, 0, 2), false),
+        '^' => ((1, 1, 4), false),
+        '=' => ((1, 1, 3), true),
+        '/' => ((1, 1, 2), false),
+        '?' => ((1, 1, 2), true),
+        '_' => ((1, 1, 1), false),
+        ',' => ((2, 1, 2), false),
+        '<' => ((2, 1, 2), true),
+        '.' => ((2, 1, 1), false),
+        '>' => ((2, 1, 1), true),
+        '!' => ((7, 1, 3), true),
+        '"' => ((7, 1, 4), true),
+        '#' => ((6, 1, 4), true),
+        '$' => ((5, 1, 4), true),
+        '%' => ((5, 1, 3), true),
+        '&' => ((4, 1, 4), true),
+        '\'' => ((4, 1, 3), true),
+        '(' => ((3, 1, 4), true),
+        ')' => ((3, 1, 3), true),
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn press(matrix: &mut [u8; KEYBOARD_ROWS], (row, column, bit): KeyPosition) {
+    matrix[row] |= 1 << (column * 4 + bit as usize - 1);
+}
+
+pub struct FamilyKeyboardInput {
+    typed: VecDeque<(KeyPosition, bool)>, // 还没有按下的字符键
+    current: Option<((KeyPosition, bool), u8)>, // 正在按下的字符键和剩余帧数
+    held: [u8; KEYBOARD_ROWS], // 按下状态映射的键
+    suppressed: Vec<Key>, // 输入了字符的键，松开之前不再按下状态映射，避免同一个字符输入两次
+}
+
+impl FamilyKeyboardInput {
+    pub fn new() -> Self {
+        FamilyKeyboardInput {
+            typed: VecDeque::new(),
+            current: None,
+            held: [0; KEYBOARD_ROWS],
+            suppressed: Vec::new(),
+        }
+    }
+
+    // 每次界面刷新调用，收集输入的字符和按下的键
+    pub fn take_input(&mut self, ctx: &egui::Context) {
+        let (keys, modifiers, pressed, text) = ctx.input(|i| {
+            let mut pressed = Vec::new();
+            let mut text = String::new();
+            for event in &i.events {
+                match event {
+                    egui::Event::Key { key, pressed: true, repeat: false, .. } => pressed.push(*key),
+                    egui::Event::Text(t) => text.push_str(t),
+                    _ => {}
+                }
+            }
+            (i.keys_down.clone(), i.modifiers, pressed, text)
+        });
+        for (position, shift) in text.chars().filter_map(text_key) {
+            // 和字符一起按下的键 (如 SHIFT+1 输入的 !) 由字符键处理
+            for (key, key_position) in KEY_MAP {
+                if (pressed.contains(key) || *key_position == position) && !self.suppressed.contains(key) {
+                    self.suppressed.push(*key);
+                }
+            }
+            self.typed.push_back((position, shift));
+        }
+        self.suppressed.retain(|key| keys.contains(key));
+
+        let mut matrix = [0; KEYBOARD_ROWS];
+        for (key, position) in KEY_MAP {
+            if keys.contains(key) && !self.suppressed.contains(key) {
+                press(&mut matrix, *position);
+            }
+        }
+        if modifiers.shift {
+            press(&mut matrix, LSHIFT);
+        }
+        if modifiers.ctrl {
+            press(&mut matrix, CTR);
+        }
+        if modifiers.alt {
+            press(&mut matrix, GRPH);
+        }
+        self.held = matrix;
+    }
+
+    // 每个模拟帧调用一次，返回键盘矩阵
+    pub fn next_frame(&mut self) -> [u8; KEYBOARD_ROWS] {
+        if self.current.map_or(true, |(_, frames)| frames == 0) {
+            self.current = self.typed.pop_front().map(|key| (key, TEXT_PRESS_FRAMES + TEXT_RELEASE_FRAMES));
+        }
+        if let Some(((position, shift), frames)) = &mut self.current {
+            *frames -= 1;
+            // 字符键按下期间只按这个键，SHIFT 按字符决定
+            let mut matrix = [0; KEYBOARD_ROWS];
+            if *frames >= TEXT_RELEASE_FRAMES {
+                press(&mut matrix, *position);
+                if *shift {
+                    press(&mut matrix, LSHIFT);
+                }
+            }
+            return matrix;
+        }
+        self.held
+    }
+}
@@ -5,7 +5,11 @@ mod mapper;
 mod ppu;
 mod utils;
 mod window;
+mod bindings;
+mod gamepad;
+mod keyboard;
 mod bus;
+mod input;
 
 pub mod emulator;
 
@@ -30,9 +34,66 @@ pub enum NesError {
     // 在此处定义库中可能遇到的各种错误
     IoError(std::io::Error),
     InvalidRom,
+    InvalidMagic, // 文件头不是 "NES\x1A"
+    TruncatedHeader(usize), // 文件不足16字节的头，参数为实际长度
+    TruncatedTrainer { expected: usize, actual: usize }, // trainer 超出文件末尾
+    TruncatedPrgRom { expected: usize, actual: usize },
+    TruncatedChrRom { expected: usize, actual: usize },
+    UnsupportedMapper { mapper: u16, submapper: u8 },
+    InvalidGameDb(String), // 游戏数据库格式错误
+    InvalidUnif(String), // UNIF 文件的块结构错误
+    UnsupportedBoard(String), // UNIF 电路板名无法对应到已实现的 mapper
+    InvalidPatch(String), // IPS/UPS/BPS 补丁格式错误
+    PatchChecksumMismatch { name: &'static str, expected: u32, actual: u32 }, // UPS/BPS 的 CRC32 校验失败
+    ArchiveError(String), // zip/gzip 解压失败
+    NoRomInArchive(String), // 压缩包中没有可加载的 ROM
+    MissingFdsBios, // 没有找到 FDS BIOS (disksys.rom)
+    InvalidFds(String), // FDS 磁盘镜像或 BIOS 无效
+    InvalidState(String), // 即时存档格式错误或与当前游戏不符
+    InvalidMovie(String), // 录像文件格式错误或与当前游戏不符
+    InvalidWav(String), // 磁带的 WAV 文件格式错误或不支持
     // ...
 }
 
+impl std::fmt::Display for NesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NesError::IoError(error) => write!(f, "读取文件失败: {}", error),
+            NesError::InvalidRom => write!(f, "无效的 ROM 文件"),
+            NesError::InvalidMagic => write!(f, "不是 iNES/NES 2.0 文件（文件头应为 NES\\x1A）"),
+            NesError::TruncatedHeader(actual) => write!(f, "文件头不完整: 需要16字节, 实际{}字节", actual),
+            NesError::TruncatedTrainer { expected, actual } => {
+                write!(f, "trainer 超出文件末尾: 需要{}字节, 剩余{}字节", expected, actual)
+            }
+            NesError::TruncatedPrgRom { expected, actual } => {
+                write!(f, "PRG-ROM 不完整: 需要{}字节, 剩余{}字节", expected, actual)
+            }
+            NesError::TruncatedChrRom { expected, actual } => {
+                write!(f, "CHR-ROM 不完整: 需要{}字节, 剩余{}字节", expected, actual)
+            }
+            NesError::UnsupportedMapper { mapper, submapper } => {
+                write!(f, "暂不支持的 mapper: {} (submapper {})", mapper, submapper)
+            }
+            NesError::InvalidGameDb(message) => write!(f, "游戏数据库格式错误: {}", message),
+            NesError::InvalidUnif(message) => write!(f, "UNIF 文件格式错误: {}", message),
+            NesError::UnsupportedBoard(board) => write!(f, "暂不支持的电路板: {}", board),
+            NesError::InvalidPatch(message) => write!(f, "补丁格式错误: {}", message),
+            NesError::PatchChecksumMismatch { name, expected, actual } => {
+                write!(f, "{} CRC32 校验失败: 需要{:08X}, 实际{:08X}", name, expected, actual)
+            }
+            NesError::ArchiveError(message) => write!(f, "解压失败: {}", message),
+            NesError::NoRomInArchive(path) => write!(f, "压缩包中没有 ROM 文件: {}", path),
+            NesError::MissingFdsBios => write!(f, "需要 FDS BIOS (disksys.rom)"),
+            NesError::InvalidFds(message) => write!(f, "无效的 FDS 镜像: {}", message),
+            NesError::InvalidState(message) => write!(f, "无法读取存档: {}", message),
+            NesError::InvalidMovie(message) => write!(f, "无法读取录像: {}", message),
+            NesError::InvalidWav(message) => write!(f, "无法读取 WAV 文件: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for NesError {}
+
 impl From<std::io::Error> for NesError {
     fn from(error: std::io::Error) -> Self {
         NesError::IoError(error)
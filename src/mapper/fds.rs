@@ -0,0 +1,600 @@
+// Famicom Disk System：RAM 适配器 + 磁盘驱动器
+// $6000-$DFFF 为32KB PRG-RAM，$E000-$FFFF 为8KB BIOS，PPU 使用8KB CHR-RAM
+// 磁盘以每字节约150个 CPU 周期的速度顺序读写，每传输一个字节可以产生 IRQ
+// https://www.nesdev.org/wiki/Family_Computer_Disk_System
+// https://www.nesdev.org/wiki/FDS_file_format
+
+use super::fds_audio::FdsAudio;
+use super::{Mapper, RomHeader};
+use crate::utils::patch::{apply_patch, create_ips};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::{NesError, NesResult};
+
+pub const FDS_SIDE_SIZE: usize = 65500;
+pub const FDS_BIOS_SIZE: usize = 0x2000;
+// fwNES 格式的16字节文件头
+const FDS_HEADER_SIZE: usize = 16;
+// 每面开头的28300位间隙，以及每个块之后的976位间隙
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+// 磁头回到起点后，约50000个周期才开始传输
+const REWIND_DELAY: u32 = 50000;
+const BYTE_DELAY: u32 = 150;
+// 换面时保持弹出状态的周期数（约半秒），让 BIOS 能检测到磁盘被取出
+const SWAP_DELAY: u32 = 900_000;
+
+// .fds 文件可能带 fwNES 头，也可能直接以第一面的磁盘信息块开始
+pub fn is_fds_image(data: &[u8]) -> bool {
+    data.starts_with(b"FDS\x1A") || data.get(1..15) == Some(b"*NINTENDO-HVC*")
+}
+
+pub fn fds_rom_header(side_count: usize) -> RomHeader {
+    RomHeader {
+        prg_rom_size: FDS_BIOS_SIZE,
+        mapper_number: 20, // NES 2.0 为 FDS 保留的 mapper 号
+        prg_ram_size: 32 * 1024,
+        chr_ram_size: 8 * 1024,
+        misc_rom_count: side_count as u8,
+        ..Default::default()
+    }
+}
+
+// 块的长度，文件数据块的长度由前一个文件头块给出
+fn block_length(block_type: u8, file_size: usize) -> Option<usize> {
+    match block_type {
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+// 把文件中的一面转换为驱动器看到的数据：块之间插入间隙、$80 起始标记和 CRC
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut data = vec![0; LEADING_GAP];
+    let mut position = 0;
+    let mut file_size = 0;
+    while position < side.len() {
+        let block_type = side[position];
+        let Some(length) = block_length(block_type, file_size) else {
+            break;
+        };
+        if block_type == 3 && position + 14 < side.len() {
+            file_size = side[position + 13] as usize | (side[position + 14] as usize) << 8;
+        }
+        let end = (position + length).min(side.len());
+        data.push(0x80);
+        data.extend_from_slice(&side[position..end]);
+        // CRC 不做校验，写入固定值
+        data.extend_from_slice(&[0x4D, 0x62]);
+        data.extend(std::iter::repeat(0).take(BLOCK_GAP));
+        position = end;
+    }
+    // 留出空间给游戏追加的新文件
+    let capacity = FDS_SIDE_SIZE + LEADING_GAP + 8 * BLOCK_GAP;
+    if data.len() < capacity {
+        data.resize(capacity, 0);
+    }
+    data
+}
+
+// add_gaps 的逆过程，从驱动器数据中取出各个块，用于保存写入后的磁盘
+fn remove_gaps(data: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(FDS_SIDE_SIZE);
+    let mut position = 0;
+    let mut file_size = 0;
+    let mut in_gap = true;
+    while position < data.len() {
+        if in_gap {
+            in_gap = data[position] != 0x80;
+            position += 1;
+            continue;
+        }
+        let block_type = data[position];
+        let Some(length) = block_length(block_type, file_size) else {
+            break;
+        };
+        if block_type == 3 && position + 14 < data.len() {
+            file_size = data[position + 13] as usize | (data[position + 14] as usize) << 8;
+        }
+        let end = (position + length).min(data.len());
+        side.extend_from_slice(&data[position..end]);
+        // 跳过 CRC
+        position = end + 2;
+        in_gap = true;
+    }
+    side.resize(FDS_SIDE_SIZE, 0);
+    side
+}
+
+pub struct FdsMapper {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    original: Vec<u8>, // 未应用存档差异的原始文件，用于生成差异
+    header: Vec<u8>,
+    sides: Vec<Vec<u8>>, // 带间隙的各面数据
+    disk_written: bool,
+    mirror_mode: u8,
+
+    // 磁盘驱动器
+    inserted_side: Option<usize>,
+    pending_side: Option<(usize, u32)>, // 换面时等待插入的面和剩余周期
+    disk_position: usize,
+    delay: u32,
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    previous_crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    end_of_head: bool,
+    scanning_disk: bool,
+    gap_ended: bool,
+    transfer_complete: bool,
+    read_data: u8,
+    write_data: u8,
+    ext_connector: u8,
+    disk_irq: bool,
+
+    // 计时器 IRQ
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_irq_enabled: bool,
+    timer_irq_repeat: bool,
+    timer_irq: bool,
+
+    audio: FdsAudio,
+}
+
+impl FdsMapper {
+    // save 为之前保存的磁盘写入差异（IPS 格式）
+    pub fn new(image: Vec<u8>, bios: Vec<u8>, save: Option<&[u8]>) -> NesResult<Self> {
+        if bios.len() != FDS_BIOS_SIZE {
+            return Err(NesError::InvalidFds(format!("BIOS 应为{}字节, 实际{}字节", FDS_BIOS_SIZE, bios.len())));
+        }
+        let disk = match save {
+            Some(save) => apply_patch(&image, save)?,
+            None => image.clone(),
+        };
+        let header_size = if disk.starts_with(b"FDS\x1A") { FDS_HEADER_SIZE } else { 0 };
+        let header = disk[..header_size].to_vec();
+        let sides: Vec<Vec<u8>> = disk[header_size..]
+            .chunks(FDS_SIDE_SIZE)
+            .filter(|side| side.first() == Some(&1))
+            .map(add_gaps)
+            .collect();
+        if sides.is_empty() {
+            return Err(NesError::InvalidFds("没有找到磁盘面".to_string()));
+        }
+        Ok(FdsMapper {
+            bios,
+            prg_ram: vec![0; 32 * 1024],
+            chr_ram: vec![0; 8 * 1024],
+            original: image,
+            header,
+            sides,
+            disk_written: false,
+            mirror_mode: 1,
+            inserted_side: Some(0),
+            pending_side: None,
+            disk_position: 0,
+            delay: 0,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            previous_crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            end_of_head: true,
+            scanning_disk: false,
+            gap_ended: false,
+            transfer_complete: false,
+            read_data: 0,
+            write_data: 0,
+            ext_connector: 0,
+            disk_irq: false,
+            disk_registers_enabled: true,
+            sound_registers_enabled: true,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_irq_enabled: false,
+            timer_irq_repeat: false,
+            timer_irq: false,
+            audio: FdsAudio::new(),
+        })
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    // 当前磁盘内容对应的 .fds 文件
+    fn disk_image(&self) -> Vec<u8> {
+        let mut image = self.header.clone();
+        for side in &self.sides {
+            image.extend(remove_gaps(side));
+        }
+        image
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_irq_enabled {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_counter = self.timer_reload;
+            self.timer_irq = true;
+            if !self.timer_irq_repeat {
+                self.timer_irq_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn clock_disk(&mut self) {
+        if let Some((side, delay)) = self.pending_side {
+            if delay == 0 {
+                self.inserted_side = Some(side);
+                self.pending_side = None;
+            } else {
+                self.pending_side = Some((side, delay - 1));
+            }
+        }
+
+        let Some(side) = self.inserted_side else {
+            self.end_of_head = true;
+            self.scanning_disk = false;
+            return;
+        };
+        if !self.motor_on {
+            self.end_of_head = true;
+            self.scanning_disk = false;
+            return;
+        }
+        if self.reset_transfer && !self.scanning_disk {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = REWIND_DELAY;
+            self.end_of_head = false;
+            self.disk_position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning_disk = true;
+        let mut need_irq = self.disk_irq_enabled;
+        if self.read_mode {
+            let data = self.sides[side][self.disk_position];
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // 读到 $80 起始标记，间隙结束，标记本身不产生 IRQ
+                self.gap_ended = true;
+                need_irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+        } else {
+            if !self.crc_control {
+                self.transfer_complete = true;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+            // CRC 阶段和未就绪时写入0
+            let data = if !self.previous_crc_control && self.disk_ready { self.write_data } else { 0 };
+            self.sides[side][self.disk_position] = data;
+            self.disk_written = true;
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.disk_position += 1;
+        if self.disk_position >= self.sides[side].len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+}
+
+impl Mapper for FdsMapper {
+    fn read_prg_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xDFFF => self.prg_ram[(addr - 0x6000) as usize],
+            0xE000..=0xFFFF => self.bios[(addr - 0xE000) as usize],
+            _ => 0,
+        }
+    }
+
+    fn read_prg_ram(&self, addr: u16) -> u8 {
+        self.prg_ram[(addr as usize).wrapping_sub(0x6000) % self.prg_ram.len()]
+    }
+
+    fn write_prg_rom(&mut self, addr: u16, data: u8) {
+        // $8000-$DFFF 仍是 RAM，BIOS 不可写
+        if let 0x8000..=0xDFFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+        }
+    }
+
+    fn write_prg_ram(&mut self, addr: u16, data: u8) {
+        let len = self.prg_ram.len();
+        self.prg_ram[(addr as usize).wrapping_sub(0x6000) % len] = data;
+    }
+
+    fn read_chr_rom(&self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize % self.chr_ram.len()]
+    }
+
+    fn write_chr_rom(&mut self, addr: u16, data: u8) {
+        let len = self.chr_ram.len();
+        self.chr_ram[addr as usize % len] = data;
+    }
+
+    fn ppu_mirror_mode(&self) -> u8 {
+        self.mirror_mode
+    }
+
+    fn reset(&mut self) {
+        self.motor_on = false;
+        self.end_of_head = true;
+        self.scanning_disk = false;
+        self.disk_irq = false;
+        self.timer_irq = false;
+        self.timer_irq_enabled = false;
+        self.disk_registers_enabled = true;
+        self.sound_registers_enabled = true;
+        self.audio = FdsAudio::new();
+    }
+
+    // 磁盘内容也保存，读档后与存档时的磁盘一致
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        writer.write_bytes(&self.chr_ram);
+        writer.write_u8(self.sides.len() as u8);
+        for side in &self.sides {
+            writer.write_bytes(side);
+        }
+        writer.write_bool(self.disk_written);
+        writer.write_u8(self.mirror_mode);
+
+        writer.write_u8(self.inserted_side.map_or(0xFF, |side| side as u8));
+        writer.write_u8(self.pending_side.map_or(0xFF, |(side, _)| side as u8));
+        writer.write_u32(self.pending_side.map_or(0, |(_, delay)| delay));
+        writer.write_u32(self.disk_position as u32);
+        writer.write_u32(self.delay);
+        for flag in [
+            self.motor_on,
+            self.reset_transfer,
+            self.read_mode,
+            self.crc_control,
+            self.previous_crc_control,
+            self.disk_ready,
+            self.disk_irq_enabled,
+            self.end_of_head,
+            self.scanning_disk,
+            self.gap_ended,
+            self.transfer_complete,
+            self.disk_irq,
+        ] {
+            writer.write_bool(flag);
+        }
+        writer.write_u8(self.read_data);
+        writer.write_u8(self.write_data);
+        writer.write_u8(self.ext_connector);
+
+        writer.write_bool(self.disk_registers_enabled);
+        writer.write_bool(self.sound_registers_enabled);
+        writer.write_u16(self.timer_reload);
+        writer.write_u16(self.timer_counter);
+        writer.write_bool(self.timer_irq_enabled);
+        writer.write_bool(self.timer_irq_repeat);
+        writer.write_bool(self.timer_irq);
+
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.prg_ram)?;
+        reader.read_into(&mut self.chr_ram)?;
+        if reader.read_u8()? as usize != self.sides.len() {
+            return Err(NesError::InvalidState("磁盘面数与当前镜像不一致".to_string()));
+        }
+        for side in self.sides.iter_mut() {
+            reader.read_into(side)?;
+        }
+        self.disk_written = reader.read_bool()?;
+        self.mirror_mode = reader.read_u8()?;
+
+        let side_count = self.sides.len();
+        let read_side = |value: u8| Some(value as usize).filter(|&side| side < side_count);
+        self.inserted_side = read_side(reader.read_u8()?);
+        let pending_side = read_side(reader.read_u8()?);
+        let pending_delay = reader.read_u32()?;
+        self.pending_side = pending_side.map(|side| (side, pending_delay));
+        self.disk_position = reader.read_u32()? as usize;
+        self.delay = reader.read_u32()?;
+        for flag in [
+            &mut self.motor_on,
+            &mut self.reset_transfer,
+            &mut self.read_mode,
+            &mut self.crc_control,
+            &mut self.previous_crc_control,
+            &mut self.disk_ready,
+            &mut self.disk_irq_enabled,
+            &mut self.end_of_head,
+            &mut self.scanning_disk,
+            &mut self.gap_ended,
+            &mut self.transfer_complete,
+            &mut self.disk_irq,
+        ] {
+            *flag = reader.read_bool()?;
+        }
+        // 读完最后一个字节后马达停止，位置停在磁盘末尾，重新转动时先回到起点
+        // 没有插入磁盘时位置也会在插入后回到起点，只检查插入的这一面
+        if let Some(side) = self.inserted_side {
+            let length = self.sides[side].len();
+            let rewinding = !self.motor_on || self.end_of_head;
+            if self.disk_position > length || (self.disk_position == length && !rewinding) {
+                return Err(NesError::InvalidState(format!("磁盘位置{}超出磁盘范围", self.disk_position)));
+            }
+        }
+        self.read_data = reader.read_u8()?;
+        self.write_data = reader.read_u8()?;
+        self.ext_connector = reader.read_u8()?;
+
+        self.disk_registers_enabled = reader.read_bool()?;
+        self.sound_registers_enabled = reader.read_bool()?;
+        self.timer_reload = reader.read_u16()?;
+        self.timer_counter = reader.read_u16()?;
+        self.timer_irq_enabled = reader.read_bool()?;
+        self.timer_irq_repeat = reader.read_bool()?;
+        self.timer_irq = reader.read_bool()?;
+
+        self.audio.load_state(reader)
+    }
+
+    fn read_expansion(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4030 if self.disk_registers_enabled => {
+                let mut status = 0;
+                status |= if self.timer_irq { 0x01 } else { 0 };
+                status |= if self.transfer_complete { 0x02 } else { 0 };
+                status |= if self.end_of_head { 0x40 } else { 0 };
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+                status
+            }
+            0x4031 if self.disk_registers_enabled => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                self.read_data
+            }
+            0x4032 if self.disk_registers_enabled => {
+                // 第0位: 没有磁盘 第1位: 未就绪 第2位: 写保护
+                let no_disk = self.inserted_side.is_none();
+                let mut status = 0x40;
+                status |= if no_disk { 0x01 } else { 0 };
+                status |= if no_disk || !self.scanning_disk { 0x02 } else { 0 };
+                status |= if no_disk { 0x04 } else { 0 };
+                status
+            }
+            // 电池状态，第7位为1表示电量正常
+            0x4033 if self.disk_registers_enabled => 0x80,
+            0x4040..=0x4092 if self.sound_registers_enabled => self.audio.read(addr),
+            _ => 0,
+        }
+    }
+
+    fn write_expansion(&mut self, addr: u16, data: u8) {
+        if addr == 0x4023 {
+            self.disk_registers_enabled = data & 0x01 != 0;
+            self.sound_registers_enabled = data & 0x02 != 0;
+            if !self.disk_registers_enabled {
+                self.timer_irq_enabled = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+            }
+            return;
+        }
+        match addr {
+            0x4020 if self.disk_registers_enabled => {
+                self.timer_reload = (self.timer_reload & 0xFF00) | data as u16;
+            }
+            0x4021 if self.disk_registers_enabled => {
+                self.timer_reload = (self.timer_reload & 0x00FF) | (data as u16) << 8;
+            }
+            0x4022 if self.disk_registers_enabled => {
+                self.timer_irq_repeat = data & 0x01 != 0;
+                self.timer_irq_enabled = data & 0x02 != 0;
+                if self.timer_irq_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled => {
+                self.motor_on = data & 0x01 != 0;
+                self.reset_transfer = data & 0x02 != 0;
+                self.read_mode = data & 0x04 != 0;
+                // 0: 垂直镜像 1: 水平镜像，与 iNES 头的约定相反
+                self.mirror_mode = if data & 0x08 != 0 { 0 } else { 1 };
+                self.crc_control = data & 0x10 != 0;
+                self.disk_ready = data & 0x40 != 0;
+                self.disk_irq_enabled = data & 0x80 != 0;
+                self.disk_irq = false;
+            }
+            0x4026 if self.disk_registers_enabled => self.ext_connector = data,
+            0x4040..=0x4092 if self.sound_registers_enabled => self.audio.write(addr, data),
+            _ => {}
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.audio.clock();
+        self.clock_timer();
+        self.clock_disk();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn disk_side_count(&self) -> usize {
+        self.side_count()
+    }
+
+    fn inserted_disk_side(&self) -> Option<usize> {
+        self.inserted_side.or(self.pending_side.map(|(side, _)| side))
+    }
+
+    fn insert_disk_side(&mut self, side: Option<usize>) {
+        let side = side.filter(|&side| side < self.sides.len());
+        match (self.inserted_side, side) {
+            // 直接换面时先弹出一段时间
+            (Some(_), Some(side)) => {
+                self.inserted_side = None;
+                self.pending_side = Some((side, SWAP_DELAY));
+            }
+            (_, side) => {
+                self.inserted_side = side;
+                self.pending_side = None;
+            }
+        }
+    }
+
+    fn disk_diff(&self) -> Option<Vec<u8>> {
+        if !self.disk_written {
+            return None;
+        }
+        create_ips(&self.original, &self.disk_image()).ok()
+    }
+}
@@ -0,0 +1,307 @@
+// FDS 扩展音频：64步6位波表 + 频率调制单元，两个包络
+// https://www.nesdev.org/wiki/FDS_audio
+
+use crate::utils::state::{StateReader, StateWriter};
+use crate::{NesError, NesResult};
+
+// $4089 低2位选择的主音量：2/2, 2/3, 2/4, 2/5
+const MASTER_VOLUME: [u32; 4] = [30, 20, 15, 12];
+
+// 调制表中每个值对应的计数器增量，4 表示把计数器清零
+const MOD_ADJUSTMENTS: [i32; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+// 音量/调制共用的包络
+#[derive(Debug, Clone, Default)]
+struct Envelope {
+    speed: u8,
+    gain: u8,
+    increase: bool,
+    disabled: bool, // 为真时直接使用 speed 作为增益
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8, master_speed: u8) {
+        self.disabled = data & 0x80 != 0;
+        self.increase = data & 0x40 != 0;
+        self.speed = data & 0x3F;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.speed);
+        writer.write_u8(self.gain);
+        writer.write_bool(self.increase);
+        writer.write_bool(self.disabled);
+        writer.write_u32(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.speed = reader.read_u8()?;
+        self.gain = reader.read_u8()?;
+        self.increase = reader.read_bool()?;
+        self.disabled = reader.read_bool()?;
+        self.timer = reader.read_u32()?;
+        Ok(())
+    }
+
+    // 返回增益是否改变
+    fn clock(&mut self, master_speed: u8) -> bool {
+        if self.disabled || master_speed == 0 {
+            return false;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.reset_timer(master_speed);
+            if self.increase && self.gain < 32 {
+                self.gain += 1;
+            } else if !self.increase && self.gain > 0 {
+                self.gain -= 1;
+            }
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    wave_write_enabled: bool, // $4089 第7位，允许写波表时停止输出
+    wave_halted: bool,
+    envelopes_disabled: bool,
+    wave_frequency: u16,
+    wave_accumulator: u32,
+    wave_position: u8,
+    master_volume: u8,
+    master_envelope_speed: u8,
+    volume: Envelope,
+    latched_gain: u8, // 音量增益只在波形回到第0步时更新
+
+    mod_table: [u8; 64],
+    mod_position: u8,
+    mod_halted: bool,
+    mod_frequency: u16,
+    mod_accumulator: u32,
+    mod_counter: i8, // 7位有符号数
+    modulation: Envelope,
+
+    output: u32,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave_table: [0; 64],
+            wave_write_enabled: false,
+            wave_halted: true,
+            envelopes_disabled: false,
+            wave_frequency: 0,
+            wave_accumulator: 0,
+            wave_position: 0,
+            master_volume: 0,
+            master_envelope_speed: 0xE8,
+            volume: Envelope::default(),
+            latched_gain: 0,
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_halted: true,
+            mod_frequency: 0,
+            mod_accumulator: 0,
+            mod_counter: 0,
+            modulation: Envelope::default(),
+            output: 0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => self.wave_table[(addr - 0x4040) as usize] | 0x40,
+            0x4090 => self.volume.gain | 0x40,
+            0x4092 => self.modulation.gain | 0x40,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407F => {
+                if self.wave_write_enabled {
+                    self.wave_table[(addr - 0x4040) as usize] = data & 0x3F;
+                }
+            }
+            0x4080 => self.volume.write(data, self.master_envelope_speed),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0x0F00) | data as u16,
+            0x4083 => {
+                self.wave_frequency = (self.wave_frequency & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.wave_halted = data & 0x80 != 0;
+                self.envelopes_disabled = data & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_accumulator = 0;
+                    self.wave_position = 0;
+                }
+                if self.envelopes_disabled {
+                    self.volume.reset_timer(self.master_envelope_speed);
+                    self.modulation.reset_timer(self.master_envelope_speed);
+                }
+            }
+            0x4084 => self.modulation.write(data, self.master_envelope_speed),
+            0x4085 => self.mod_counter = ((data << 1) as i8) >> 1,
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | data as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.mod_halted = data & 0x80 != 0;
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            0x4088 => {
+                // 调制单元停止时才能写入，每次写入占两个位置
+                if self.mod_halted {
+                    let position = self.mod_position as usize;
+                    self.mod_table[position] = data & 0x07;
+                    self.mod_table[(position + 1) & 0x3F] = data & 0x07;
+                    self.mod_position = (self.mod_position + 2) & 0x3F;
+                }
+            }
+            0x4089 => {
+                self.wave_write_enabled = data & 0x80 != 0;
+                self.master_volume = data & 0x03;
+            }
+            0x408A => {
+                self.master_envelope_speed = data;
+                self.volume.reset_timer(data);
+                self.modulation.reset_timer(data);
+            }
+            _ => {}
+        }
+    }
+
+    // 调制后的频率，算法来自 nesdev wiki
+    fn modulated_frequency(&self) -> u32 {
+        let mut temp = self.mod_counter as i32 * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        let pitch = self.wave_frequency as i32;
+        let mut offset = pitch * temp;
+        let remainder = offset & 0x3F;
+        offset >>= 6;
+        if remainder >= 32 {
+            offset += 1;
+        }
+        (pitch + offset).max(0) as u32
+    }
+
+    // 每个 CPU 周期调用一次
+    pub fn clock(&mut self) {
+        if !self.wave_halted && !self.envelopes_disabled {
+            self.volume.clock(self.master_envelope_speed);
+            self.modulation.clock(self.master_envelope_speed);
+        }
+
+        if !self.mod_halted && self.mod_frequency > 0 {
+            self.mod_accumulator += self.mod_frequency as u32;
+            if self.mod_accumulator > 0xFFFF {
+                self.mod_accumulator &= 0xFFFF;
+                let value = self.mod_table[self.mod_position as usize];
+                self.mod_position = (self.mod_position + 1) & 0x3F;
+                self.mod_counter = if value == 4 {
+                    0
+                } else {
+                    // 保持在7位有符号数范围内
+                    (((self.mod_counter as i32 + MOD_ADJUSTMENTS[value as usize]) << 25) >> 25) as i8
+                };
+            }
+        }
+
+        if self.wave_halted || self.wave_write_enabled {
+            return;
+        }
+        self.wave_accumulator += self.modulated_frequency();
+        if self.wave_accumulator > 0xFFFF {
+            self.wave_accumulator &= 0xFFFF;
+            self.wave_position = (self.wave_position + 1) & 0x3F;
+            if self.wave_position == 0 {
+                self.latched_gain = self.volume.gain.min(32);
+            }
+        }
+        self.output = self.wave_table[self.wave_position as usize] as u32
+            * self.latched_gain as u32
+            * MASTER_VOLUME[self.master_volume as usize];
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.wave_table);
+        writer.write_bool(self.wave_write_enabled);
+        writer.write_bool(self.wave_halted);
+        writer.write_bool(self.envelopes_disabled);
+        writer.write_u16(self.wave_frequency);
+        writer.write_u32(self.wave_accumulator);
+        writer.write_u8(self.wave_position);
+        writer.write_u8(self.master_volume);
+        writer.write_u8(self.master_envelope_speed);
+        self.volume.save_state(writer);
+        writer.write_u8(self.latched_gain);
+        writer.write_bytes(&self.mod_table);
+        writer.write_u8(self.mod_position);
+        writer.write_bool(self.mod_halted);
+        writer.write_u16(self.mod_frequency);
+        writer.write_u32(self.mod_accumulator);
+        writer.write_u8(self.mod_counter as u8);
+        self.modulation.save_state(writer);
+        writer.write_u32(self.output);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.wave_table)?;
+        self.wave_write_enabled = reader.read_bool()?;
+        self.wave_halted = reader.read_bool()?;
+        self.envelopes_disabled = reader.read_bool()?;
+        self.wave_frequency = reader.read_u16()?;
+        self.wave_accumulator = reader.read_u32()?;
+        self.wave_position = reader.read_u8()?;
+        self.master_volume = reader.read_u8()?;
+        self.master_envelope_speed = reader.read_u8()?;
+        self.volume.load_state(reader)?;
+        self.latched_gain = reader.read_u8()?;
+        reader.read_into(&mut self.mod_table)?;
+        self.mod_position = reader.read_u8()?;
+        self.mod_halted = reader.read_bool()?;
+        self.mod_frequency = reader.read_u16()?;
+        self.mod_accumulator = reader.read_u32()?;
+        self.mod_counter = reader.read_u8()? as i8;
+        self.modulation.load_state(reader)?;
+        self.output = reader.read_u32()?;
+        // 这些值会用作数组下标
+        if self.wave_position >= 64
+            || self.mod_position >= 64
+            || self.master_volume as usize >= MASTER_VOLUME.len()
+            || self.mod_table.iter().any(|&value| value as usize >= MOD_ADJUSTMENTS.len())
+        {
+            return Err(NesError::InvalidState("FDS 音频状态无效".to_string()));
+        }
+        Ok(())
+    }
+
+    // 归一化到 0.0..=1.0 的输出
+    pub fn output(&self) -> f32 {
+        self.output as f32 / (63 * 32 * 30) as f32
+    }
+}
@@ -0,0 +1,294 @@
+// 游戏数据库：很多 dump 的 iNES 头里 mapper/镜像/submapper 是错的，
+// 按 PRG+CHR 数据的 CRC32/SHA-1 查到条目后用数据库的值覆盖头。
+// 内置数据库编译进程序，也可以加载 NES 2.0 数据库格式的 XML（nes20db.xml）或同格式的 CSV 文件进行扩充。
+// 内置数据库只有仓库自带的测试 ROM，完整的数据要用外部文件：把 nes20db.xml 或 gamedb.csv
+// 放在 ROM 所在目录或当前目录，加载 ROM 时会自动读入；也可以在界面中用 Load DB 手动加载。
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+
+use super::{ConsoleType, RomHeader, TimingMode};
+use crate::{NesError, NesResult};
+
+const BUILTIN_DB: &str = include_str!("gamedb.csv");
+
+// 数据库中的一条记录，None 表示不覆盖头中的值
+#[derive(Debug, Clone, Default)]
+pub struct GameInfo {
+    pub crc32: u32,
+    pub sha1: Option<String>, // 40位大写十六进制
+    pub title: String,
+    pub board: String,
+    pub region: Option<TimingMode>,
+    pub mapper_number: Option<u16>,
+    pub submapper_number: Option<u8>,
+    pub mirroring_type: Option<u8>,
+    pub four_screen: Option<bool>,
+    pub battery_backed_ram: Option<bool>,
+    pub prg_ram_size: Option<usize>,
+    pub prg_nvram_size: Option<usize>,
+    pub chr_ram_size: Option<usize>,
+    pub chr_nvram_size: Option<usize>,
+    pub console_type: Option<ConsoleType>,
+    pub default_expansion_device: Option<u8>,
+}
+
+impl GameInfo {
+    // 用数据库的值覆盖头
+    pub fn apply(&self, rom_header: &mut RomHeader) {
+        if let Some(mapper_number) = self.mapper_number {
+            rom_header.mapper_number = mapper_number;
+        }
+        if let Some(submapper_number) = self.submapper_number {
+            rom_header.submapper_number = submapper_number;
+        }
+        if let Some(mirroring_type) = self.mirroring_type {
+            rom_header.mirroring_type = mirroring_type;
+        }
+        if let Some(four_screen) = self.four_screen {
+            rom_header.four_screen = four_screen;
+        }
+        if let Some(battery_backed_ram) = self.battery_backed_ram {
+            rom_header.battery_backed_ram = battery_backed_ram;
+        }
+        if let Some(prg_ram_size) = self.prg_ram_size {
+            rom_header.prg_ram_size = prg_ram_size;
+        }
+        if let Some(prg_nvram_size) = self.prg_nvram_size {
+            rom_header.prg_nvram_size = prg_nvram_size;
+        }
+        if let Some(chr_ram_size) = self.chr_ram_size {
+            rom_header.chr_ram_size = chr_ram_size;
+        }
+        if let Some(chr_nvram_size) = self.chr_nvram_size {
+            rom_header.chr_nvram_size = chr_nvram_size;
+        }
+        if let Some(region) = self.region {
+            rom_header.timing = region;
+        }
+        if let Some(console_type) = self.console_type {
+            rom_header.console_type = console_type;
+        }
+        if let Some(default_expansion_device) = self.default_expansion_device {
+            rom_header.default_expansion_device = default_expansion_device;
+        }
+    }
+}
+
+pub struct GameDb {
+    entries: HashMap<u32, Vec<GameInfo>>, // 以 CRC32 为键，同一 CRC32 下用 SHA-1 区分
+}
+
+impl Default for GameDb {
+    fn default() -> Self {
+        GameDb::builtin()
+    }
+}
+
+impl GameDb {
+    pub fn new() -> Self {
+        GameDb {
+            entries: HashMap::new(),
+        }
+    }
+
+    // 编译进程序的数据库
+    pub fn builtin() -> Self {
+        let mut game_db = GameDb::new();
+        game_db.load_csv(BUILTIN_DB).expect("内置游戏数据库格式错误");
+        game_db
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.values().map(|games| games.len()).sum()
+    }
+
+    // 加载用户提供的数据库，.xml 按 nes20db 格式解析，其余按 CSV 解析。返回新增的条目数
+    pub fn load_file(&mut self, path: &str) -> NesResult<usize> {
+        let text = fs::read_to_string(Path::new(path))?;
+        let is_xml = Path::new(path)
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("xml"));
+        if is_xml {
+            self.load_xml(&text)
+        } else {
+            self.load_csv(&text)
+        }
+    }
+
+    // 按 PRG+CHR 数据查找，CRC32 命中后如果条目有 SHA-1 还要校验 SHA-1
+    pub fn lookup(&self, rom_data: &[u8]) -> Option<&GameInfo> {
+        let crc32 = crc32fast::hash(rom_data);
+        let games = self.entries.get(&crc32)?;
+        let mut sha1 = None;
+        games.iter().find(|game| match &game.sha1 {
+            None => true,
+            Some(expected) => {
+                let sha1 = sha1.get_or_insert_with(|| sha1_smol::Sha1::from(rom_data).digest().to_string().to_uppercase());
+                sha1 == expected
+            }
+        })
+    }
+
+    fn insert(&mut self, game: GameInfo) {
+        let games = self.entries.entry(game.crc32).or_default();
+        // 后加载的条目覆盖相同哈希的旧条目
+        games.retain(|old| old.sha1 != game.sha1);
+        games.push(game);
+    }
+
+    // CSV：crc32,sha1,mapper,submapper,mirroring,battery,prg_ram,prg_nvram,chr_ram,chr_nvram,region,board,title
+    // 以 # 开头的行和表头行被忽略，title 为最后一列，可以包含逗号
+    pub fn load_csv(&mut self, text: &str) -> NesResult<usize> {
+        let mut count = 0;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("crc32,") {
+                continue;
+            }
+            let invalid = |field: &str| NesError::InvalidGameDb(format!("第{}行: 无效的{}字段", line_number + 1, field));
+            let fields: Vec<&str> = line.splitn(13, ',').map(|field| field.trim()).collect();
+            if fields.len() != 13 {
+                return Err(NesError::InvalidGameDb(format!("第{}行: 需要13列, 实际{}列", line_number + 1, fields.len())));
+            }
+            let mut game = GameInfo {
+                crc32: u32::from_str_radix(fields[0], 16).map_err(|_| invalid("crc32"))?,
+                sha1: parse_sha1(fields[1]).map_err(|_| invalid("sha1"))?,
+                board: fields[11].to_string(),
+                title: fields[12].to_string(),
+                ..Default::default()
+            };
+            game.mapper_number = parse_optional(fields[2]).map_err(|_| invalid("mapper"))?;
+            game.submapper_number = parse_optional(fields[3]).map_err(|_| invalid("submapper"))?;
+            parse_mirroring(&mut game, fields[4]).map_err(|_| invalid("mirroring"))?;
+            game.battery_backed_ram = parse_optional::<u8>(fields[5]).map_err(|_| invalid("battery"))?.map(|battery| battery != 0);
+            game.prg_ram_size = parse_optional(fields[6]).map_err(|_| invalid("prg_ram"))?;
+            game.prg_nvram_size = parse_optional(fields[7]).map_err(|_| invalid("prg_nvram"))?;
+            game.chr_ram_size = parse_optional(fields[8]).map_err(|_| invalid("chr_ram"))?;
+            game.chr_nvram_size = parse_optional(fields[9]).map_err(|_| invalid("chr_nvram"))?;
+            game.region = parse_optional::<u8>(fields[10]).map_err(|_| invalid("region"))?.map(parse_region);
+            self.insert(game);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // nes20db.xml：每个 <game> 元素以 <!-- 文件名 --> 注释开头，
+    // <rom> 给出 PRG+CHR 的哈希，<pcb>/<console>/<prgram> 等给出头的正确值
+    pub fn load_xml(&mut self, text: &str) -> NesResult<usize> {
+        let game_regex = Regex::new(r"(?s)<game>(.*?)</game>").unwrap();
+        let comment_regex = Regex::new(r"<!--\s*(.*?)\s*-->").unwrap();
+        let tag_regex = Regex::new(r"<(\w+)\s([^>]*?)/?>").unwrap();
+        let attribute_regex = Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
+
+        let mut count = 0;
+        for game_match in game_regex.captures_iter(text) {
+            let body = &game_match[1];
+            let mut game = GameInfo::default();
+            let mut has_hash = false;
+            if let Some(comment) = comment_regex.captures(body) {
+                game.title = comment[1].trim_end_matches(".nes").to_string();
+            }
+            let title = game.title.clone();
+            for tag in tag_regex.captures_iter(body) {
+                let attributes: HashMap<&str, &str> = attribute_regex
+                    .captures_iter(tag.get(2).unwrap().as_str())
+                    .map(|attribute| (attribute.get(1).unwrap().as_str(), attribute.get(2).unwrap().as_str()))
+                    .collect();
+                let invalid = |field: &str| NesError::InvalidGameDb(format!("{}: 无效的{}属性", title, field));
+                let number = |name: &str| -> NesResult<Option<usize>> {
+                    attributes.get(name).map(|value| value.parse::<usize>().map_err(|_| invalid(name))).transpose()
+                };
+                match &tag[1] {
+                    "rom" => {
+                        let crc32 = attributes.get("crc32").ok_or_else(|| invalid("crc32"))?;
+                        game.crc32 = u32::from_str_radix(crc32, 16).map_err(|_| invalid("crc32"))?;
+                        game.sha1 = parse_sha1(attributes.get("sha1").unwrap_or(&"")).map_err(|_| invalid("sha1"))?;
+                        has_hash = true;
+                    }
+                    "pcb" => {
+                        game.mapper_number = number("mapper")?.map(|mapper| mapper as u16);
+                        game.submapper_number = number("submapper")?.map(|submapper| submapper as u8);
+                        game.battery_backed_ram = number("battery")?.map(|battery| battery != 0);
+                        if let Some(mirroring) = attributes.get("mirroring") {
+                            parse_mirroring(&mut game, mirroring).map_err(|_| invalid("mirroring"))?;
+                        }
+                    }
+                    "console" => {
+                        game.region = number("region")?.map(|region| parse_region(region as u8));
+                        if let Some(console_type) = number("type")? {
+                            game.console_type = Some(match console_type {
+                                0 => ConsoleType::Nes,
+                                1 => ConsoleType::VsSystem { ppu_type: 0, hardware_type: 0 },
+                                2 => ConsoleType::Playchoice10,
+                                _ => ConsoleType::Extended(console_type as u8),
+                            });
+                        }
+                    }
+                    "vs" => {
+                        let ppu_type = number("ppu")?.unwrap_or(0) as u8;
+                        let hardware_type = number("hardware")?.unwrap_or(0) as u8;
+                        game.console_type = Some(ConsoleType::VsSystem { ppu_type, hardware_type });
+                    }
+                    "prgram" => game.prg_ram_size = number("size")?,
+                    "prgnvram" => game.prg_nvram_size = number("size")?,
+                    "chrram" => game.chr_ram_size = number("size")?,
+                    "chrnvram" => game.chr_nvram_size = number("size")?,
+                    "expansion" => game.default_expansion_device = number("type")?.map(|device| device as u8),
+                    _ => {}
+                }
+            }
+            if has_hash {
+                self.insert(game);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn parse_optional<T: std::str::FromStr>(field: &str) -> Result<Option<T>, T::Err> {
+    if field.is_empty() {
+        Ok(None)
+    } else {
+        field.parse::<T>().map(Some)
+    }
+}
+
+fn parse_sha1(field: &str) -> Result<Option<String>, ()> {
+    match field.len() {
+        0 => Ok(None),
+        40 if field.chars().all(|c| c.is_ascii_hexdigit()) => Ok(Some(field.to_uppercase())),
+        _ => Err(()),
+    }
+}
+
+// H 水平 V 垂直 4 四屏；1 表示由 mapper 控制，不覆盖
+fn parse_mirroring(game: &mut GameInfo, field: &str) -> Result<(), ()> {
+    match field {
+        "" | "1" => {}
+        "H" | "h" => {
+            game.mirroring_type = Some(0);
+            game.four_screen = Some(false);
+        }
+        "V" | "v" => {
+            game.mirroring_type = Some(1);
+            game.four_screen = Some(false);
+        }
+        "4" => game.four_screen = Some(true),
+        _ => return Err(()),
+    }
+    Ok(())
+}
+
+fn parse_region(region: u8) -> TimingMode {
+    match region {
+        0 => TimingMode::Ntsc,
+        1 => TimingMode::Pal,
+        2 => TimingMode::MultiRegion,
+        _ => TimingMode::Dendy,
+    }
+}
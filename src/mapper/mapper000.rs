@@ -1,7 +1,9 @@
 // mapper.rs
 
 // 引入标准库中的类型和特质
-use super::Mapper;
+use super::{Mapper, RomHeader};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
 
 
 
@@ -16,12 +18,17 @@ pub struct NromMapper {
 
 impl NromMapper {
     // NromMapper 的构造函数
-    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirror_mode: u8) -> Self {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, rom_header: &RomHeader) -> Self {
+        // 头中未声明 PRG-RAM 时，仍按 8KB 分配（Family BASIC 等卡带依赖这块内存）
+        let prg_ram_size = match rom_header.prg_ram_size + rom_header.prg_nvram_size {
+            0 => 0x2000,
+            size => size,
+        };
         NromMapper {
             prg_rom,
-            prg_ram: vec![0; 0x2000],
+            prg_ram: vec![0; prg_ram_size],
             chr_rom,
-            mirror_mode,
+            mirror_mode: rom_header.mirroring_type,
         }
     }
 }
@@ -66,6 +73,17 @@ impl Mapper for NromMapper {
     }
     
     fn reset(&mut self) {
-        self.prg_ram = vec![0; 0x2000];
+        self.prg_ram = vec![0; self.prg_ram.len()];
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        // CHR 可能是 CHR-RAM
+        writer.write_bytes(&self.chr_rom);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.prg_ram)?;
+        reader.read_into(&mut self.chr_rom)
     }
 }
@@ -1,7 +1,9 @@
 // mapper.rs
 
 // 引入标准库中的类型和特质
-use super::Mapper;
+use super::{Mapper, RomHeader};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
 
 
 
@@ -9,16 +11,22 @@ use super::Mapper;
 #[derive(Debug)]
 pub struct Mapper001 {
     prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
     chr_rom: Vec<u8>,
     mirror_mode: u8,
 }
 
 impl Mapper001 {
-    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirror_mode: u8) -> Self {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, rom_header: &RomHeader) -> Self {
+        let prg_ram_size = match rom_header.prg_ram_size + rom_header.prg_nvram_size {
+            0 => 0x2000,
+            size => size,
+        };
         Mapper001 {
             prg_rom,
+            prg_ram: vec![0; prg_ram_size],
             chr_rom,
-            mirror_mode,
+            mirror_mode: rom_header.mirroring_type,
         }
     }
 }
@@ -30,13 +38,15 @@ impl Mapper for Mapper001 {
     }
 
     fn read_prg_ram(&self, addr: u16) -> u8 {
-        let addr = addr as usize % self.prg_rom.len();
-        self.prg_rom[addr]
+        let addr = addr as usize % self.prg_ram.len();
+        self.prg_ram[addr]
     }
 
     fn write_prg_rom(&mut self, _addr: u16, _data: u8) {
     }
-    fn write_prg_ram(&mut self, _addr: u16, _data: u8) {
+    fn write_prg_ram(&mut self, addr: u16, data: u8) {
+        let addr = addr as usize % self.prg_ram.len();
+        self.prg_ram[addr] = data;
     }
 
     fn read_chr_rom(&self, addr: u16) -> u8 {
@@ -58,4 +68,14 @@ impl Mapper for Mapper001 {
     
     fn reset(&mut self) {
     }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        writer.write_bytes(&self.chr_rom);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.read_into(&mut self.prg_ram)?;
+        reader.read_into(&mut self.chr_rom)
+    }
 }
@@ -1,7 +1,9 @@
 // mapper.rs
 
 // 引入标准库中的类型和特质
-use super::Mapper;
+use super::{Mapper, RomHeader};
+use crate::utils::state::{StateReader, StateWriter};
+use crate::NesResult;
 
 
 
@@ -9,6 +11,7 @@ use super::Mapper;
 #[derive(Debug)]
 pub struct Mapper003 {
     prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>, // CNROM 本身没有 PRG-RAM，只有头中声明或带 trainer 时才有
     chr_rom: Vec<u8>,
     prg_rom_init: Vec<u8>,
     chr_rom_init: Vec<u8>,
@@ -18,13 +21,19 @@ pub struct Mapper003 {
 
 impl Mapper003 {
     // NromMapper 的构造函数
-    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirror_mode: u8) -> Self {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, rom_header: &RomHeader) -> Self {
+        // trainer 要装入 $7000，没有声明 PRG-RAM 时也给 8KB，否则 trainer 会丢失
+        let prg_ram_size = match rom_header.prg_ram_size + rom_header.prg_nvram_size {
+            0 if rom_header.trainer => 0x2000,
+            size => size,
+        };
         Mapper003 {
+            prg_ram: vec![0; prg_ram_size],
             prg_rom_init: prg_rom.clone(),
             chr_rom_init: chr_rom.clone(),
             prg_rom,
             chr_rom,
-            mirror_mode,
+            mirror_mode: rom_header.mirroring_type,
             chr_rom_bank : 0,
         }
     }
@@ -37,13 +46,22 @@ impl Mapper for Mapper003 {
         self.prg_rom[addr]
     }
     fn read_prg_ram(&self, addr: u16) -> u8 {
-        todo!()
+        if self.prg_ram.is_empty() {
+            return 0;
+        }
+        let addr = addr as usize % self.prg_ram.len();
+        self.prg_ram[addr]
     }
 
     fn write_prg_rom(&mut self, addr: u16, data: u8) {
         self.chr_rom_bank = data & 0x3;
     }
-    fn write_prg_ram(&mut self, _addr: u16, _data: u8) {
+    fn write_prg_ram(&mut self, addr: u16, data: u8) {
+        if self.prg_ram.is_empty() {
+            return;
+        }
+        let addr = addr as usize % self.prg_ram.len();
+        self.prg_ram[addr] = data;
     }
 
     fn read_chr_rom(&self, addr: u16) -> u8 {
@@ -65,7 +83,20 @@ impl Mapper for Mapper003 {
     
     fn reset(&mut self) {
         self.chr_rom_bank = 0;
+        self.prg_ram = vec![0; self.prg_ram.len()];
         self.prg_rom = self.prg_rom_init.clone();
         self.chr_rom = self.chr_rom_init.clone();
     }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.chr_rom_bank);
+        writer.write_bytes(&self.prg_ram);
+        writer.write_bytes(&self.chr_rom);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        self.chr_rom_bank = reader.read_u8()?;
+        reader.read_into(&mut self.prg_ram)?;
+        reader.read_into(&mut self.chr_rom)
+    }
 }
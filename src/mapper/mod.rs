@@ -1,20 +1,59 @@
 pub mod mapper000;
 mod mapper003;
 mod mapper001;
+pub mod gamedb;
+mod unif;
+pub mod fds;
+mod fds_audio;
 
 use mapper000::NromMapper;
 use mapper003::Mapper003;
 use mapper001::Mapper001;
 
-#[derive(Debug)]
+use crate::utils::state::{StateReader, StateWriter};
+use crate::{NesError, NesResult};
+
+// CPU/PPU 时序，NES 2.0 第12字节（iNES 1.0 只有第9字节的 NTSC/PAL 位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimingMode {
+    #[default]
+    Ntsc, // RP2C02
+    Pal, // RP2C07
+    MultiRegion, // 多区域，兼容 NTSC 和 PAL
+    Dendy, // UMC 6527P
+}
+
+// 主机类型，第7字节低2位，为3时由第13字节给出扩展类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsoleType {
+    #[default]
+    Nes, // NES/Famicom/Dendy
+    VsSystem { ppu_type: u8, hardware_type: u8 }, // Vs. System，第13字节低4位为PPU类型，高4位为硬件类型
+    Playchoice10,
+    Extended(u8), // 扩展主机类型
+}
+
+// 卡带描述信息，iNES 1.0 中缺失的字段按 1.0 的约定填默认值
+#[derive(Debug, Clone, Default)]
 pub struct RomHeader {
-    pub prg_rom_size: usize,
-    pub chr_rom_size: usize,
-    pub mapper_number: u8,
-    pub mirroring_type: u8,
+    pub prg_rom_size: usize, // 字节数
+    pub chr_rom_size: usize, // 字节数，0 表示使用 CHR-RAM
+    pub mapper_number: u16, // NES 2.0 为12位
+    pub submapper_number: u8,
+    pub mirroring_type: u8, // 0: 水平镜像 1: 垂直镜像
+    pub four_screen: bool, // 四屏镜像，忽略 mirroring_type
     pub battery_backed_ram: bool,
     pub trainer: bool,
     pub nes2_0: bool,
+    pub prg_ram_size: usize, // 易失性 PRG-RAM 字节数
+    pub prg_nvram_size: usize, // 电池供电的 PRG-NVRAM/EEPROM 字节数
+    pub chr_ram_size: usize, // 易失性 CHR-RAM 字节数
+    pub chr_nvram_size: usize, // 电池供电的 CHR-NVRAM 字节数
+    pub timing: TimingMode,
+    pub console_type: ConsoleType,
+    pub misc_rom_count: u8, // 紧跟在 CHR-ROM 之后的杂项 ROM 个数
+    pub default_expansion_device: u8, // 默认扩展设备编号，0 表示未指定
+    pub unif_board: Option<String>, // UNIF 文件的 MAPR 电路板名
 }
 
 pub struct InterruptVectors {
@@ -44,81 +83,260 @@ pub trait Mapper: Send {
     fn write_chr_rom(&mut self, addr: u16, data: u8);
     fn ppu_mirror_mode(&self) -> u8;
     fn reset(&mut self);
-}
+    // 即时存档：bank 寄存器和各种 RAM，ROM 数据不保存
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()>;
 
+    // $4020-$5FFF 扩展区域，大多数卡带没有
+    fn read_expansion(&mut self, _addr: u16) -> u8 {
+        0
+    }
+    fn write_expansion(&mut self, _addr: u16, _data: u8) {}
+    // 每个 CPU 周期调用一次，用于 IRQ 计数器、磁盘传输等
+    fn cpu_clock(&mut self) {}
+    fn irq_pending(&self) -> bool {
+        false
+    }
+    // 扩展音频输出，范围 0.0..=1.0
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
 
-pub fn create_mapper(
-    rom_data: &[u8],
-) -> Box<dyn Mapper> {
-
-    let rom_header = parse_rom_header(&rom_data);
-    println!("prg_rom_size:{}kb,chr_rom_size:{}kb,mapper_number:{},mirroring_type:{},battery_backed_ram:{},trainer:{},nes2_0:{}",
-             rom_header.prg_rom_size,rom_header.chr_rom_size,rom_header.mapper_number,rom_header.mirroring_type,rom_header.battery_backed_ram,rom_header.trainer,rom_header.nes2_0);
+    // 磁盘机（FDS），其他卡带没有磁盘
+    fn disk_side_count(&self) -> usize {
+        0
+    }
+    fn inserted_disk_side(&self) -> Option<usize> {
+        None
+    }
+    // None 表示弹出磁盘
+    fn insert_disk_side(&mut self, _side: Option<usize>) {}
+    // 磁盘被写入过时，返回相对于原始文件的差异（IPS 格式）
+    fn disk_diff(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
 
-    // 提取PRG-ROM和CHR-ROM数据
-    let (prg_rom, chr_rom) = parse_prg_and_chr_rom_data(&rom_data);
 
-    // 提取中断信息
-    let interrupt_vectors = parse_interrupt_vectors(&prg_rom);
+// 从 iNES/NES 2.0 或 UNIF 文件中解析出的卡带内容
+#[derive(Debug, Clone, Default)]
+pub struct RomImage {
+    pub rom_header: RomHeader,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>, // 为空表示使用 CHR-RAM
+    pub trainer: Option<Vec<u8>>, // 512字节，加载时放到 $7000
+}
 
-    match rom_header.mapper_number {
-        0 => Box::new(NromMapper::new(prg_rom, chr_rom, rom_header.mirroring_type)),
-        1 => Box::new(Mapper001::new(prg_rom, chr_rom, rom_header.mirroring_type)),
-        3 => Box::new(Mapper003::new(prg_rom, chr_rom, rom_header.mirroring_type)),
-        // 在这里添加其他 Mapper 的实现
-        _ => panic!("Unsupported mapper ID: {}", rom_header.mapper_number),
+impl RomImage {
+    // PRG+CHR 数据，用于在游戏数据库中查找
+    pub fn rom_body(&self) -> Vec<u8> {
+        [self.prg_rom.as_slice(), self.chr_rom.as_slice()].concat()
     }
 }
 
+// 根据文件头判断格式并解析
+pub fn parse_rom(rom_data: &[u8]) -> NesResult<RomImage> {
+    if rom_data.starts_with(b"UNIF") {
+        unif::parse_unif(rom_data)
+    } else {
+        parse_ines(rom_data)
+    }
+}
 
+pub fn create_mapper(
+    rom_header: &RomHeader,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+) -> NesResult<Box<dyn Mapper>> {
+    // PRG-ROM 末尾至少要有6个字节的中断向量
+    if prg_rom.len() < 6 {
+        return Err(NesError::TruncatedPrgRom { expected: 6, actual: prg_rom.len() });
+    }
 
-// 解析中断向量
-fn parse_interrupt_vectors(prg_rom: &Vec<u8>) -> InterruptVectors {
-    let nmi_vector = u16::from_le_bytes([prg_rom[prg_rom.len() - 6], prg_rom[prg_rom.len() - 5]]);
-    let reset_vector = u16::from_le_bytes([prg_rom[prg_rom.len() - 4], prg_rom[prg_rom.len() - 3]]);
-    let irq_vector = u16::from_le_bytes([prg_rom[prg_rom.len() - 2], prg_rom[prg_rom.len() - 1]]);
+    // Handle CHR-ROM or CHR-RAM
+    let chr_rom = if chr_rom.is_empty() {
+        // 头中没有声明任何 CHR 存储时，按常见的 8KB CHR-RAM 处理
+        let chr_ram_size = match rom_header.chr_ram_size + rom_header.chr_nvram_size {
+            0 => 8 * 1024,
+            size => size,
+        };
+        vec![0; chr_ram_size]
+    } else {
+        chr_rom
+    };
 
-    InterruptVectors {
-        nmi_vector,
-        reset_vector,
-        irq_vector,
-    }
+    let mapper: Box<dyn Mapper> = match rom_header.mapper_number {
+        0 => Box::new(NromMapper::new(prg_rom, chr_rom, rom_header)),
+        1 => Box::new(Mapper001::new(prg_rom, chr_rom, rom_header)),
+        3 => Box::new(Mapper003::new(prg_rom, chr_rom, rom_header)),
+        // 在这里添加其他 Mapper 的实现
+        _ => {
+            return Err(NesError::UnsupportedMapper {
+                mapper: rom_header.mapper_number,
+                submapper: rom_header.submapper_number,
+            })
+        }
+    };
+    Ok(mapper)
 }
 
-fn parse_prg_and_chr_rom_data(rom_data: &[u8]) -> (Vec<u8>, Vec<u8>) {
-    let header_size = 16;
-    let prg_rom_size = rom_data[4] as usize * 16384; // PRG-ROM size is at offset 4
-    let chr_rom_size = rom_data[5] as usize * 8192; // CHR-ROM size is at offset 5
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
 
-    // Copy PRG-ROM data
-    let prg_rom_data = rom_data[header_size..header_size + prg_rom_size].to_vec();
+// 解析 iNES/NES 2.0 文件：16字节头，可选的512字节 trainer，然后是 PRG-ROM 和 CHR-ROM
+pub fn parse_ines(rom_data: &[u8]) -> NesResult<RomImage> {
+    let rom_header = parse_rom_header(rom_data)?;
+    let header_size = HEADER_SIZE;
+    let trainer_size = if rom_header.trainer { TRAINER_SIZE } else { 0 };
+    let prg_rom_size = rom_header.prg_rom_size;
+    let chr_rom_size = rom_header.chr_rom_size;
 
-    // Handle CHR-ROM or CHR-RAM
-    let chr_rom_data = if chr_rom_size == 0 {
-        vec![0; 8192] // CHR-RAM size could be different, adjust accordingly
+    if rom_data.len() < header_size + trainer_size {
+        return Err(NesError::TruncatedTrainer {
+            expected: trainer_size,
+            actual: rom_data.len().saturating_sub(header_size),
+        });
+    }
+    let trainer = if rom_header.trainer {
+        Some(rom_data[header_size..header_size + trainer_size].to_vec())
     } else {
-        rom_data[header_size + prg_rom_size..header_size + prg_rom_size + chr_rom_size].to_vec()
+        None
     };
 
-    (prg_rom_data, chr_rom_data)
-}
+    // Copy PRG-ROM data
+    let prg_rom_start = header_size + trainer_size;
+    let prg_rom_end = prg_rom_start.checked_add(prg_rom_size).ok_or(NesError::InvalidRom)?;
+    let prg_rom = rom_data
+        .get(prg_rom_start..prg_rom_end)
+        .ok_or(NesError::TruncatedPrgRom {
+            expected: prg_rom_size,
+            actual: rom_data.len() - prg_rom_start,
+        })?
+        .to_vec();
 
-pub fn parse_rom_header(rom_data: &[u8]) -> RomHeader {
-    let prg_rom_size = rom_data[4] as usize * 16 * 1024;
-    let chr_rom_size = rom_data[5] as usize * 8 * 1024;
-    let mapper_number = (rom_data[6] >> 4) | (rom_data[7] & 0xF0);
-    let mirroring_type = rom_data[6] & 0x01;
-    let battery_backed_ram = (rom_data[6] & 0x02) != 0;
-    let trainer = (rom_data[6] & 0x04) != 0;
-    let nes2_0 = (rom_data[7] & 0x0C) == 0x08;
+    // Copy CHR-ROM data
+    let chr_rom_start = prg_rom_end;
+    let chr_rom_end = chr_rom_start.checked_add(chr_rom_size).ok_or(NesError::InvalidRom)?;
+    let chr_rom = rom_data
+        .get(chr_rom_start..chr_rom_end)
+        .ok_or(NesError::TruncatedChrRom {
+            expected: chr_rom_size,
+            actual: rom_data.len() - chr_rom_start,
+        })?
+        .to_vec();
 
-    RomHeader {
-        prg_rom_size,
-        chr_rom_size,
-        mapper_number,
-        mirroring_type,
-        battery_backed_ram,
+    Ok(RomImage {
+        rom_header,
+        prg_rom,
+        chr_rom,
         trainer,
+    })
+}
+
+// NES 2.0 的 ROM 大小：高4位为0xF时使用指数-乘数表示法 EEEEEEMM，大小为 2^E * (MM*2+1)
+// 指数最大为63，格式合法但数值溢出的头按无效 ROM 处理
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> NesResult<usize> {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        1usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .ok_or(NesError::InvalidRom)
+    } else {
+        Ok(((msb as usize) << 8 | lsb as usize) * unit)
+    }
+}
+
+// NES 2.0 的 RAM 大小：移位计数为0表示没有，否则为 64 << shift
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
+
+// 老的 dump 工具会在 iNES 1.0 头的 7-15 字节写入 "DiskDude!" 等签名，
+// 第7字节的高4位会被误读为 mapper 号。NES 2.0 头不做处理。
+fn clean_ines_header(header: &mut [u8; HEADER_SIZE]) {
+    if (header[7] & 0x0C) == 0x08 {
+        return;
+    }
+    let dirty = &header[7..16] == b"DiskDude!"
+        || (header[7] & 0x0C) == 0x04
+        || header[12..16].iter().any(|&byte| byte != 0);
+    if dirty {
+        header[7..16].fill(0);
+    }
+}
+
+pub fn parse_rom_header(rom_data: &[u8]) -> NesResult<RomHeader> {
+    if rom_data.len() < HEADER_SIZE {
+        return Err(NesError::TruncatedHeader(rom_data.len()));
+    }
+    if rom_data[0..4] != *b"NES\x1A" {
+        return Err(NesError::InvalidMagic);
+    }
+    let mut header = [0; HEADER_SIZE];
+    header.copy_from_slice(&rom_data[..HEADER_SIZE]);
+    clean_ines_header(&mut header);
+    let rom_data = &header;
+    let nes2_0 = (rom_data[7] & 0x0C) == 0x08;
+    let mut rom_header = RomHeader {
+        prg_rom_size: rom_data[4] as usize * 16 * 1024,
+        chr_rom_size: rom_data[5] as usize * 8 * 1024,
+        mapper_number: ((rom_data[6] >> 4) | (rom_data[7] & 0xF0)) as u16,
+        mirroring_type: rom_data[6] & 0x01,
+        four_screen: (rom_data[6] & 0x08) != 0,
+        battery_backed_ram: (rom_data[6] & 0x02) != 0,
+        trainer: (rom_data[6] & 0x04) != 0,
         nes2_0,
+        ..Default::default()
+    };
+
+    if nes2_0 {
+        rom_header.prg_rom_size = nes2_rom_size(rom_data[4], rom_data[9] & 0x0F, 16 * 1024)?;
+        rom_header.chr_rom_size = nes2_rom_size(rom_data[5], rom_data[9] >> 4, 8 * 1024)?;
+        rom_header.mapper_number |= ((rom_data[8] & 0x0F) as u16) << 8;
+        rom_header.submapper_number = rom_data[8] >> 4;
+        rom_header.prg_ram_size = nes2_ram_size(rom_data[10] & 0x0F);
+        rom_header.prg_nvram_size = nes2_ram_size(rom_data[10] >> 4);
+        rom_header.chr_ram_size = nes2_ram_size(rom_data[11] & 0x0F);
+        rom_header.chr_nvram_size = nes2_ram_size(rom_data[11] >> 4);
+        rom_header.timing = match rom_data[12] & 0x03 {
+            0 => TimingMode::Ntsc,
+            1 => TimingMode::Pal,
+            2 => TimingMode::MultiRegion,
+            _ => TimingMode::Dendy,
+        };
+        rom_header.console_type = match rom_data[7] & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem {
+                ppu_type: rom_data[13] & 0x0F,
+                hardware_type: rom_data[13] >> 4,
+            },
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(rom_data[13] & 0x0F),
+        };
+        rom_header.misc_rom_count = rom_data[14] & 0x03;
+        rom_header.default_expansion_device = rom_data[15] & 0x3F;
+    } else {
+        // iNES 1.0：第8字节为8KB单位的PRG-RAM大小，0按8KB处理；有电池时视为NVRAM
+        let prg_ram_size = (rom_data[8].max(1) as usize) * 8 * 1024;
+        if rom_header.battery_backed_ram {
+            rom_header.prg_nvram_size = prg_ram_size;
+        } else {
+            rom_header.prg_ram_size = prg_ram_size;
+        }
+        if rom_header.chr_rom_size == 0 {
+            rom_header.chr_ram_size = 8 * 1024;
+        }
+        rom_header.timing = if rom_data[9] & 0x01 != 0 { TimingMode::Pal } else { TimingMode::Ntsc };
+        rom_header.console_type = if rom_data[7] & 0x01 != 0 {
+            ConsoleType::VsSystem { ppu_type: 0, hardware_type: 0 }
+        } else if rom_data[7] & 0x02 != 0 {
+            ConsoleType::Playchoice10
+        } else {
+            ConsoleType::Nes
+        };
     }
-}
\ No newline at end of file
+
+    Ok(rom_header)
+}
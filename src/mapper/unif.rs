@@ -0,0 +1,121 @@
+// UNIF (.unf) 格式：32字节文件头（"UNIF" + 4字节版本号 + 24字节保留），之后是一串数据块，
+// 每个块为 4字节ID + 4字节小端长度 + 数据。卡带类型由 MAPR 块中的电路板名给出，而不是 mapper 号。
+// https://www.nesdev.org/wiki/UNIF
+
+use super::{RomHeader, RomImage, TimingMode};
+use crate::{NesError, NesResult};
+
+const UNIF_HEADER_SIZE: usize = 32;
+
+// 电路板名的前缀只表示厂商/地区，去掉后再匹配
+const BOARD_PREFIXES: [&str; 7] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-", "KONAMI-"];
+
+// 把电路板名转换为模拟器支持的 mapper 号
+fn board_to_mapper(board: &str) -> Option<u16> {
+    let name = BOARD_PREFIXES
+        .iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(board);
+    match name {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => Some(0),
+        // MMC1 的各种电路板
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM" | "SKROM"
+        | "SLROM" | "SL1ROM" | "SL2ROM" | "SL3ROM" | "SLRROM" | "SNROM" | "SOROM" | "SUROM"
+        | "SXROM" => Some(1),
+        "CNROM" => Some(3),
+        _ => None,
+    }
+}
+
+pub fn parse_unif(rom_data: &[u8]) -> NesResult<RomImage> {
+    if rom_data.len() < UNIF_HEADER_SIZE {
+        return Err(NesError::TruncatedHeader(rom_data.len()));
+    }
+    if !rom_data.starts_with(b"UNIF") {
+        return Err(NesError::InvalidMagic);
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut rom_header = RomHeader::default();
+
+    let mut offset = UNIF_HEADER_SIZE;
+    while offset < rom_data.len() {
+        let chunk_header = rom_data
+            .get(offset..offset + 8)
+            .ok_or_else(|| NesError::InvalidUnif(format!("偏移{:X}处的块头不完整", offset)))?;
+        let id = String::from_utf8_lossy(&chunk_header[0..4]).to_string();
+        let length = u32::from_le_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]) as usize;
+        let data = rom_data
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(|| NesError::InvalidUnif(format!("{}块需要{}字节, 剩余{}字节", id, length, rom_data.len() - offset - 8)))?;
+        offset += 8 + length;
+
+        // PRG0..PRGF / CHR0..CHRF 按十六进制编号排列
+        let chunk_index = || (chunk_header[3] as char).to_digit(16).map(|index| index as usize);
+        match id.as_str() {
+            "MAPR" => {
+                let end = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+                board = Some(String::from_utf8_lossy(&data[..end]).trim().to_string());
+            }
+            "MIRR" => match data.first() {
+                Some(0) => rom_header.mirroring_type = 0,
+                Some(1) => rom_header.mirroring_type = 1,
+                Some(4) => rom_header.four_screen = true,
+                // 2/3 为单屏镜像，5 由 mapper 控制，保持默认值
+                _ => {}
+            },
+            "BATR" => rom_header.battery_backed_ram = true,
+            "TVCI" => {
+                rom_header.timing = match data.first() {
+                    Some(1) => TimingMode::Pal,
+                    Some(2) => TimingMode::MultiRegion,
+                    _ => TimingMode::Ntsc,
+                }
+            }
+            _ if id.starts_with("PRG") => {
+                if let Some(index) = chunk_index() {
+                    prg_chunks[index] = Some(data);
+                }
+            }
+            _ if id.starts_with("CHR") => {
+                if let Some(index) = chunk_index() {
+                    chr_chunks[index] = Some(data);
+                }
+            }
+            // NAME/READ/DINF/CTRL/PCKn/CCKn 等块与模拟无关
+            _ => {}
+        }
+    }
+
+    let board = board.ok_or_else(|| NesError::InvalidUnif("缺少 MAPR 块".to_string()))?;
+    let mapper_number = board_to_mapper(&board).ok_or_else(|| NesError::UnsupportedBoard(board.clone()))?;
+
+    let prg_rom: Vec<u8> = prg_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    let chr_rom: Vec<u8> = chr_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    if prg_rom.is_empty() {
+        return Err(NesError::InvalidUnif("缺少 PRG 块".to_string()));
+    }
+
+    rom_header.prg_rom_size = prg_rom.len();
+    rom_header.chr_rom_size = chr_rom.len();
+    rom_header.mapper_number = mapper_number;
+    // UNIF 不记录 RAM 大小，按 8KB PRG-RAM 处理，有电池时视为 NVRAM
+    if rom_header.battery_backed_ram {
+        rom_header.prg_nvram_size = 8 * 1024;
+    } else {
+        rom_header.prg_ram_size = 8 * 1024;
+    }
+    if chr_rom.is_empty() {
+        rom_header.chr_ram_size = 8 * 1024;
+    }
+    rom_header.unif_board = Some(board);
+
+    Ok(RomImage {
+        rom_header,
+        prg_rom,
+        chr_rom,
+        trainer: None,
+    })
+}
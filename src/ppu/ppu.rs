@@ -7,6 +7,8 @@ use std::thread;
 use crate::{
     bus::{Bus, RWMessage, RWResult, RWType},
     utils::{Frame, GlobalSignal},
+    utils::state::{StateReader, StateWriter},
+    NesResult,
 };
 
 pub struct Registers {
@@ -257,6 +259,37 @@ impl Ppu {
         // ... reset other fields
     }
 
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.section(b"PPU ");
+        writer.write_u64(self.cycles);
+        writer.write_u16(self.scanline);
+        writer.write_u16(self.dot);
+        writer.write_u8(self.ppustatus);
+        writer.write_bool(self.nmi_status);
+        writer.write_bool(self.new_frame);
+        writer.write_u64(self.frame_num);
+        writer.write_u16(self.current_tile_data);
+        writer.write_u16(self.tile_shift_registers[0]);
+        writer.write_u16(self.tile_shift_registers[1]);
+        // 保存画面，读档后暂停时也能显示
+        writer.write_bytes(&self.frame_color_index_cache);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> NesResult<()> {
+        reader.section(b"PPU ")?;
+        self.cycles = reader.read_u64()?;
+        self.scanline = reader.read_u16()?;
+        self.dot = reader.read_u16()?;
+        self.ppustatus = reader.read_u8()?;
+        self.nmi_status = reader.read_bool()?;
+        self.new_frame = reader.read_bool()?;
+        self.frame_num = reader.read_u64()?;
+        self.current_tile_data = reader.read_u16()?;
+        self.tile_shift_registers[0] = reader.read_u16()?;
+        self.tile_shift_registers[1] = reader.read_u16()?;
+        reader.read_into(&mut self.frame_color_index_cache)
+    }
+
     pub fn get_current_log(&mut self) -> String {
         format!(
             "PPU: cycles: {}, scanline: {}, dot: {}",
@@ -287,100 +320,47 @@ impl Ppu {
         self.current_tile_data = self.tile_shift_registers[0];
     }
 
-    fn fetch_tile_data(&mut self,tile_data_address: u16,palette_address: u16) -> Vec<[u8; 8]> {
-        // 读取图块数据,返回一个8x8的颜色索引数组
-        let mut tile_data = vec![[0; 8]; 8];    
-
-        for y in 0..8 {
-            let tail_data_low = self.read(tile_data_address + y);
-            let tail_data_high = self.read(tile_data_address + y + 8);
-            for x in 0..8 {
-                let low = (tail_data_low >> (7 - x)) & 1;
-                let high = (tail_data_high >> (7 - x)) & 1;
-                let color_index = (high << 1) | low;
-                let color = self.read(palette_address + color_index as u16);
-                tile_data[y as usize][x as usize] = color;
-            }
-        }
-        tile_data
-    }
-
-    fn get_nametable(&self, index: u8) -> [u8; 0x3c0] {
-        let nametable_base = match index {
-            0 => 0x2000,
-            1 => 0x2400,
-            2 => 0x2800,
-            3 => 0x2C00,
-            _ => unreachable!(),
-        };
-        let mut nametable = [0; 0x3c0];
-        for i in 0..0x3c0 {
-            nametable[i] = self.read(nametable_base + i as u16);
-        }
-        nametable
-    }
-
-    fn get_attribute_table(&self, index: u8) -> [u8; 0x40] {
-        let nametable_base = match index {
-            0 => 0x2000,
-            1 => 0x2400,
-            2 => 0x2800,
-            3 => 0x2C00,
-            _ => unreachable!(),
-        };
-        let mut attribute_table = [0; 0x40];
-        for i in 0..0x40 {
-            attribute_table[i] = self.read(nametable_base + 0x3c0 + i as u16);
-        }
-        attribute_table
+    // 按当前的 VRAM 和 OAM 渲染一条扫描线到 frame_color_index_cache
+    // 每条可见扫描线结束时调用，帧中途对 VRAM、OAM 或寄存器的改动会反映在之后的行上
+    fn render_scanline(&mut self, y: usize) {
+        self.render_background_line(y);
+        self.render_sprite_line(y);
     }
 
-    pub fn test_render_background(&mut self) {
+    fn render_background_line(&mut self, y: usize) {
         // 背景暴力渲染，直接输出当前name table的数据，scorll和mask都不管
-        // 一帧中pattern_table_base不会改变，所以这里直接写死
-        let name_table = self.get_nametable(0);
-        let attribute_table = self.get_attribute_table(0);
         let ctrl = self.read_reg(PpuRegister::PpuCtrl);
         let pattern_table_base = match ctrl >> 4 & 1 {
             0 => 0x0000,
             1 => 0x1000,
             _ => unreachable!(),
         };
+        let pattern_y = y / 8;
+        let fine_y = (y % 8) as u16;
 
-        let mut tile_data: [[u8; 8]; 8] = [[0; 8]; 8];
-        for i in 0..0x3c0 {
+        for pattern_x in 0..32 {
             // 获取tile 索引
-            let tile_index = name_table[i];
+            let tile_index = self.read(0x2000 + (pattern_y * 32 + pattern_x) as u16);
             // 获取调色板索引
-            let pattern_x = i % 32;
-            let pattern_y = i / 32;
-            let attribute = attribute_table[(pattern_x / 4) + (pattern_y / 4) * 8];
+            let attribute = self.read(0x23c0 + ((pattern_x / 4) + (pattern_y / 4) * 8) as u16);
             let palette_index =
                 (attribute >> (((pattern_x % 4) / 2) + (((pattern_y % 4) / 2) * 2)) * 2) & 0x3;
             let palette_address = 0x3f00 + palette_index as u16 * 4;
-            let tile_data_address = pattern_table_base + tile_index as u16 * 16;
-            // 获取tile数据
-            // let tail_data_color_index = self.read_tail_data(tile_data_address);
-
-            for y in 0..8 {
-                let tail_data_low = self.read(tile_data_address + y);
-                let tail_data_high = self.read(tile_data_address + y + 8);
-                for x in 0..8 {
-                    let low = (tail_data_low >> (7 - x)) & 1;
-                    let high = (tail_data_high >> (7 - x)) & 1;
-                    let color_index = (high << 1) | low;
-                    let color = self.read(palette_address + color_index as u16);
-                    tile_data[y as usize][x as usize] = color;
-                    let frame_x = pattern_x * 8 + x;
-                    let frame_y = pattern_y * 8 + y as usize;
-                    self.frame_color_index_cache[frame_y * 256 + frame_x] =
-                        tile_data[y as usize][x];
-                }
+            let tile_data_address = pattern_table_base + tile_index as u16 * 16 + fine_y;
+
+            let tail_data_low = self.read(tile_data_address);
+            let tail_data_high = self.read(tile_data_address + 8);
+            for x in 0..8 {
+                let low = (tail_data_low >> (7 - x)) & 1;
+                let high = (tail_data_high >> (7 - x)) & 1;
+                let color_index = (high << 1) | low;
+                let color = self.read(palette_address + color_index as u16);
+                self.frame_color_index_cache[y * 256 + pattern_x * 8 + x] = color;
             }
         }
     }
 
-    pub fn test_render_sprite(&mut self) {
+    fn render_sprite_line(&mut self, y: usize) {
         // PPU的OAM是一个256字节的内存，存储了屏幕上最多64个精灵的信息。每个精灵的信息占4个字节，分别是：
         // 每个精灵共4字节的属性, 共计64个精灵
         // 字节0: Y坐标-1
@@ -392,12 +372,20 @@ impl Ppu {
         // |*-- ----- 水平翻转：0-正常，1-翻转
         // *--- ----- 垂直翻转：0-正常，1-翻转
         // 字节3: X坐标
+        let ppuctrl = self.read_reg(PpuRegister::PpuCtrl);
+        let tall_sprites = ppuctrl >> 5 & 1 != 0;
+        let height = if tall_sprites { 16 } else { 8 };
+
+        // 倒序绘制，编号小的精灵覆盖在上面
         for i in (0..256).step_by(4).rev() {
             // 获取当前精灵的信息
-            let sprite_y = self.read_oam(i);
+            let sprite_y = self.read_oam(i) as usize;
+            if y < sprite_y || y >= sprite_y + height {
+                continue;
+            }
             let sprite_tile_index = self.read_oam(i + 1);
             let sprite_attributes = self.read_oam(i + 2);
-            let sprite_x = self.read_oam(i + 3);
+            let sprite_x = self.read_oam(i + 3) as usize;
 
             if sprite_attributes >> 5 & 1 != 0 {
                 // 精灵不可见
@@ -409,69 +397,47 @@ impl Ppu {
             // 调色板索引，每个精灵使用一个4色调色板，存储在0x3F10、0x3F14、0x3F18或0x3F1C的4个地址中
             let palette_address = 0x3F10 + ((sprite_attributes & 0x3) * 4) as u16;
 
-            let ppuctrl = self.read_reg(PpuRegister::PpuCtrl);
+            // 精灵内的行号，垂直翻转时从底部数
+            let mut row = (y - sprite_y) as u16;
+            if flip_vertically {
+                row = height as u16 - 1 - row;
+            }
 
-            let mut sprite_tile_data:Vec<[u8; 8]>;
-            if (ppuctrl >> 5 & 1) != 0 {
-                // 8x16模式下，精灵的模式索引是2个字节，其中低位字节指定上半部分的图案，高位字节指定下半部分的图案
-                // 由于每个图案是8x8，所以上下两个图案的地址是连续的
-                // 图案表的基地址
+            let tile_data_address = if tall_sprites {
+                // 8x16模式下，图案编号的最低位选择图案表，其余位选择上下相邻的两个图案
                 let pattern_table_base: u16 = match sprite_tile_index & 1 {
                     0 => 0x0000,
                     1 => 0x1000,
                     _ => unreachable!(),
                 };
-                let sprite_tile_data_address = pattern_table_base + (sprite_tile_index>>1) as u16 * 0x20;
-                sprite_tile_data = self.fetch_tile_data(sprite_tile_data_address, palette_address);
-                let mut sprite_tile_data_next = self.fetch_tile_data(sprite_tile_data_address + 0x10, palette_address);
-                sprite_tile_data.append(&mut sprite_tile_data_next);
+                pattern_table_base + (sprite_tile_index >> 1) as u16 * 0x20 + (row / 8) * 0x10 + row % 8
             } else {
                 // 8x8模式
-                // 图案表的基地址
                 let pattern_table_base = match ppuctrl >> 3 & 1 {
                     0 => 0x0000,
                     1 => 0x1000,
                     _ => unreachable!(),
                 };
-                // 获取精灵的图案数据地址
-                let sprite_tile_data_address = pattern_table_base + sprite_tile_index as u16 * 0x10;
-                sprite_tile_data = self.fetch_tile_data(sprite_tile_data_address, palette_address);
-            }
-            // 旋转
-            sprite_tile_data = self.rotate(sprite_tile_data, flip_horizontally, flip_vertically);
-            // 写入帧缓存 sprite_tile_data：Vec<[u8; 8]>
-            self.draw_sprite(sprite_x as usize, sprite_y as usize, sprite_tile_data)
-        
-        }
-    }
-
+                pattern_table_base + sprite_tile_index as u16 * 0x10 + row
+            };
 
-    fn draw_sprite(&mut self, sprite_x: usize, sprite_y: usize, sprite_tile_data: Vec<[u8; 8]>) {
-        for (y, row) in sprite_tile_data.iter().enumerate() {
-            for (x, &color_index) in row.iter().enumerate() {
+            let tail_data_low = self.read(tile_data_address);
+            let tail_data_high = self.read(tile_data_address + 8);
+            for x in 0..8 {
                 let frame_x = sprite_x + x;
-                let frame_y = sprite_y + y;
-                if frame_x < 256 && frame_y < 240 { // assuming your frame is 256x240
-                    self.frame_color_index_cache[frame_y * 256 + frame_x] = color_index;
+                if frame_x >= 256 {
+                    break;
                 }
+                let bit = if flip_horizontally { x } else { 7 - x };
+                let low = (tail_data_low >> bit) & 1;
+                let high = (tail_data_high >> bit) & 1;
+                let color_index = (high << 1) | low;
+                let color = self.read(palette_address + color_index as u16);
+                self.frame_color_index_cache[y * 256 + frame_x] = color;
             }
         }
     }
 
-    fn rotate(&mut self, mut tile_data: Vec<[u8; 8]>, flip_h:bool ,flip_v:bool) -> Vec<[u8; 8]> {
-        if flip_h {
-            for row in tile_data.iter_mut() {
-                row.reverse();
-            }
-        }
-    
-        if flip_v {
-            tile_data.reverse();
-        }
-    
-        tile_data
-    }
-
     fn set_nmi(&mut self, nmi: bool) {
         // todo： 优化，ppu只能设置nmi，不需要读取其他的
         // nmi 位在第2位
@@ -506,6 +472,10 @@ impl Ppu {
                 // 像素渲染
                 // self.render_pixel();
             }
+            (0..=239, 256) => {
+                // 这条扫描线的像素已经输出完毕
+                self.render_scanline(self.scanline as usize);
+            }
             (0..=239, 256..=319) => {
                 // 读取下一个tile
                 // self.fetch_tile();
@@ -522,8 +492,6 @@ impl Ppu {
             }
             (240, 0) => {
                 // 垂直空白扫描线
-                self.test_render_background();
-                self.test_render_sprite();
                 self.new_frame = true;
             }
             (241, 1) => {
@@ -1,4 +1,22 @@
 pub mod test_rom;
 // pub mod test_egui;
 // pub mod test_ppu;
-pub mod test_run;
\ No newline at end of file
+pub mod test_run;
+#[cfg(test)]
+mod test_mapper;
+#[cfg(test)]
+mod test_gamedb;
+#[cfg(test)]
+mod test_patch;
+#[cfg(test)]
+mod test_movie;
+#[cfg(test)]
+mod test_input;
+#[cfg(test)]
+mod test_interrupts;
+#[cfg(test)]
+mod test_unstable;
+#[cfg(test)]
+mod test_archive;
+#[cfg(test)]
+mod test_fds;
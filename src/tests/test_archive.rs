@@ -0,0 +1,74 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::emulator::Emulator;
+use crate::utils::archive::{list_rom_entries, read_rom_entry, MAX_ROM_SIZE};
+use crate::NesError;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fc_archive_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+    let mut zip = ZipWriter::new(std::fs::File::create(path).unwrap());
+    for (name, data) in files {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[test]
+fn zip_entries_keep_archive_order() {
+    let dir = temp_dir("order");
+    let path = dir.join("games.zip");
+    write_zip(
+        &path,
+        &[("b.nes", b"b"), ("readme.txt", b"r"), ("a.fds", b"a"), ("music.nsf", b"n"), ("sub/c.unf", b"c")],
+    );
+    let path = path.to_str().unwrap();
+    assert_eq!(list_rom_entries(path).unwrap(), ["b.nes", "a.fds", "sub/c.unf"]);
+    // 不指定条目时读取第一个
+    assert_eq!(read_rom_entry(path, None).unwrap(), ("b.nes".to_string(), b"b".to_vec()));
+    assert_eq!(read_rom_entry(path, Some("a.fds")).unwrap(), ("a.fds".to_string(), b"a".to_vec()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn oversized_gzip_is_rejected() {
+    let dir = temp_dir("gzip");
+    let path = dir.join("game.nes.gz");
+    let mut encoder = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::fast());
+    encoder.write_all(&vec![0; MAX_ROM_SIZE as usize + 1]).unwrap();
+    encoder.finish().unwrap();
+    let result = read_rom_entry(path.to_str().unwrap(), None);
+    assert!(matches!(result, Err(NesError::ArchiveError(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn games_in_one_zip_have_separate_state_slots() {
+    let dir = temp_dir("slots");
+    let path = dir.join("games.zip");
+    let rom = std::fs::read("rom/nestest.nes").unwrap();
+    write_zip(&path, &[("one.nes", &rom), ("sub/two.nes", &rom)]);
+    let path = path.to_str().unwrap();
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom_entry(path, Some("sub/two.nes")).unwrap();
+    emulator.save_state_slot(0).unwrap();
+    assert!(dir.join("games.zip#sub_two.nes.st0").is_file());
+    // 另一个游戏没有这个存档
+    emulator.load_rom(path).unwrap();
+    assert!(emulator.load_state_slot(0).is_err());
+    emulator.save_state_slot(0).unwrap();
+    assert!(dir.join("games.zip#one.nes.st0").is_file());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,93 @@
+use crate::bus::Bus;
+use crate::mapper::fds::{FDS_BIOS_SIZE, FDS_SIDE_SIZE};
+use crate::utils::state::{StateReader, StateWriter};
+
+// 只有磁盘信息块的一面
+fn disk_side() -> Vec<u8> {
+    let mut side = vec![0; FDS_SIDE_SIZE];
+    side[0] = 0x01;
+    side[1..15].copy_from_slice(b"*NINTENDO-HVC*");
+    side
+}
+
+fn fds_bus() -> Bus {
+    let mut bus = Bus::new();
+    bus.load_fds(disk_side(), vec![0; FDS_BIOS_SIZE], None).unwrap();
+    bus
+}
+
+#[test]
+fn sound_registers_follow_4023_bit1() {
+    let mut bus = fds_bus();
+    // 关闭声音寄存器时读写都无效
+    bus.cpu_write(0x4023, 0x01);
+    bus.cpu_write(0x4089, 0x80);
+    bus.cpu_write(0x4040, 0x3F);
+    assert_eq!(bus.cpu_read(0x4040), 0x00);
+    assert_eq!(bus.cpu_read(0x4090), 0x00);
+
+    bus.cpu_write(0x4023, 0x03);
+    assert_eq!(bus.cpu_read(0x4040), 0x40);
+    bus.cpu_write(0x4089, 0x80);
+    bus.cpu_write(0x4040, 0x3F);
+    assert_eq!(bus.cpu_read(0x4040), 0x7F);
+}
+
+#[test]
+fn fds_audio_is_mixed_into_samples() {
+    let mut bus = fds_bus();
+    for _ in 0..2048 {
+        bus.clock();
+    }
+    // 没有发声时输出为0
+    let samples = bus.take_audio_samples();
+    assert!(!samples.is_empty());
+    assert!(samples.iter().all(|&sample| sample == 0.0));
+
+    // 波表全为最大值，音量包络关闭、增益32，主音量 2/2
+    bus.cpu_write(0x4089, 0x80);
+    for addr in 0x4040..=0x407F {
+        bus.cpu_write(addr, 0x3F);
+    }
+    bus.cpu_write(0x4089, 0x00);
+    bus.cpu_write(0x4080, 0xA0);
+    bus.cpu_write(0x4082, 0xFF);
+    bus.cpu_write(0x4083, 0x0F);
+    // 波形回到第0步时才更新音量增益
+    for _ in 0..4096 {
+        bus.clock();
+    }
+    let samples = bus.take_audio_samples();
+    assert!(*samples.last().unwrap() > 0.0);
+    assert!(bus.take_audio_samples().is_empty());
+}
+
+#[test]
+fn state_saved_at_end_of_disk_can_be_loaded() {
+    let mut bus = fds_bus();
+    // 马达转动、读模式，开始扫描后一直读到磁盘末尾马达停止
+    bus.cpu_write(0x4025, 0x05);
+    let mut cycles = 0;
+    let mut scanned = false;
+    while cycles < 20_000_000 {
+        bus.clock();
+        cycles += 1;
+        let ready = bus.cpu_read(0x4032) & 0x02 == 0;
+        if scanned && !ready {
+            break;
+        }
+        scanned |= ready;
+    }
+    assert!(scanned && bus.cpu_read(0x4032) & 0x02 != 0);
+    let mut writer = StateWriter::new(0);
+    bus.save_state(&mut writer);
+    let state = writer.finish();
+
+    let mut other = fds_bus();
+    other.load_state(&mut StateReader::new(&state).unwrap()).unwrap();
+    // 重新转动马达时从头开始读
+    other.cpu_write(0x4025, 0x05);
+    for _ in 0..100 {
+        other.clock();
+    }
+}
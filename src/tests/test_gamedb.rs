@@ -0,0 +1,151 @@
+use crate::emulator::Emulator;
+use crate::mapper::gamedb::GameDb;
+use crate::mapper::{parse_rom, ConsoleType, RomHeader, TimingMode};
+use crate::NesError;
+
+fn hashes(data: &[u8]) -> (String, String) {
+    let crc32 = format!("{:08X}", crc32fast::hash(data));
+    let sha1 = sha1_smol::Sha1::from(data).digest().to_string().to_uppercase();
+    (crc32, sha1)
+}
+
+#[test]
+fn builtin_db_matches_nestest() {
+    let rom = std::fs::read("rom/nestest.nes").unwrap();
+    let image = parse_rom(&rom).unwrap();
+    let game_db = GameDb::builtin();
+    let game = game_db.lookup(&image.rom_body()).expect("nestest 应在内置数据库中");
+    assert_eq!(game.title, "nestest");
+    assert_eq!(game.board, "NES-NROM-128");
+    assert_eq!(game.mapper_number, Some(0));
+}
+
+#[test]
+fn csv_entry_overrides_header() {
+    let body = b"prg and chr";
+    let (crc32, sha1) = hashes(body);
+    let mut game_db = GameDb::new();
+    let csv = format!(
+        "# 注释\ncrc32,sha1,mapper,submapper,mirroring,battery,prg_ram,prg_nvram,chr_ram,chr_nvram,region,board,title\n\
+         {},{},1,5,V,1,,8192,,,1,SNROM,Title, With Comma\n",
+        crc32, sha1
+    );
+    assert_eq!(game_db.load_csv(&csv).unwrap(), 1);
+
+    let game = game_db.lookup(body).unwrap();
+    assert_eq!(game.title, "Title, With Comma");
+    let mut rom_header = RomHeader { mapper_number: 3, mirroring_type: 0, prg_ram_size: 8192, ..Default::default() };
+    game.apply(&mut rom_header);
+    assert_eq!(rom_header.mapper_number, 1);
+    assert_eq!(rom_header.submapper_number, 5);
+    assert_eq!(rom_header.mirroring_type, 1);
+    assert!(!rom_header.four_screen);
+    assert!(rom_header.battery_backed_ram);
+    // 空字段不覆盖
+    assert_eq!(rom_header.prg_ram_size, 8192);
+    assert_eq!(rom_header.prg_nvram_size, 8192);
+    assert_eq!(rom_header.timing, TimingMode::Pal);
+}
+
+#[test]
+fn sha1_mismatch_is_not_a_match() {
+    let body = b"prg and chr";
+    let (crc32, _) = hashes(body);
+    let mut game_db = GameDb::new();
+    game_db.load_csv(&format!("{},{},0,,,,,,,,,NROM,Other\n", crc32, "0".repeat(40))).unwrap();
+    assert!(game_db.lookup(body).is_none());
+    // 只有 CRC32 的条目只按 CRC32 匹配
+    game_db.load_csv(&format!("{},,0,,,,,,,,,NROM,Crc Only\n", crc32)).unwrap();
+    assert_eq!(game_db.lookup(body).unwrap().title, "Crc Only");
+}
+
+#[test]
+fn later_entry_replaces_same_hash() {
+    let (crc32, sha1) = hashes(b"x");
+    let mut game_db = GameDb::new();
+    game_db.load_csv(&format!("{},{},0,,,,,,,,,NROM,Old\n", crc32, sha1)).unwrap();
+    game_db.load_csv(&format!("{},{},1,,,,,,,,,SNROM,New\n", crc32, sha1)).unwrap();
+    assert_eq!(game_db.len(), 1);
+    assert_eq!(game_db.lookup(b"x").unwrap().title, "New");
+}
+
+#[test]
+fn invalid_csv_is_an_error() {
+    let mut game_db = GameDb::new();
+    assert!(matches!(game_db.load_csv("1234,,0\n"), Err(NesError::InvalidGameDb(_))));
+    assert!(matches!(game_db.load_csv("XYZ,,0,,,,,,,,,NROM,Bad\n"), Err(NesError::InvalidGameDb(_))));
+    assert!(matches!(game_db.load_csv("1234,,0,,X,,,,,,,NROM,Bad\n"), Err(NesError::InvalidGameDb(_))));
+    assert!(matches!(game_db.load_csv("1234,ABC,0,,,,,,,,,NROM,Bad\n"), Err(NesError::InvalidGameDb(_))));
+}
+
+#[test]
+fn xml_entry_overrides_header() {
+    let body = b"vs system game";
+    let (crc32, sha1) = hashes(body);
+    let xml = format!(
+        r#"<nes20db>
+<game>
+	<!-- Vs. Game (USA).nes -->
+	<rom size="14" crc32="{}" sha1="{}"/>
+	<pcb mapper="99" submapper="0" mirroring="4" battery="0"/>
+	<prgram size="2048"/>
+	<chrnvram size="8192"/>
+	<console type="1" region="0"/>
+	<vs hardware="1" ppu="3"/>
+	<expansion type="8"/>
+</game>
+<game>
+	<!-- No Hash.nes -->
+	<pcb mapper="0"/>
+</game>
+</nes20db>"#,
+        crc32, sha1
+    );
+    let mut game_db = GameDb::new();
+    assert_eq!(game_db.load_xml(&xml).unwrap(), 1);
+
+    let game = game_db.lookup(body).unwrap();
+    assert_eq!(game.title, "Vs. Game (USA)");
+    let mut rom_header = RomHeader::default();
+    game.apply(&mut rom_header);
+    assert_eq!(rom_header.mapper_number, 99);
+    assert!(rom_header.four_screen);
+    assert!(!rom_header.battery_backed_ram);
+    assert_eq!(rom_header.prg_ram_size, 2048);
+    assert_eq!(rom_header.chr_nvram_size, 8192);
+    assert_eq!(rom_header.timing, TimingMode::Ntsc);
+    assert_eq!(rom_header.console_type, ConsoleType::VsSystem { ppu_type: 3, hardware_type: 1 });
+    assert_eq!(rom_header.default_expansion_device, 8);
+}
+
+#[test]
+fn invalid_xml_attribute_is_an_error() {
+    let xml = r#"<game><!-- Bad.nes --><rom crc32="nothex"/></game>"#;
+    assert!(matches!(GameDb::new().load_xml(xml), Err(NesError::InvalidGameDb(_))));
+    let xml = r#"<game><!-- Bad.nes --><rom crc32="00000000"/><prgram size="lots"/></game>"#;
+    assert!(matches!(GameDb::new().load_xml(xml), Err(NesError::InvalidGameDb(_))));
+}
+
+#[test]
+fn external_db_next_to_rom_is_loaded() {
+    let dir = std::env::temp_dir().join(format!("fc_gamedb_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = std::fs::read("rom/nestest.nes").unwrap();
+    let (crc32, sha1) = hashes(&parse_rom(&rom).unwrap().rom_body());
+    std::fs::write(dir.join("game.nes"), &rom).unwrap();
+    std::fs::write(
+        dir.join("gamedb.csv"),
+        format!("{},{},0,0,H,0,,,,,0,NES-NROM-128,external nestest\n", crc32, sha1),
+    )
+    .unwrap();
+
+    let mut emulator = Emulator::new();
+    let builtin = emulator.game_db.len();
+    let path = dir.join("game.nes");
+    emulator.load_rom(path.to_str().unwrap()).unwrap();
+    assert_eq!(emulator.game_info().unwrap().title, "external nestest");
+    // 再次加载不会重复读入
+    emulator.load_rom(path.to_str().unwrap()).unwrap();
+    assert_eq!(emulator.game_db.len(), builtin);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
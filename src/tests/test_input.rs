@@ -0,0 +1,15 @@
+use crate::emulator::Emulator;
+
+#[test]
+fn out_of_range_players_are_ignored() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom("rom/nestest.nes").unwrap();
+    emulator.set_buttons(0, 0x01);
+    emulator.set_buttons(4, 0xFF);
+    emulator.set_buttons(usize::MAX, 0xFF);
+    emulator.input_filter_mut().set_turbo(4, 0, 2);
+    emulator.input_filter_mut().set_turbo(0, 8, 2);
+    assert_eq!(emulator.input_filter().turbo(4, 0), 0);
+    assert_eq!(emulator.input_filter().turbo(0, 8), 0);
+    emulator.run_frame();
+}
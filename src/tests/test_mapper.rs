@@ -0,0 +1,203 @@
+use crate::bus::Bus;
+use crate::mapper::gamedb::GameDb;
+use crate::mapper::{create_mapper, parse_rom, parse_rom_header, TimingMode};
+use crate::NesError;
+
+// 16字节的 NES 2.0 头，后面不带数据
+fn nes2_header(prg_lsb: u8, chr_lsb: u8, size_msb: u8) -> Vec<u8> {
+    let mut header = vec![b'N', b'E', b'S', 0x1A, prg_lsb, chr_lsb, 0, 0x08, 0, size_msb, 0, 0, 0, 0, 0, 0];
+    header.resize(16, 0);
+    header
+}
+
+#[test]
+fn nes2_exponent_size_overflow_is_invalid_rom() {
+    // 指数 63，乘数 7：2^63 * 7 溢出
+    let rom = nes2_header(0xFF, 0, 0x0F);
+    assert!(matches!(parse_rom(&rom), Err(NesError::InvalidRom)));
+    let rom = nes2_header(0, 0xFF, 0xF0);
+    assert!(matches!(parse_rom(&rom), Err(NesError::InvalidRom)));
+}
+
+#[test]
+fn huge_nes2_rom_size_is_truncated() {
+    // 2^63 字节：大小本身不溢出，文件不够长时返回截断错误
+    let rom = nes2_header(0xFC, 0, 0x0F);
+    assert!(matches!(parse_rom(&rom), Err(NesError::TruncatedPrgRom { expected: 0x8000_0000_0000_0000, actual: 0 })));
+}
+
+#[test]
+fn truncated_prg_rom_is_an_error() {
+    let mut rom = nes2_header(1, 0, 0);
+    rom.extend(vec![0; 100]);
+    assert!(matches!(parse_rom(&rom), Err(NesError::TruncatedPrgRom { expected: 0x4000, actual: 100 })));
+}
+
+#[test]
+fn unsupported_mapper_is_an_error() {
+    let mut rom = nes2_header(1, 1, 0);
+    rom[6] = 0x40; // mapper 4
+    rom.extend(vec![0; 0x4000 + 0x2000]);
+    let image = parse_rom(&rom).unwrap();
+    let result = create_mapper(&image.rom_header, image.prg_rom, image.chr_rom);
+    assert!(matches!(result, Err(NesError::UnsupportedMapper { mapper: 4, submapper: 0 })));
+}
+
+// iNES 1.0 的 16KB PRG + 8KB CHR，带 512 字节 trainer，trainer 的第 i 字节为 i
+fn ines_with_trainer(mapper: u8) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x04 | (mapper << 4), 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    rom.extend((0..512).map(|i| i as u8));
+    rom.extend(vec![0; 0x4000 + 0x2000]);
+    rom
+}
+
+#[test]
+fn trainer_is_loaded_at_7000_and_after_reset() {
+    for mapper in [0, 3] {
+        let mut bus = Bus::new();
+        bus.load_rom(ines_with_trainer(mapper), &GameDb::new()).unwrap();
+        assert_eq!(bus.cpu_read_debug(0x7000), 0x00, "mapper {}", mapper);
+        assert_eq!(bus.cpu_read_debug(0x7001), 0x01, "mapper {}", mapper);
+        assert_eq!(bus.cpu_read_debug(0x71FF), 0xFF, "mapper {}", mapper);
+        bus.cpu_write(0x7001, 0x55);
+        assert_eq!(bus.cpu_read_debug(0x7001), 0x55, "mapper {}", mapper);
+        bus.reset();
+        assert_eq!(bus.cpu_read_debug(0x7001), 0x01, "mapper {}", mapper);
+    }
+}
+
+#[test]
+fn cnrom_without_trainer_has_no_prg_ram() {
+    // NES 2.0 头声明没有 PRG-RAM (iNES 1.0 的第8字节为0时按 8KB 处理)
+    let mut rom = nes2_header(1, 1, 0);
+    rom[6] = 0x30;
+    rom.extend(vec![0; 0x4000 + 0x2000]);
+    let mut bus = Bus::new();
+    bus.load_rom(rom, &GameDb::new()).unwrap();
+    bus.cpu_write(0x7000, 0x55);
+    assert_eq!(bus.cpu_read_debug(0x7000), 0x00);
+}
+
+#[test]
+fn truncated_trainer_is_an_error() {
+    let mut rom = ines_with_trainer(0);
+    rom.truncate(16 + 100);
+    assert!(matches!(parse_rom(&rom), Err(NesError::TruncatedTrainer { expected: 512, actual: 100 })));
+}
+
+// 第7~15字节是老 dump 工具的签名，第7字节的高4位不能当作 mapper 号
+#[test]
+fn diskdude_header_is_cleaned() {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x10];
+    rom.extend(b"DiskDude!");
+    let header = parse_rom_header(&rom).unwrap();
+    assert_eq!(header.mapper_number, 1);
+}
+
+#[test]
+fn garbage_in_header_tail_is_cleaned() {
+    let rom = [b'N', b'E', b'S', 0x1A, 1, 1, 0x10, 0x20, 0, 0, 0, 0, 0, b'X', b'Y', 0];
+    let header = parse_rom_header(&rom).unwrap();
+    assert_eq!(header.mapper_number, 1);
+}
+
+#[test]
+fn clean_ines_header_is_kept() {
+    let rom = [b'N', b'E', b'S', 0x1A, 1, 1, 0x10, 0x20, 0, 0, 0, 0, 0, 0, 0, 0];
+    let header = parse_rom_header(&rom).unwrap();
+    assert_eq!(header.mapper_number, 0x21);
+}
+
+#[test]
+fn nes2_header_is_not_cleaned() {
+    // NES 2.0 的第12~15字节有意义，不能当成垃圾清零
+    let rom = [b'N', b'E', b'S', 0x1A, 1, 1, 0x10, 0x28, 0, 0, 0, 0, 0x01, 0, 0, 0];
+    let header = parse_rom_header(&rom).unwrap();
+    assert_eq!(header.mapper_number, 0x21);
+}
+
+// 32字节 UNIF 头加上给定的块
+fn unif(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut rom = b"UNIF".to_vec();
+    rom.extend(7u32.to_le_bytes());
+    rom.resize(32, 0);
+    for (id, data) in chunks {
+        rom.extend(*id);
+        rom.extend((data.len() as u32).to_le_bytes());
+        rom.extend(*data);
+    }
+    rom
+}
+
+#[test]
+fn unif_chunks_are_parsed() {
+    let rom = unif(&[
+        (b"MAPR", b"NES-SNROM\0"),
+        (b"NAME", b"test\0"),
+        (b"PRG1", &[2; 0x4000]),
+        (b"PRG0", &[1; 0x4000]),
+        (b"CHR0", &[3; 0x2000]),
+        (b"MIRR", &[1]),
+        (b"BATR", &[1]),
+        (b"TVCI", &[1]),
+    ]);
+    let image = parse_rom(&rom).unwrap();
+    let header = &image.rom_header;
+    assert_eq!(header.mapper_number, 1);
+    assert_eq!(header.unif_board.as_deref(), Some("NES-SNROM"));
+    assert_eq!(header.mirroring_type, 1);
+    assert!(header.battery_backed_ram);
+    assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0, 0x2000));
+    assert_eq!(header.timing, TimingMode::Pal);
+    // PRG 块按编号而不是文件中的顺序拼接
+    assert_eq!(image.prg_rom.len(), 0x8000);
+    assert_eq!((image.prg_rom[0], image.prg_rom[0x4000]), (1, 2));
+    assert_eq!(image.chr_rom, vec![3; 0x2000]);
+    assert!(create_mapper(&image.rom_header, image.prg_rom, image.chr_rom).is_ok());
+}
+
+#[test]
+fn unif_without_chr_uses_chr_ram() {
+    let rom = unif(&[(b"MAPR", b"HVC-CNROM"), (b"PRG0", &[0; 0x8000]), (b"MIRR", &[4])]);
+    let header = parse_rom(&rom).unwrap().rom_header;
+    assert_eq!(header.mapper_number, 3);
+    assert!(header.four_screen);
+    assert_eq!(header.chr_ram_size, 0x2000);
+    assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0x2000, 0));
+}
+
+#[test]
+fn unif_board_names_map_to_mappers() {
+    for (board, mapper) in [("NES-NROM-256", 0), ("NROM", 0), ("HVC-SLROM", 1), ("UNL-CNROM", 3)] {
+        let rom = unif(&[(b"MAPR", board.as_bytes()), (b"PRG0", &[0; 0x4000])]);
+        assert_eq!(parse_rom(&rom).unwrap().rom_header.mapper_number, mapper, "{}", board);
+    }
+}
+
+#[test]
+fn unknown_unif_board_is_an_error() {
+    let rom = unif(&[(b"MAPR", b"UNL-FOO\0"), (b"PRG0", &[0; 0x4000])]);
+    assert!(matches!(parse_rom(&rom), Err(NesError::UnsupportedBoard(board)) if board == "UNL-FOO"));
+}
+
+#[test]
+fn unif_without_mapr_or_prg_is_an_error() {
+    let rom = unif(&[(b"PRG0", &[0; 0x4000])]);
+    assert!(matches!(parse_rom(&rom), Err(NesError::InvalidUnif(_))));
+    let rom = unif(&[(b"MAPR", b"NES-NROM-128"), (b"CHR0", &[0; 0x2000])]);
+    assert!(matches!(parse_rom(&rom), Err(NesError::InvalidUnif(_))));
+}
+
+#[test]
+fn truncated_unif_chunks_are_errors() {
+    // 块数据比声明的长度短
+    let mut rom = unif(&[(b"MAPR", b"NES-NROM-128"), (b"PRG0", &[0; 0x4000])]);
+    rom.truncate(rom.len() - 1);
+    assert!(matches!(parse_rom(&rom), Err(NesError::InvalidUnif(_))));
+    // 块头不完整
+    let mut rom = unif(&[(b"MAPR", b"NES-NROM-128"), (b"PRG0", &[0; 0x4000])]);
+    rom.extend(b"CHR0");
+    assert!(matches!(parse_rom(&rom), Err(NesError::InvalidUnif(_))));
+    // 文件头不完整
+    assert!(matches!(parse_rom(b"UNIF\x07\0\0\0"), Err(NesError::TruncatedHeader(8))));
+}
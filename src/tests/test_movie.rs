@@ -0,0 +1,52 @@
+use crate::emulator::Emulator;
+use crate::utils::movie::md5;
+
+fn hex(digest: [u8; 16]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// RFC 1321 附录 A.5 的测试向量
+#[test]
+fn md5_rfc1321_test_suite() {
+    let cases: [(&[u8], &str); 7] = [
+        (b"", "d41d8cd98f00b204e9800998ecf8427e"),
+        (b"a", "0cc175b9c0f1b6a831c399e269772661"),
+        (b"abc", "900150983cd24fb0d6963f7d28e17f72"),
+        (b"message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+        (b"abcdefghijklmnopqrstuvwxyz", "c3fcd3d76192e4007dfb496cca67e13b"),
+        (b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789", "d174ab98d277d9f5a5611c2c9f419d9f"),
+        (b"12345678901234567890123456789012345678901234567890123456789012345678901234567890", "57edf4a22be3c955ac49da2e2107b67a"),
+    ];
+    for (message, expected) in cases {
+        assert_eq!(hex(md5(message)), expected);
+    }
+}
+
+#[test]
+fn rewinding_counts_one_rerecord_per_session() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom("rom/nestest.nes").unwrap();
+    emulator.start_recording(true).unwrap();
+    for _ in 0..20 {
+        emulator.run_frame();
+    }
+    let rerecords = |emulator: &Emulator| emulator.movie().unwrap().rerecord_count;
+
+    // 按住回溯键连续回退
+    for _ in 0..4 {
+        assert!(emulator.rewind_step());
+    }
+    assert_eq!(rerecords(&emulator), 1);
+
+    // 运行一帧后再回溯是新的一次
+    emulator.run_frame();
+    assert!(emulator.rewind_step());
+    assert!(emulator.rewind_step());
+    assert_eq!(rerecords(&emulator), 2);
+
+    // 手动读档总是计数
+    let state = emulator.save_state();
+    emulator.load_state(&state).unwrap();
+    emulator.load_state(&state).unwrap();
+    assert_eq!(rerecords(&emulator), 4);
+}
@@ -0,0 +1,172 @@
+use crate::utils::patch::{apply_patch, create_ips};
+use crate::NesError;
+
+// UPS/BPS 变长整数编码
+fn push_number(patch: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let bits = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | bits);
+            return;
+        }
+        patch.push(bits);
+        value -= 1;
+    }
+}
+
+// 追加 源CRC32/目标CRC32/补丁CRC32
+fn push_checksums(patch: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let patch_crc32 = crc32fast::hash(patch);
+    patch.extend_from_slice(&patch_crc32.to_le_bytes());
+}
+
+fn is_invalid_patch<T: std::fmt::Debug>(result: crate::NesResult<T>) -> bool {
+    matches!(result, Err(NesError::InvalidPatch(_)))
+}
+
+#[test]
+fn ips_records_rle_and_truncate() {
+    let rom = [0u8; 8];
+    let mut patch = b"PATCH".to_vec();
+    patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]); // $01: AA BB
+    patch.extend_from_slice(&[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x06, 0xCC]); // RLE $04: 6 * CC，超出原长度时扩展
+    patch.extend_from_slice(b"EOF");
+    assert_eq!(apply_patch(&rom, &patch).unwrap(), [0, 0xAA, 0xBB, 0, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC]);
+    // EOF 后的截断长度
+    patch.extend_from_slice(&[0x00, 0x00, 0x03]);
+    assert_eq!(apply_patch(&rom, &patch).unwrap(), [0, 0xAA, 0xBB]);
+}
+
+#[test]
+fn ips_without_eof_is_truncated() {
+    let mut patch = b"PATCH".to_vec();
+    patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x04, 0xAA]);
+    assert!(is_invalid_patch(apply_patch(&[0; 4], &patch)));
+}
+
+#[test]
+fn create_ips_round_trip() {
+    let source = vec![0u8; 0x454F50];
+    let mut target = source.clone();
+    target[3] = 1;
+    // 偏移 0x454F46 的记录不能被读成 EOF
+    target[0x454F46] = 2;
+    target.truncate(0x454F48);
+    let patch = create_ips(&source, &target).unwrap();
+    assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+}
+
+#[test]
+fn ups_xor_blocks() {
+    let source = b"hello world".to_vec();
+    let target = b"jello_world!".to_vec();
+    let mut patch = b"UPS1".to_vec();
+    push_number(&mut patch, source.len());
+    push_number(&mut patch, target.len());
+    push_number(&mut patch, 0); // 跳过0字节后异或
+    patch.extend_from_slice(&[b'h' ^ b'j', 0x00]);
+    push_number(&mut patch, 3); // 结束的0也占一个字节，跳到下标5
+    patch.extend_from_slice(&[b' ' ^ b'_', 0x00]);
+    push_number(&mut patch, 4); // 新增的最后一个字节，下标11
+    patch.extend_from_slice(&[b'!', 0x00]);
+    push_checksums(&mut patch, &source, &target);
+    assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+}
+
+// BPS：SourceRead、TargetRead、SourceCopy 和重叠的 TargetCopy
+fn bps_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    push_number(&mut patch, source.len());
+    push_number(&mut patch, target.len());
+    push_number(&mut patch, 0);
+    push_number(&mut patch, (2 - 1) << 2); // SourceRead 2: "AB"
+    push_number(&mut patch, ((1 - 1) << 2) | 1); // TargetRead 1: "x"
+    patch.push(b'x');
+    push_number(&mut patch, ((3 - 1) << 2) | 2); // SourceCopy 3，相对 +4: "EFG"
+    push_number(&mut patch, 4 << 1);
+    push_number(&mut patch, ((4 - 1) << 2) | 3); // TargetCopy 4，相对 +1，与输出重叠: "BxBx"
+    push_number(&mut patch, 1 << 1);
+    push_number(&mut patch, ((2 - 1) << 2) | 2); // SourceCopy 2，相对 -7 回到 0: "AB"
+    push_number(&mut patch, (7 << 1) | 1);
+    push_checksums(&mut patch, source, target);
+    patch
+}
+
+#[test]
+fn bps_commands() {
+    let source = b"ABCDEFGH";
+    let target = b"ABxEFGBxEFAB";
+    assert_eq!(apply_patch(source, &bps_patch(source, target)).unwrap(), target);
+}
+
+#[test]
+fn patch_crc_mismatch() {
+    let source = b"ABCDEFGH";
+    let mut patch = bps_patch(source, b"ABxEFGBxEFAB");
+    patch[5] ^= 1;
+    assert!(matches!(apply_patch(source, &patch), Err(NesError::PatchChecksumMismatch { name: "补丁", .. })));
+}
+
+#[test]
+fn source_crc_mismatch() {
+    let patch = bps_patch(b"ABCDEFGH", b"ABxEFGBxEFAB");
+    assert!(matches!(apply_patch(b"ABCDEFGX", &patch), Err(NesError::PatchChecksumMismatch { name: "源 ROM", .. })));
+}
+
+#[test]
+fn target_crc_mismatch() {
+    let source = b"ABCDEFGH";
+    let patch = bps_patch(source, b"ABxEFGBxEFAC");
+    assert!(matches!(apply_patch(source, &patch), Err(NesError::PatchChecksumMismatch { name: "目标 ROM", .. })));
+}
+
+// 只有头和校验和的 BPS，body 为头之后的内容
+fn bps_with_body(source: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    patch.extend_from_slice(body);
+    push_checksums(&mut patch, source, &[]);
+    patch
+}
+
+#[test]
+fn oversized_numbers_are_invalid() {
+    // 11个无结束标记的 0x7F 字节，超过 usize
+    let body = [0x7F; 11];
+    assert!(is_invalid_patch(apply_patch(b"", &bps_with_body(b"", &body))));
+    // 目标大小过大
+    let mut body = vec![0x80];
+    push_number(&mut body, usize::MAX / 2);
+    assert!(is_invalid_patch(apply_patch(b"", &bps_with_body(b"", &body))));
+}
+
+#[test]
+fn bps_huge_relative_offset_is_invalid() {
+    let source = b"ABCD";
+    let mut body = Vec::new();
+    push_number(&mut body, source.len());
+    push_number(&mut body, 4);
+    push_number(&mut body, 0);
+    push_number(&mut body, ((4 - 1) << 2) | 2); // SourceCopy，相对偏移接近 isize::MAX
+    push_number(&mut body, usize::MAX - 1);
+    assert!(is_invalid_patch(apply_patch(source, &bps_with_body(source, &body))));
+    // 两次大的偏移相加溢出
+    let mut body = Vec::new();
+    push_number(&mut body, source.len());
+    push_number(&mut body, 8);
+    push_number(&mut body, 0);
+    push_number(&mut body, ((1 - 1) << 2) | 3);
+    push_number(&mut body, usize::MAX - 1);
+    assert!(is_invalid_patch(apply_patch(source, &bps_with_body(source, &body))));
+}
+
+#[test]
+fn bps_metadata_length_overflow_is_invalid() {
+    let mut body = Vec::new();
+    push_number(&mut body, 0);
+    push_number(&mut body, 0);
+    push_number(&mut body, usize::MAX);
+    assert!(is_invalid_patch(apply_patch(b"", &bps_with_body(b"", &body))));
+}
@@ -14,7 +14,6 @@ pub fn run_test() {
     let rom_path = "rom/nestest.nes";
     let mut emulator = Emulator::new();
     let pip_ppu_frameout = emulator.pip_ppu_frame.1.clone();
-    let pip_input_stream_in = emulator.pip_input_stream.0.clone();
     emulator.load_rom(rom_path);
     let a = thread::spawn(move || loop {
         emulator.clock(); // 在此处运行模拟器的单步执行功能
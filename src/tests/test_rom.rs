@@ -20,7 +20,7 @@ pub fn run_test() {
     let log_path = "rom/nestest.log";
     let mut emulator =Emulator::new();
     // emulator.start();
-    emulator.load_rom(rom_path);
+    emulator.load_rom(rom_path).expect("无法加载 ROM 文件");
     emulator.cpu.registers.pc = 0xC000; // 测试时，从特定地址开始运行
     emulator.cpu.registers.p = 0x24; // 测试时，从特定地址开始运行
     emulator.bus.borrow_mut().apu_io_registers.ram = [0xff; 0x20];
@@ -35,7 +35,8 @@ pub fn run_test() {
 
         let mut emulator_log_line = emulator.get_log(); // 获取模拟器的日志
 
-        emulator_log_line.push_str(format!(" PPU:{:3},{:3} CYC:{}", emulator.ppu.scanline, emulator.ppu.dot, emulator.cpu.cpu_cycle).as_str());
+        let (scanline, dot) = (emulator.ppu.borrow().scanline, emulator.ppu.borrow().dot);
+        emulator_log_line.push_str(format!(" PPU:{:3},{:3} CYC:{}", scanline, dot, emulator.cpu.cpu_cycle).as_str());
         // println!("获取日志结束");
         println!("{}", emulator_log_line);
 
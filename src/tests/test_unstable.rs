@@ -0,0 +1,175 @@
+use crate::emulator::Emulator;
+
+// 代码放在 $0600，数据放在 $0200~$03FF
+const CODE: u16 = 0x0600;
+
+struct Registers {
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+}
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.load_rom("rom/nestest.nes").unwrap();
+    emulator
+}
+
+// 执行一条指令，返回用掉的 CPU 周期
+fn run(emulator: &mut Emulator, code: &[u8], registers: Registers) -> u64 {
+    {
+        let mut bus = emulator.bus.borrow_mut();
+        for (i, &byte) in code.iter().enumerate() {
+            bus.cpu_write(CODE + i as u16, byte);
+        }
+    }
+    let cpu = &mut emulator.cpu;
+    cpu.registers.pc = CODE;
+    cpu.registers.a = registers.a;
+    cpu.registers.x = registers.x;
+    cpu.registers.y = registers.y;
+    cpu.registers.sp = registers.sp;
+    cpu.registers.p = 0x24;
+    let start = cpu.cpu_cycle;
+    emulator.cpu_step();
+    assert_eq!(emulator.cpu.registers.pc, CODE + code.len() as u16);
+    emulator.cpu.cpu_cycle - start
+}
+
+fn read(emulator: &Emulator, address: u16) -> u8 {
+    emulator.bus.borrow().cpu_read_debug(address)
+}
+
+#[test]
+fn shx_ands_with_high_byte_plus_one() {
+    let mut emulator = emulator();
+    // SHX $0210,Y：$0211 = X & $03
+    let cycles = run(&mut emulator, &[0x9E, 0x10, 0x02], Registers { a: 0, x: 0xA5, y: 0x01, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0211), 0x01);
+    assert_eq!(cycles, 5);
+}
+
+#[test]
+fn shx_page_cross_replaces_high_byte() {
+    let mut emulator = emulator();
+    // SHX $02F0,Y 跨页：值为 X & $03 = $01，地址高字节换成这个值 -> $01E0
+    let cycles = run(&mut emulator, &[0x9E, 0xF0, 0x02], Registers { a: 0, x: 0xA5, y: 0xF0, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x01E0), 0x01);
+    assert_eq!(read(&emulator, 0x03E0), 0x00);
+    assert_eq!(cycles, 5);
+}
+
+#[test]
+fn shy_ands_with_high_byte_plus_one() {
+    let mut emulator = emulator();
+    // SHY $0220,X：$0222 = Y & $03
+    let cycles = run(&mut emulator, &[0x9C, 0x20, 0x02], Registers { a: 0, x: 0x02, y: 0xFF, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0222), 0x03);
+    assert_eq!(cycles, 5);
+}
+
+#[test]
+fn shy_page_cross_replaces_high_byte() {
+    let mut emulator = emulator();
+    // SHY $02FF,X 跨页：值为 Y & $03 = $02，地址 $0311 -> $0211
+    let cycles = run(&mut emulator, &[0x9C, 0xFF, 0x02], Registers { a: 0, x: 0x12, y: 0x06, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0211), 0x02);
+    assert_eq!(read(&emulator, 0x0311), 0x00);
+    assert_eq!(cycles, 5);
+}
+
+#[test]
+fn ahx_absolute_y_stores_a_and_x() {
+    let mut emulator = emulator();
+    // AHX $0230,Y：$0234 = A & X & $03
+    let cycles = run(&mut emulator, &[0x9F, 0x30, 0x02], Registers { a: 0x0F, x: 0x3E, y: 0x04, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0234), 0x02);
+    assert_eq!(cycles, 5);
+
+    // AHX $02FC,Y 跨页：值为 $02，地址 $0300 -> $0200
+    let cycles = run(&mut emulator, &[0x9F, 0xFC, 0x02], Registers { a: 0x0F, x: 0x3E, y: 0x04, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0200), 0x02);
+    assert_eq!(read(&emulator, 0x0300), 0x00);
+    assert_eq!(cycles, 5);
+}
+
+#[test]
+fn ahx_indirect_y_uses_pointer_high_byte() {
+    let mut emulator = emulator();
+    emulator.bus.borrow_mut().cpu_write(0x0010, 0x40);
+    emulator.bus.borrow_mut().cpu_write(0x0011, 0x02);
+    // AHX ($10),Y：指针 $0240，$0241 = A & X & $03
+    let cycles = run(&mut emulator, &[0x93, 0x10], Registers { a: 0xFF, x: 0xFF, y: 0x01, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0241), 0x03);
+    assert_eq!(cycles, 6);
+
+    // 跨页：指针 $02F0 + $20 = $0310，值为 $03 -> $0310
+    emulator.bus.borrow_mut().cpu_write(0x0010, 0xF0);
+    let cycles = run(&mut emulator, &[0x93, 0x10], Registers { a: 0xFF, x: 0xFF, y: 0x20, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0310), 0x03);
+    assert_eq!(cycles, 6);
+
+    // 跨页且值改变高字节：值为 $01 -> $0110
+    let cycles = run(&mut emulator, &[0x93, 0x10], Registers { a: 0x0D, x: 0xF1, y: 0x20, sp: 0xFD });
+    assert_eq!(read(&emulator, 0x0110), 0x01);
+    assert_eq!(cycles, 6);
+}
+
+#[test]
+fn tas_sets_sp_and_stores_it() {
+    let mut emulator = emulator();
+    // TAS $0250,Y：SP = A & X = $33，$0260 = $33 & $03
+    let cycles = run(&mut emulator, &[0x9B, 0x50, 0x02], Registers { a: 0xF3, x: 0x3F, y: 0x10, sp: 0xFD });
+    assert_eq!(emulator.cpu.registers.sp, 0x33);
+    assert_eq!(read(&emulator, 0x0260), 0x03);
+    assert_eq!(cycles, 5);
+
+    // TAS $04F8,Y 跨页：值为 $33 & $05 = $01，地址 $0508 -> $0108
+    let cycles = run(&mut emulator, &[0x9B, 0xF8, 0x04], Registers { a: 0xF3, x: 0x3F, y: 0x10, sp: 0xFD });
+    assert_eq!(emulator.cpu.registers.sp, 0x33);
+    assert_eq!(read(&emulator, 0x0108), 0x01);
+    assert_eq!(cycles, 5);
+}
+
+#[test]
+fn las_ands_memory_with_sp() {
+    let mut emulator = emulator();
+    emulator.bus.borrow_mut().cpu_write(0x0300, 0x77);
+    emulator.bus.borrow_mut().cpu_write(0x0380, 0x80);
+    // LAS $0300,Y：A = X = SP = $77 & $F0
+    let cycles = run(&mut emulator, &[0xBB, 0x00, 0x03], Registers { a: 0, x: 0, y: 0x00, sp: 0xF0 });
+    assert_eq!((emulator.cpu.registers.a, emulator.cpu.registers.x, emulator.cpu.registers.sp), (0x70, 0x70, 0x70));
+    assert_eq!(cycles, 4);
+
+    // LAS $02F0,Y 跨页多一个周期，结果为负数
+    let cycles = run(&mut emulator, &[0xBB, 0xF0, 0x02], Registers { a: 0, x: 0, y: 0x90, sp: 0xFF });
+    assert_eq!((emulator.cpu.registers.a, emulator.cpu.registers.x, emulator.cpu.registers.sp), (0x80, 0x80, 0x80));
+    assert_eq!(emulator.cpu.registers.p & 0x82, 0x80);
+    assert_eq!(cycles, 5);
+}
+
+#[test]
+fn xaa_uses_magic_constant() {
+    let mut emulator = emulator();
+    // XAA #$3C：A = (A | $EE) & X & $3C
+    run(&mut emulator, &[0x8B, 0x3C], Registers { a: 0x00, x: 0x0F, y: 0, sp: 0xFD });
+    assert_eq!(emulator.cpu.registers.a, 0x0C);
+    emulator.set_xaa_magic(0xFF);
+    run(&mut emulator, &[0x8B, 0x3C], Registers { a: 0x00, x: 0x0F, y: 0, sp: 0xFD });
+    assert_eq!(emulator.cpu.registers.a, 0x0F & 0x3C);
+    emulator.set_xaa_magic(0x00);
+    run(&mut emulator, &[0x8B, 0xFF], Registers { a: 0x00, x: 0xFF, y: 0, sp: 0xFD });
+    assert_eq!(emulator.cpu.registers.a, 0x00);
+    assert_eq!(emulator.cpu.registers.p & 0x02, 0x02);
+}
+
+#[test]
+fn xaa_magic_survives_power_cycle() {
+    let mut emulator = emulator();
+    emulator.set_xaa_magic(0xFF);
+    // 从上电开始录像会重新上电
+    emulator.start_recording(false).unwrap();
+    run(&mut emulator, &[0x8B, 0xFF], Registers { a: 0x00, x: 0xFF, y: 0, sp: 0xFD });
+    assert_eq!(emulator.cpu.registers.a, 0xFF);
+}
@@ -0,0 +1,79 @@
+// 从 zip/gzip 压缩包中读取 ROM
+// zip 中可能有多个文件，只挑选扩展名为 ROM_EXTENSIONS 的条目；gzip 只包含一个文件
+// 解压后的大小限制在 MAX_ROM_SIZE 以内，防止压缩炸弹占满内存
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::{NesError, NesResult};
+
+pub const ROM_EXTENSIONS: [&str; 4] = ["nes", "unf", "unif", "fds"];
+pub const ARCHIVE_EXTENSIONS: [&str; 2] = ["zip", "gz"];
+// 解压后的最大字节数，远大于实际存在的 ROM
+pub const MAX_ROM_SIZE: u64 = 64 * 1024 * 1024;
+
+fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    Path::new(name)
+        .extension()
+        .map_or(false, |extension| extensions.iter().any(|e| extension.eq_ignore_ascii_case(e)))
+}
+
+pub fn is_archive(path: &str) -> bool {
+    has_extension(path, &ARCHIVE_EXTENSIONS)
+}
+
+fn zip_error(error: zip::result::ZipError) -> NesError {
+    NesError::ArchiveError(error.to_string())
+}
+
+// 最多读取 MAX_ROM_SIZE 字节，超出时报错
+fn read_limited(reader: impl Read, name: &str) -> NesResult<Vec<u8>> {
+    let mut buffer = Vec::new();
+    reader.take(MAX_ROM_SIZE + 1).read_to_end(&mut buffer)?;
+    if buffer.len() as u64 > MAX_ROM_SIZE {
+        return Err(NesError::ArchiveError(format!("{} 解压后超过 {} 字节", name, MAX_ROM_SIZE)));
+    }
+    Ok(buffer)
+}
+
+// 压缩包中可以加载的 ROM 条目名，按压缩包中的顺序排列
+pub fn list_rom_entries(path: &str) -> NesResult<Vec<String>> {
+    if has_extension(path, &["gz"]) {
+        // gzip 里只有一个文件，文件名就是去掉 .gz 的部分
+        let name = Path::new(path).file_stem().unwrap_or_default().to_string_lossy().to_string();
+        return Ok(vec![name]);
+    }
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(zip_error)?;
+    // file_names 遍历的是哈希表，顺序不固定，按索引读取才能保持压缩包中的顺序
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index).map_err(zip_error)?;
+        if has_extension(file.name(), &ROM_EXTENSIONS) {
+            entries.push(file.name().to_string());
+        }
+    }
+    Ok(entries)
+}
+
+// 读取压缩包中的 ROM，entry 为 None 时取第一个 ROM 条目，返回实际读取的条目名和数据
+pub fn read_rom_entry(path: &str, entry: Option<&str>) -> NesResult<(String, Vec<u8>)> {
+    if has_extension(path, &["gz"]) {
+        let name = list_rom_entries(path)?.remove(0);
+        let buffer = read_limited(GzDecoder::new(File::open(path)?), &name)?;
+        return Ok((name, buffer));
+    }
+    let name = match entry {
+        Some(entry) => entry.to_string(),
+        None => list_rom_entries(path)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| NesError::NoRomInArchive(path.to_string()))?,
+    };
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(zip_error)?;
+    let buffer = read_limited(archive.by_name(&name).map_err(zip_error)?, &name)?;
+    Ok((name, buffer))
+}
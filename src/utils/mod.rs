@@ -3,6 +3,12 @@
 
 // pub use self::window::Window;
 mod palettes;
+pub mod patch;
+pub mod archive;
+pub mod state;
+pub mod rewind;
+pub mod movie;
+pub mod wav;
 
 
 pub use palettes::Palettes;
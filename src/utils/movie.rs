@@ -0,0 +1,296 @@
+// 输入录像，使用 FCEUX 的 .fm2 文本格式
+// 文件头为 "键 值" 行，之后每帧一行: |命令|端口0|端口1|端口2|
+// 使用 Four Score 时为: |命令|1P|2P|3P|4P|
+// 手柄按键按 RLDUTSBA 顺序写出，'.' 表示未按下
+// https://fceux.com/web/help/fm2.html
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use crate::{NesError, NesResult};
+
+// FM2 中手柄按键的顺序，第 i 个字符对应按键字节的第 7-i 位
+const BUTTON_CHARS: &[u8; 8] = b"RLDUTSBA";
+
+// 每帧的命令位
+pub const COMMAND_SOFT_RESET: u8 = 0x01;
+pub const COMMAND_POWER: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    Finished, // 播放到结尾，不再覆盖输入
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub port0: u8, // 按键字节，第0位为A，第7位为右
+    pub port1: u8,
+    pub port2: u8, // 四人适配器的 3P/4P
+    pub port3: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Movie {
+    pub mode: MovieMode,
+    pub read_only: bool, // 只读时读档不会截断录像
+    pub rerecord_count: u32,
+    pub pal: bool,
+    pub fds: bool,
+    pub port1: bool, // 端口1是否接了手柄
+    pub fourscore: bool, // 四人适配器，每帧有4个手柄
+    pub rom_filename: String,
+    pub rom_checksum: [u8; 16], // ROM 的 MD5
+    pub guid: String,
+    pub comments: Vec<String>,
+    pub savestate: Option<Vec<u8>>, // 从即时存档开始录制时的存档，否则从上电开始
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new(rom_filename: String, rom_checksum: [u8; 16], savestate: Option<Vec<u8>>) -> Self {
+        Movie {
+            mode: MovieMode::Recording,
+            read_only: false,
+            rerecord_count: 0,
+            pal: false,
+            fds: false,
+            port1: true,
+            fourscore: false,
+            rom_filename,
+            rom_checksum,
+            guid: new_guid(),
+            comments: Vec::new(),
+            savestate,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> NesResult<Self> {
+        let mut movie = Movie::new(String::new(), [0; 16], None);
+        movie.mode = MovieMode::Playing;
+        movie.read_only = true;
+        movie.port1 = false;
+        let mut version_found = false;
+        let mut port0 = true;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            let invalid = |message: &str| NesError::InvalidMovie(format!("第{}行: {}", line_number + 1, message));
+            if line.starts_with('|') {
+                movie.frames.push(parse_frame(line).ok_or_else(|| invalid("输入格式错误"))?);
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" => {
+                    if value != "3" {
+                        return Err(invalid("只支持 version 3"));
+                    }
+                    version_found = true;
+                }
+                "rerecordCount" => movie.rerecord_count = value.parse().map_err(|_| invalid("rerecordCount 不是数字"))?,
+                "palFlag" => movie.pal = value == "1",
+                "FDS" => movie.fds = value == "1",
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => {
+                    let checksum = value
+                        .strip_prefix("base64:")
+                        .and_then(|value| BASE64.decode(value).ok())
+                        .and_then(|bytes| <[u8; 16]>::try_from(bytes).ok())
+                        .ok_or_else(|| invalid("romChecksum 格式错误"))?;
+                    movie.rom_checksum = checksum;
+                }
+                "guid" => movie.guid = value.to_string(),
+                "comment" => movie.comments.push(value.to_string()),
+                "savestate" => {
+                    let state = value.strip_prefix("base64:").unwrap_or(value);
+                    movie.savestate = Some(BASE64.decode(state).map_err(|_| invalid("savestate 格式错误"))?);
+                }
+                "port0" => port0 = value == "1",
+                "port1" => movie.port1 = value == "1",
+                "fourscore" => movie.fourscore = value == "1",
+                // emuVersion/microphone/NewPPU 等不影响回放
+                _ => {}
+            }
+        }
+        if !version_found {
+            return Err(NesError::InvalidMovie("缺少 version".to_string()));
+        }
+        // 目前只支持标准手柄或 Four Score
+        if !port0 && !movie.fourscore {
+            return Err(NesError::InvalidMovie("只支持端口0上的标准手柄".to_string()));
+        }
+        if !movie.fourscore {
+            for frame in movie.frames.iter_mut() {
+                frame.port2 = 0;
+                frame.port3 = 0;
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        let mut header = |key: &str, value: &str| {
+            text.push_str(key);
+            text.push(' ');
+            text.push_str(value);
+            text.push('\n');
+        };
+        header("version", "3");
+        header("emuVersion", "22020");
+        header("rerecordCount", &self.rerecord_count.to_string());
+        header("palFlag", if self.pal { "1" } else { "0" });
+        header("romFilename", &self.rom_filename);
+        header("romChecksum", &format!("base64:{}", BASE64.encode(self.rom_checksum)));
+        header("guid", &self.guid);
+        header("fourscore", if self.fourscore { "1" } else { "0" });
+        header("microphone", "0");
+        header("port0", "1");
+        header("port1", if self.port1 { "1" } else { "0" });
+        header("port2", "0");
+        header("FDS", if self.fds { "1" } else { "0" });
+        header("NewPPU", "0");
+        for comment in &self.comments {
+            header("comment", comment);
+        }
+        if let Some(state) = &self.savestate {
+            header("savestate", &format!("base64:{}", BASE64.encode(state)));
+        }
+        for frame in &self.frames {
+            text.push_str(&format_frame(frame, self.port1, self.fourscore));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+fn parse_frame(line: &str) -> Option<MovieFrame> {
+    let mut fields = line.split('|').skip(1);
+    let commands = fields.next()?;
+    let commands = if commands.is_empty() { 0 } else { commands.parse().ok()? };
+    let port0 = parse_buttons(fields.next()?);
+    // 没有接设备的端口为空，没有 Four Score 时端口2为扩展口，不是手柄
+    let port1 = fields.next().map_or(0, parse_buttons);
+    let port2 = fields.next().map_or(0, parse_buttons);
+    let port3 = fields.next().map_or(0, parse_buttons);
+    Some(MovieFrame { commands, port0, port1, port2, port3 })
+}
+
+fn parse_buttons(field: &str) -> u8 {
+    let mut buttons = 0;
+    for (i, &button) in field.as_bytes().iter().take(8).enumerate() {
+        if button != b'.' && button != b' ' {
+            buttons |= 0x80 >> i;
+        }
+    }
+    buttons
+}
+
+fn format_buttons(buttons: u8) -> String {
+    BUTTON_CHARS
+        .iter()
+        .enumerate()
+        .map(|(i, &button)| if buttons & (0x80 >> i) != 0 { button as char } else { '.' })
+        .collect()
+}
+
+fn format_frame(frame: &MovieFrame, port1: bool, fourscore: bool) -> String {
+    if fourscore {
+        return format!(
+            "|{}|{}|{}|{}|{}|",
+            frame.commands,
+            format_buttons(frame.port0),
+            format_buttons(frame.port1),
+            format_buttons(frame.port2),
+            format_buttons(frame.port3)
+        );
+    }
+    let port1 = if port1 { format_buttons(frame.port1) } else { String::new() };
+    format!("|{}|{}|{}||", frame.commands, format_buttons(frame.port0), port1)
+}
+
+// FCEUX 的 romChecksum 只计算 PRG 和 CHR，不包括 iNES 头和 trainer
+pub fn rom_checksum(rom: &[u8]) -> [u8; 16] {
+    if rom.starts_with(b"NES\x1A") && rom.len() >= 16 {
+        let body_start = if rom[6] & 0x04 != 0 { 16 + 512 } else { 16 };
+        return md5(rom.get(body_start..).unwrap_or(&[]));
+    }
+    md5(rom)
+}
+
+// FCEUX 只要求 guid 唯一，用当前时间生成
+fn new_guid() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos());
+    let bytes = md5(&nanos.to_le_bytes());
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+// FM2 的 romChecksum 为 MD5，https://www.ietf.org/rfc/rfc1321.txt
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+        5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+        4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+        6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    // floor(abs(sin(i + 1)) * 2^32)
+    const CONSTANTS: [u32; 64] = [
+        0xD76AA478, 0xE8C7B756, 0x242070DB, 0xC1BDCEEE, 0xF57C0FAF, 0x4787C62A, 0xA8304613, 0xFD469501,
+        0x698098D8, 0x8B44F7AF, 0xFFFF5BB1, 0x895CD7BE, 0x6B901122, 0xFD987193, 0xA679438E, 0x49B40821,
+        0xF61E2562, 0xC040B340, 0x265E5A51, 0xE9B6C7AA, 0xD62F105D, 0x02441453, 0xD8A1E681, 0xE7D3FBC8,
+        0x21E1CDE6, 0xC33707D6, 0xF4D50D87, 0x455A14ED, 0xA9E3E905, 0xFCEFA3F8, 0x676F02D9, 0x8D2A4C8A,
+        0xFFFA3942, 0x8771F681, 0x6D9D6122, 0xFDE5380C, 0xA4BEEA44, 0x4BDECFA9, 0xF6BB4B60, 0xBEBFBC70,
+        0x289B7EC6, 0xEAA127FA, 0xD4EF3085, 0x04881D05, 0xD9D4D039, 0xE6DB99E5, 0x1FA27CF8, 0xC4AC5665,
+        0xF4292244, 0x432AFF97, 0xAB9423A7, 0xFC93A039, 0x655B59C3, 0x8F0CCC92, 0xFFEFF47D, 0x85845DD1,
+        0x6FA87E4F, 0xFE2CE6E0, 0xA3014314, 0x4E0811A1, 0xF7537E82, 0xBD3AF235, 0x2AD7D2BB, 0xEB86D391,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476];
+    for chunk in message.chunks(64) {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(chunk.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(CONSTANTS[i])
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0; 16];
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
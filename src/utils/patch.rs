@@ -0,0 +1,266 @@
+// 软补丁：在内存中对 ROM 数据应用 IPS/UPS/BPS 补丁，不修改原文件
+// IPS: https://zerosoft.zophar.net/ips.php
+// UPS/BPS: byuu 的补丁格式，末尾12字节为 源CRC32/目标CRC32/补丁CRC32，应用前后都要校验
+
+use crate::{NesError, NesResult};
+
+// UPS/BPS 声明的目标大小上限，防止损坏的补丁申请过大的内存
+const MAX_TARGET_SIZE: usize = 0x1000_0000;
+
+// 根据补丁文件头自动判断格式
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> NesResult<Vec<u8>> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(NesError::InvalidPatch("无法识别的补丁格式".to_string()))
+    }
+}
+
+// 生成把 source 变为 target 的 IPS 补丁，用于保存 FDS 磁盘写入
+pub fn create_ips(source: &[u8], target: &[u8]) -> NesResult<Vec<u8>> {
+    if target.len() > 0x100_0000 {
+        return Err(NesError::InvalidPatch("IPS 只支持16MB以内的文件".to_string()));
+    }
+    let mut patch = b"PATCH".to_vec();
+    let mut offset = 0;
+    while offset < target.len() {
+        if source.get(offset) == Some(&target[offset]) {
+            offset += 1;
+            continue;
+        }
+        // 偏移 0x454F46 会被当作 "EOF"，从前一个字节开始写
+        let start = if offset == 0x454F46 { offset - 1 } else { offset };
+        let mut end = offset;
+        while end < target.len() && end - start < 0xFFFF && source.get(end) != Some(&target[end]) {
+            end += 1;
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((end - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&target[start..end]);
+        offset = end;
+    }
+    patch.extend_from_slice(b"EOF");
+    if target.len() < source.len() {
+        patch.extend_from_slice(&(target.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(patch)
+}
+
+// 按顺序读取补丁数据
+struct PatchReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], offset: usize) -> Self {
+        PatchReader { data, offset }
+    }
+
+    fn truncated() -> NesError {
+        NesError::InvalidPatch("补丁数据不完整".to_string())
+    }
+
+    fn overflow() -> NesError {
+        NesError::InvalidPatch("补丁中的数值溢出".to_string())
+    }
+
+    fn read_u8(&mut self) -> NesResult<u8> {
+        let byte = *self.data.get(self.offset).ok_or_else(Self::truncated)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, length: usize) -> NesResult<&'a [u8]> {
+        let end = self.offset.checked_add(length).ok_or_else(Self::truncated)?;
+        let bytes = self.data.get(self.offset..end).ok_or_else(Self::truncated)?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    // 大端整数（IPS）
+    fn read_be(&mut self, length: usize) -> NesResult<usize> {
+        Ok(self.read_bytes(length)?.iter().fold(0, |acc, &byte| (acc << 8) | byte as usize))
+    }
+
+    // UPS/BPS 的变长整数：每字节低7位，最高位为1表示结束，每多一个字节要额外加上 shift
+    fn read_number(&mut self) -> NesResult<usize> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read_u8()?;
+            value = ((byte & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or_else(Self::overflow)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(Self::overflow)?;
+            value = value.checked_add(shift).ok_or_else(Self::overflow)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> NesResult<Vec<u8>> {
+    let mut target = rom.to_vec();
+    let mut reader = PatchReader::new(patch, 5);
+    loop {
+        if reader.data.get(reader.offset..reader.offset + 3) == Some(b"EOF") {
+            reader.offset += 3;
+            break;
+        }
+        let offset = reader.read_be(3)?;
+        let size = reader.read_be(2)?;
+        // size 为0时是 RLE 记录：2字节长度 + 1字节填充值
+        let (length, rle_value) = if size == 0 {
+            (reader.read_be(2)?, Some(reader.read_u8()?))
+        } else {
+            (size, None)
+        };
+        if target.len() < offset + length {
+            target.resize(offset + length, 0);
+        }
+        match rle_value {
+            Some(value) => target[offset..offset + length].fill(value),
+            None => target[offset..offset + length].copy_from_slice(reader.read_bytes(length)?),
+        }
+    }
+    // EOF 之后可选的3字节截断长度
+    if let Ok(truncate) = reader.read_be(3) {
+        target.truncate(truncate);
+    }
+    Ok(target)
+}
+
+// 读取并校验 UPS/BPS 末尾的三个 CRC32，返回（源CRC32，目标CRC32）
+fn read_checksums(rom: &[u8], patch: &[u8]) -> NesResult<(u32, u32)> {
+    if patch.len() < 16 {
+        return Err(PatchReader::truncated());
+    }
+    let footer = &patch[patch.len() - 12..];
+    let read_u32 = |offset: usize| u32::from_le_bytes([footer[offset], footer[offset + 1], footer[offset + 2], footer[offset + 3]]);
+    let (source_crc32, target_crc32, patch_crc32) = (read_u32(0), read_u32(4), read_u32(8));
+    verify_checksum("补丁", patch_crc32, &patch[..patch.len() - 4])?;
+    verify_checksum("源 ROM", source_crc32, rom)?;
+    Ok((source_crc32, target_crc32))
+}
+
+fn verify_checksum(name: &'static str, expected: u32, data: &[u8]) -> NesResult<()> {
+    let actual = crc32fast::hash(data);
+    if actual != expected {
+        return Err(NesError::PatchChecksumMismatch { name, expected, actual });
+    }
+    Ok(())
+}
+
+fn read_target_size(reader: &mut PatchReader) -> NesResult<usize> {
+    let target_size = reader.read_number()?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(NesError::InvalidPatch(format!("目标大小{}字节过大", target_size)));
+    }
+    Ok(target_size)
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> NesResult<Vec<u8>> {
+    let (_, target_crc32) = read_checksums(rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = PatchReader::new(&patch[..end], 4);
+    let _source_size = reader.read_number()?;
+    let target_size = read_target_size(&mut reader)?;
+
+    // 目标先复制源数据，再按块异或
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut position: usize = 0;
+    while reader.offset < end {
+        position = position.checked_add(reader.read_number()?).ok_or_else(PatchReader::overflow)?;
+        loop {
+            let xor = reader.read_u8()?;
+            if xor == 0 {
+                position = position.saturating_add(1);
+                break;
+            }
+            if let Some(byte) = target.get_mut(position) {
+                *byte ^= xor;
+            }
+            position = position.saturating_add(1);
+        }
+    }
+    verify_checksum("目标 ROM", target_crc32, &target)?;
+    Ok(target)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> NesResult<Vec<u8>> {
+    let (_, target_crc32) = read_checksums(rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = PatchReader::new(&patch[..end], 4);
+    let _source_size = reader.read_number()?;
+    let target_size = read_target_size(&mut reader)?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+
+    let out_of_range = || NesError::InvalidPatch("BPS 复制越界".to_string());
+    let mut target = vec![0; target_size];
+    let mut output_offset: usize = 0;
+    let mut source_relative_offset: isize = 0;
+    let mut target_relative_offset: isize = 0;
+    // 相对偏移：最低位为符号位，累加到当前的相对位置上
+    let add_relative = |reader: &mut PatchReader, position: isize| -> NesResult<isize> {
+        let data = reader.read_number()?;
+        let offset = (data >> 1) as isize;
+        let offset = if data & 1 != 0 { -offset } else { offset };
+        position.checked_add(offset).ok_or_else(PatchReader::overflow)
+    };
+    while reader.offset < end {
+        let data = reader.read_number()?;
+        let command = data & 0x03;
+        let length = (data >> 2) + 1;
+        let output_end = output_offset.checked_add(length).ok_or_else(out_of_range)?;
+        if output_end > target_size {
+            return Err(out_of_range());
+        }
+        match command {
+            // SourceRead：从源的相同位置复制
+            0 => {
+                let source = rom.get(output_offset..output_end).ok_or_else(out_of_range)?;
+                target[output_offset..output_end].copy_from_slice(source);
+            }
+            // TargetRead：从补丁中复制
+            1 => {
+                target[output_offset..output_end].copy_from_slice(reader.read_bytes(length)?);
+            }
+            // SourceCopy：从源的相对位置复制
+            2 => {
+                source_relative_offset = add_relative(&mut reader, source_relative_offset)?;
+                let start = usize::try_from(source_relative_offset).map_err(|_| out_of_range())?;
+                let source = start
+                    .checked_add(length)
+                    .and_then(|source_end| rom.get(start..source_end))
+                    .ok_or_else(out_of_range)?;
+                target[output_offset..output_end].copy_from_slice(source);
+                // length 不超过 target_size，不会溢出
+                source_relative_offset += length as isize;
+            }
+            // TargetCopy：从已输出的目标中复制，允许重叠，需要逐字节复制
+            _ => {
+                target_relative_offset = add_relative(&mut reader, target_relative_offset)?;
+                for i in 0..length {
+                    let start = usize::try_from(target_relative_offset).map_err(|_| out_of_range())?;
+                    if start >= output_offset + i {
+                        return Err(out_of_range());
+                    }
+                    target[output_offset + i] = target[start];
+                    target_relative_offset += 1;
+                }
+            }
+        }
+        output_offset = output_end;
+    }
+    verify_checksum("目标 ROM", target_crc32, &target)?;
+    Ok(target)
+}
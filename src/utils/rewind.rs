@@ -0,0 +1,111 @@
+// 回溯缓冲区：每隔若干帧保存一次即时存档
+// 只有最新的快照完整保存，更早的快照保存为与后一个快照的异或差异并压缩，
+// 相邻快照之间大部分字节相同，异或后几乎全是0，压缩后很小
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+pub struct RewindBuffer {
+    interval: u32, // 每多少帧保存一次
+    capacity: usize, // 最多保留的快照数
+    frames_since_capture: u32,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>, // 从旧到新，每项为 压缩(后一个快照 ^ 该快照)
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    // 写入 Vec 不会失败
+    encoder.write_all(data).expect("压缩回溯快照失败");
+    encoder.finish().expect("压缩回溯快照失败")
+}
+
+fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    let mut buffer = Vec::new();
+    DeflateDecoder::new(data).read_to_end(&mut buffer).ok()?;
+    Some(buffer)
+}
+
+impl RewindBuffer {
+    // seconds 秒的历史，按60帧每秒计算
+    pub fn new(seconds: u32, interval: u32) -> Self {
+        let interval = interval.max(1);
+        RewindBuffer {
+            interval,
+            capacity: (seconds * 60 / interval).max(1) as usize,
+            frames_since_capture: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn configure(&mut self, seconds: u32, interval: u32) {
+        let latest = self.latest.take();
+        let deltas = std::mem::take(&mut self.deltas);
+        *self = RewindBuffer::new(seconds, interval);
+        // 保留已有的历史，超出新容量的部分丢弃
+        self.latest = latest;
+        self.deltas = deltas;
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.frames_since_capture = 0;
+    }
+
+    // 当前保存的快照数
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    // 每帧调用一次，返回这一帧是否需要保存快照
+    pub fn frame_elapsed(&mut self) -> bool {
+        self.frames_since_capture += 1;
+        if self.frames_since_capture >= self.interval {
+            self.frames_since_capture = 0;
+            return true;
+        }
+        false
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            if latest.len() == state.len() {
+                self.deltas.push_back(compress(&xor(&state, &latest)));
+            } else {
+                // 换了游戏，之前的历史无法再用
+                self.deltas.clear();
+            }
+        }
+        self.latest = Some(state);
+        self.trim();
+    }
+
+    // 取出最新的快照，前一个快照成为最新
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.latest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.latest = decompress(&delta).map(|delta| xor(&state, &delta));
+            if self.latest.is_none() {
+                self.deltas.clear();
+            }
+        }
+        Some(state)
+    }
+
+    fn trim(&mut self) {
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+}
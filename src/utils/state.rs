@@ -0,0 +1,133 @@
+// 即时存档的二进制格式
+// 文件头: "FCST" + 4字节版本号 + 4字节 ROM CRC32，之后各部件按固定顺序写入，每段以4字节标签开头
+// 所有整数为小端，变长数据前有4字节长度
+
+use crate::{NesError, NesResult};
+
+pub const STATE_MAGIC: &[u8; 4] = b"FCST";
+// 格式改变时加1，旧版本的存档不再兼容
+pub const STATE_VERSION: u32 = 13;
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(rom_crc32: u32) -> Self {
+        let mut writer = StateWriter { data: Vec::new() };
+        writer.data.extend_from_slice(STATE_MAGIC);
+        writer.write_u32(STATE_VERSION);
+        writer.write_u32(rom_crc32);
+        writer
+    }
+
+    // 段标签，读取时用于发现错位
+    pub fn section(&mut self, tag: &[u8; 4]) {
+        self.data.extend_from_slice(tag);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    pub rom_crc32: u32,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> NesResult<Self> {
+        if !data.starts_with(STATE_MAGIC) {
+            return Err(NesError::InvalidState("不是即时存档文件".to_string()));
+        }
+        let mut reader = StateReader { data, offset: STATE_MAGIC.len(), rom_crc32: 0 };
+        let version = reader.read_u32()?;
+        if version != STATE_VERSION {
+            return Err(NesError::InvalidState(format!("存档版本为{}, 当前版本为{}", version, STATE_VERSION)));
+        }
+        reader.rom_crc32 = reader.read_u32()?;
+        Ok(reader)
+    }
+
+    fn take(&mut self, length: usize) -> NesResult<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + length)
+            .ok_or_else(|| NesError::InvalidState("存档数据不完整".to_string()))?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    pub fn section(&mut self, tag: &[u8; 4]) -> NesResult<()> {
+        if self.take(4)? != tag {
+            return Err(NesError::InvalidState(format!("缺少 {} 段", String::from_utf8_lossy(tag).trim())));
+        }
+        Ok(())
+    }
+
+    pub fn read_u8(&mut self) -> NesResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> NesResult<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> NesResult<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> NesResult<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_u64(&mut self) -> NesResult<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_bytes(&mut self) -> NesResult<&'a [u8]> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    // 读入定长的缓冲区，长度不符说明存档和当前卡带不对应
+    pub fn read_into(&mut self, buffer: &mut [u8]) -> NesResult<()> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(NesError::InvalidState(format!("需要{}字节, 存档中为{}字节", buffer.len(), bytes.len())));
+        }
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+}
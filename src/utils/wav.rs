@@ -0,0 +1,73 @@
+// 磁带使用的 WAV 文件，只支持未压缩的 PCM (8位无符号或16位有符号)
+// 多声道时只取第一个声道
+// http://soundfile.sapp.org/doc/WaveFormat/
+
+use crate::{NesError, NesResult};
+
+// 读取 WAV 文件，返回单声道16位采样和采样率
+pub fn read_wav(data: &[u8]) -> NesResult<(Vec<i16>, u32)> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(NesError::InvalidWav("不是 RIFF/WAVE 文件".to_string()));
+    }
+    let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+
+    let mut format = None; // (声道数, 采样率, 位数)
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = u32_at(offset + 4) as usize;
+        let body = offset + 8;
+        let end = body.checked_add(size).filter(|end| *end <= data.len());
+        match id {
+            b"fmt " => {
+                if size < 16 || end.is_none() {
+                    return Err(NesError::InvalidWav("fmt 块不完整".to_string()));
+                }
+                if u16_at(body) != 1 {
+                    return Err(NesError::InvalidWav(format!("不支持的编码格式 {}", u16_at(body))));
+                }
+                format = Some((u16_at(body + 2) as usize, u32_at(body + 4), u16_at(body + 14)));
+            }
+            b"data" => {
+                let (channels, sample_rate, bits) =
+                    format.ok_or_else(|| NesError::InvalidWav("data 块之前没有 fmt 块".to_string()))?;
+                // 有的录音软件写出的 data 长度超过文件末尾，按实际长度读取
+                let samples = &data[body..end.unwrap_or(data.len())];
+                let frame_size = channels.max(1) * (bits as usize / 8);
+                let samples = match bits {
+                    8 => samples.chunks_exact(frame_size).map(|frame| (frame[0] as i16 - 128) << 8).collect(),
+                    16 => samples.chunks_exact(frame_size).map(|frame| i16::from_le_bytes([frame[0], frame[1]])).collect(),
+                    _ => return Err(NesError::InvalidWav(format!("不支持{}位采样", bits))),
+                };
+                return Ok((samples, sample_rate));
+            }
+            _ => {}
+        }
+        // 块的长度为奇数时后面有1字节填充
+        offset = body + size + (size & 1);
+    }
+    Err(NesError::InvalidWav("没有 data 块".to_string()))
+}
+
+// 写出单声道16位 PCM 的 WAV 文件
+pub fn write_wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_size = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + samples.len() * 2);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // 单声道
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
@@ -11,6 +11,12 @@ use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::time::Instant;
 use rfd::FileDialog;
+use crate::utils::archive;
+use crate::utils::movie::MovieMode;
+use crate::bindings::{self, BindingTarget, Hotkey, KeyBindings, BUTTON_NAMES};
+use crate::gamepad::{GamepadInput, AXES};
+use crate::keyboard::FamilyKeyboardInput;
+use crate::input::{Expansion, PortConfig, PortDevice, TapeMode};
 
 #[derive( serde::Serialize)]
 
@@ -20,6 +26,12 @@ struct Status {
     rom_path: String,
     log_enabled: bool,
     run_to_cycle: String,
+    load_error: Option<String>, // 最近一次加载 ROM 失败的原因
+    archive_choice: Option<(String, Vec<String>)>, // 压缩包中有多个 ROM 时等待选择：(压缩包路径, 条目)
+    state_slot: u8, // 当前即时存档槽 0~9
+    rewind_seconds: u32, // 回溯历史长度
+    rewind_interval: u32, // 每多少帧保存一个回溯快照
+    movie_read_only: bool, // 播放录像时读档不修改录像
 }
 
 struct CpuState {
@@ -30,6 +42,7 @@ struct CpuState {
     sp: u8,
     p: u8,
     cycles: u64,
+    jammed: Option<u16>, // 执行 JAM 后停机的地址
     log: VecDeque<String>,
 }
 struct PpuState{
@@ -63,8 +76,15 @@ pub struct MyApp {
     fps_show: f64,
     window_status: Status,
     emulator_state: EmulatorState,
+    key_bindings: KeyBindings,
+    waiting_binding: Option<BindingTarget>, // 正在等待按键的设置项
+    gamepad: GamepadInput,
+    family_keyboard: FamilyKeyboardInput, // 接了 Family BASIC 键盘时主机键盘的输入
 }
 
+// 按住快进键时每次刷新运行的帧数
+const FAST_FORWARD_FRAMES: usize = 4;
+
 
 fn setup_custom_fonts(ctx: &egui::Context) {
     // Start with the default fonts (we will be adding to them rather than replacing them).
@@ -100,7 +120,7 @@ impl MyApp {
     pub fn new(cc: &eframe::CreationContext<'_>,emulator: Emulator) -> Self {
         setup_custom_fonts(&cc.egui_ctx);
         let fps_target = 60.0;
-        Self {
+        let mut app = Self {
             emulator,
             image: RetainedImage::from_image_bytes(
                 "rust-logo-256x256.png",
@@ -118,6 +138,12 @@ impl MyApp {
                 rom_path: String::from(""),
                 log_enabled: false,
                 run_to_cycle: "0".to_string(),
+                load_error: None,
+                archive_choice: None,
+                state_slot: 0,
+                rewind_seconds: 60,
+                rewind_interval: 2,
+                movie_read_only: true,
             },
             emulator_state: EmulatorState{
                 cpu_state: CpuState{
@@ -128,6 +154,7 @@ impl MyApp {
                     sp: 0,
                     p: 0,
                     cycles: 0,
+                    jammed: None,
                     log: VecDeque::with_capacity(100),
                 },
                 ppu_state: PpuState{
@@ -145,6 +172,34 @@ impl MyApp {
                 },
                 frame: 0,
             },
+            // 恢复上次保存的按键设置
+            key_bindings: cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, bindings::STORAGE_KEY))
+                .unwrap_or_default(),
+            waiting_binding: None,
+            gamepad: GamepadInput::new(),
+            family_keyboard: FamilyKeyboardInput::new(),
+        };
+        app.apply_turbo();
+        app
+    }
+
+    // 把按键设置中的连发间隔交给模拟器
+    fn apply_turbo(&mut self) {
+        for (player, rates) in self.key_bindings.turbo.iter().enumerate() {
+            for (button, rate) in rates.iter().enumerate() {
+                self.emulator.input_filter_mut().set_turbo(player, button, *rate);
+            }
+        }
+    }
+
+    fn toggle_macro_recording(&mut self) {
+        let filter = self.emulator.input_filter_mut();
+        if filter.is_macro_recording() {
+            filter.stop_macro_recording();
+        } else {
+            filter.start_macro_recording();
         }
     }
 
@@ -156,15 +211,16 @@ impl MyApp {
         self.emulator_state.cpu_state.sp = self.emulator.cpu.registers.sp;
         self.emulator_state.cpu_state.p = self.emulator.cpu.registers.p;
         self.emulator_state.cpu_state.cycles = self.emulator.cpu.cpu_cycle;
+        self.emulator_state.cpu_state.jammed = self.emulator.cpu.jammed();
         self.emulator_state.cpu_state.log.push_front(self.emulator.cpu.get_current_log());
         if self.emulator_state.cpu_state.log.len() > 100 {
             self.emulator_state.cpu_state.log.pop_back();
         }
     }
     fn update_ppu_state(&mut self) {
-        self.emulator_state.ppu_state.scanline = self.emulator.ppu.scanline;
-        self.emulator_state.ppu_state.dot = self.emulator.ppu.dot;
-        self.emulator_state.ppu_state.cycles = self.emulator.ppu.cycles;
+        self.emulator_state.ppu_state.scanline = self.emulator.ppu.borrow().scanline;
+        self.emulator_state.ppu_state.dot = self.emulator.ppu.borrow().dot;
+        self.emulator_state.ppu_state.cycles = self.emulator.ppu.borrow().cycles;
         self.emulator_state.ppu_state.ppuctrl = self.emulator.bus.borrow().registers.ppuctrl;
         self.emulator_state.ppu_state.ppumask = self.emulator.bus.borrow().registers.ppumask;
         self.emulator_state.ppu_state.ppustatus = self.emulator.bus.borrow().registers.ppustatus;
@@ -172,7 +228,7 @@ impl MyApp {
     fn update_bus_state(&mut self) {
         let interrupt_status =  self.emulator.bus.borrow_mut().interrupt_status;
         self.emulator_state.bus_state.nmi = interrupt_status>>1 & 1 == 1;
-        self.emulator_state.bus_state.irq = interrupt_status & 1 == 1;
+        self.emulator_state.bus_state.irq = self.emulator.bus.borrow().irq_sources() != 0;
         self.emulator_state.bus_state.reset = interrupt_status>>2 & 1 == 1;
     }
     fn update_emulator_state(&mut self) {
@@ -182,12 +238,24 @@ impl MyApp {
     }
 
     fn loop_to_frame(&mut self) -> Frame {
-        while !self.emulator.ppu.new_frame {
-            self.emulator.cpu_step();
+        // 字符键按模拟帧计时，每帧取一次键盘矩阵
+        if self.emulator.expansion() == Expansion::FamilyBasicKeyboard {
+            let matrix = self.family_keyboard.next_frame();
+            self.emulator.set_keyboard(matrix);
         }
-        self.emulator.ppu.new_frame = false;
+        self.emulator.run_frame();
+        self.current_frame()
+    }
+
+    // 回退一个快照，画面也恢复为当时的画面
+    fn rewind_to_frame(&mut self) -> Frame {
+        self.emulator.rewind_step();
+        self.current_frame()
+    }
+
+    fn current_frame(&self) -> Frame {
         Frame {
-            data: self.emulator.ppu.frame_color_index_cache.to_vec(),
+            data: self.emulator.ppu.borrow().frame_color_index_cache.to_vec(),
             width: 256,
             height: 240,
         }
@@ -222,16 +290,350 @@ impl MyApp {
             ),
         )
     }
+
+    fn load_rom_entry(&mut self, path: &str, entry: Option<&str>) {
+        match self.emulator.load_rom_entry(path, entry) {
+            Ok(()) => {
+                let file_name = std::path::Path::new(path).file_name().unwrap_or_default().to_string_lossy().to_string();
+                self.window_status.rom_path = match entry {
+                    Some(entry) => format!("{}/{}", file_name, entry),
+                    None => file_name,
+                };
+                self.window_status.load_error = None;
+            }
+            Err(error) => {
+                self.window_status.load_error = Some(error.to_string());
+            }
+        }
+        self.update_emulator_state();
+    }
+
+    fn save_state_slot(&mut self) {
+        if let Err(error) = self.emulator.save_state_slot(self.window_status.state_slot) {
+            self.window_status.load_error = Some(error.to_string());
+        }
+    }
+
+    fn load_state_slot(&mut self) {
+        match self.emulator.load_state_slot(self.window_status.state_slot) {
+            Ok(()) => {
+                // 显示存档时的画面
+                self.image = self.frame_to_color_image(&self.current_frame());
+                self.window_status.load_error = None;
+            }
+            Err(error) => {
+                self.window_status.load_error = Some(error.to_string());
+            }
+        }
+        self.update_emulator_state();
+    }
+
+    // 快捷键，按键可在设置中修改
+    fn handle_hotkeys(&mut self, ctx: &egui::Context) {
+        if self.waiting_binding.is_some() {
+            self.capture_binding(ctx);
+            return;
+        }
+        // 键盘给 Family BASIC 键盘使用
+        if self.emulator.expansion() == Expansion::FamilyBasicKeyboard {
+            return;
+        }
+        let pressed = |hotkey: Hotkey| ctx.input(|i| i.key_pressed(self.key_bindings.hotkey(hotkey)));
+        let (pause, reset, save, load, next_slot, screenshot) = (
+            pressed(Hotkey::Pause),
+            pressed(Hotkey::Reset),
+            pressed(Hotkey::SaveState),
+            pressed(Hotkey::LoadState),
+            pressed(Hotkey::NextSlot),
+            pressed(Hotkey::Screenshot),
+        );
+        let (record_macro, play_macro) = (pressed(Hotkey::RecordMacro), pressed(Hotkey::PlayMacro));
+        if record_macro {
+            self.toggle_macro_recording();
+        }
+        if play_macro {
+            self.emulator.input_filter_mut().play_macro();
+        }
+        if pause {
+            self.window_status.paused = !self.window_status.paused;
+            self.update_emulator_state();
+        }
+        if reset {
+            self.emulator.reset();
+            self.update_emulator_state();
+        }
+        if next_slot {
+            self.window_status.state_slot = (self.window_status.state_slot + 1) % 10;
+        }
+        if save {
+            self.save_state_slot();
+        }
+        if load {
+            self.load_state_slot();
+        }
+        if screenshot {
+            self.take_screenshot();
+        }
+    }
+
+    // 等待设置的按键，Esc 取消
+    fn capture_binding(&mut self, ctx: &egui::Context) {
+        let Some(target) = self.waiting_binding else {
+            return;
+        };
+        if let BindingTarget::GamepadButton(button) = target {
+            if let Some(gamepad_button) = self.gamepad.take_pressed() {
+                self.key_bindings.set_gamepad_button(button, gamepad_button);
+                self.waiting_binding = None;
+            } else if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.waiting_binding = None;
+            }
+            return;
+        }
+        let key = ctx.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Key { key, pressed: true, .. } => Some(*key),
+                _ => None,
+            })
+        });
+        match key {
+            Some(egui::Key::Escape) => self.waiting_binding = None,
+            Some(key) => {
+                self.key_bindings.set(target, key);
+                self.waiting_binding = None;
+            }
+            None => {}
+        }
+    }
+
+    // 显示按键，点击后等待新的按键
+    fn binding_button(&mut self, ui: &mut egui::Ui, target: BindingTarget) {
+        let text = if self.waiting_binding == Some(target) {
+            "请按键...".to_string()
+        } else {
+            self.key_bindings.name(target)
+        };
+        if ui.button(text).clicked() {
+            self.waiting_binding = Some(target);
+            self.gamepad.clear_pressed();
+        }
+    }
+
+    fn show_key_bindings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("按键设置").show(ui, |ui| {
+            for port in 0..2 {
+                ui.label(format!("{}P", port + 1));
+                egui::Grid::new(("controller_bindings", port)).show(ui, |ui| {
+                    for (button, name) in BUTTON_NAMES.iter().enumerate() {
+                        ui.label(*name);
+                        self.binding_button(ui, BindingTarget::Button { port, button });
+                        if button % 2 == 1 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            }
+            ui.label("快捷键");
+            egui::Grid::new("hotkey_bindings").show(ui, |ui| {
+                for (i, hotkey) in Hotkey::ALL.iter().enumerate() {
+                    ui.label(hotkey.name());
+                    self.binding_button(ui, BindingTarget::Hotkey(*hotkey));
+                    if i % 2 == 1 {
+                        ui.end_row();
+                    }
+                }
+            });
+            ui.label("连发间隔 (帧, 0 为关闭)");
+            let players = self.emulator.port_config().players();
+            let mut turbo_changed = false;
+            egui::Grid::new("turbo_settings").show(ui, |ui| {
+                ui.label("");
+                for name in BUTTON_NAMES.iter() {
+                    ui.label(*name);
+                }
+                ui.end_row();
+                for player in 0..players {
+                    ui.label(format!("{}P", player + 1));
+                    for button in 0..8 {
+                        let rate = &mut self.key_bindings.turbo[player][button];
+                        turbo_changed |= ui.add(egui::DragValue::new(rate).clamp_range(0..=30)).changed();
+                    }
+                    ui.end_row();
+                }
+            });
+            if turbo_changed {
+                self.apply_turbo();
+            }
+            ui.label("Power Pad");
+            egui::Grid::new("power_pad_bindings").show(ui, |ui| {
+                for button in 0..12 {
+                    ui.label(format!("{}", button + 1));
+                    self.binding_button(ui, BindingTarget::PowerPad(button));
+                    if button % 4 == 3 {
+                        ui.end_row();
+                    }
+                }
+            });
+            self.show_gamepad_bindings(ui);
+            if ui.button("恢复默认").clicked() {
+                self.key_bindings = KeyBindings::default();
+                self.waiting_binding = None;
+                self.apply_turbo();
+            }
+        });
+    }
+
+    // 手柄的分配和按键设置，两个手柄使用同一套设置
+    fn show_gamepad_bindings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let names = self.gamepad.port_names();
+            let players = self.emulator.port_config().players();
+            let names: Vec<String> = names[..players]
+                .iter()
+                .enumerate()
+                .map(|(player, name)| format!("{}P: {}", player + 1, name.as_deref().unwrap_or("无")))
+                .collect();
+            ui.label(format!("手柄 {}", names.join(" ")));
+            if ui.button("交换").clicked() {
+                self.gamepad.swap_ports();
+            }
+        });
+        egui::Grid::new("gamepad_bindings").show(ui, |ui| {
+            for (button, name) in BUTTON_NAMES.iter().enumerate() {
+                ui.label(*name);
+                self.binding_button(ui, BindingTarget::GamepadButton(button));
+                if button % 2 == 1 {
+                    ui.end_row();
+                }
+            }
+        });
+        let mapping = &mut self.key_bindings.gamepad;
+        ui.horizontal(|ui| {
+            for (label, axis) in [("左右轴", &mut mapping.axis_x), ("上下轴", &mut mapping.axis_y)] {
+                ui.label(label);
+                egui::ComboBox::from_id_source(label)
+                    .selected_text(format!("{:?}", axis))
+                    .show_ui(ui, |ui| {
+                        for option in AXES {
+                            ui.selectable_value(axis, option, format!("{:?}", option));
+                        }
+                    });
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("摇杆阈值");
+            ui.add(egui::Slider::new(&mut mapping.threshold, 0.1..=0.9));
+        });
+    }
+
+    // 截图保存在 ROM 旁边：game.nes.<时间戳>.png
+    fn take_screenshot(&mut self) {
+        let Some(rom_path) = self.emulator.rom_path().map(|path| path.to_string()) else {
+            self.window_status.load_error = Some("还没有加载游戏".to_string());
+            return;
+        };
+        let frame = self.current_frame();
+        let mut rgba_data = Vec::with_capacity(frame.data.len() * 4);
+        for color_index in frame.data.iter() {
+            rgba_data.extend_from_slice(&self.palette.colors[*color_index as usize]);
+        }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis());
+        let path = format!("{}.{}.png", rom_path, timestamp);
+        if let Err(error) = image::save_buffer(&path, &rgba_data, frame.width, frame.height, image::ColorType::Rgba8) {
+            self.window_status.load_error = Some(format!("截图失败: {}", error));
+        }
+    }
+
+    // 开始录像后显示上电或存档时的画面
+    fn start_recording(&mut self, from_savestate: bool) {
+        match self.emulator.start_recording(from_savestate) {
+            Ok(()) => {
+                self.image = self.frame_to_color_image(&self.current_frame());
+                self.window_status.load_error = None;
+            }
+            Err(error) => {
+                self.window_status.load_error = Some(error.to_string());
+            }
+        }
+        self.update_emulator_state();
+    }
+
+    fn play_movie(&mut self) {
+        let Some(files) = FileDialog::new().add_filter("fm2", &["fm2"]).pick_file() else {
+            return;
+        };
+        match self.emulator.play_movie(&files.to_string_lossy(), self.window_status.movie_read_only) {
+            Ok(()) => {
+                self.image = self.frame_to_color_image(&self.current_frame());
+                self.window_status.load_error = None;
+            }
+            Err(error) => {
+                self.window_status.load_error = Some(error.to_string());
+            }
+        }
+        self.update_emulator_state();
+    }
+
+    fn save_movie(&mut self) {
+        let Some(files) = FileDialog::new().add_filter("fm2", &["fm2"]).save_file() else {
+            return;
+        };
+        if let Err(error) = self.emulator.save_movie(&files.to_string_lossy()) {
+            self.window_status.load_error = Some(error.to_string());
+        }
+    }
+
+    // 选择压缩包中要加载的 ROM
+    fn show_archive_choice(&mut self, ctx: &egui::Context) {
+        let Some((path, entries)) = self.window_status.archive_choice.clone() else {
+            return;
+        };
+        let mut open = true;
+        let mut selected = None;
+        egui::Window::new("选择 ROM").open(&mut open).show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for entry in &entries {
+                    if ui.button(entry).clicked() {
+                        selected = Some(entry.clone());
+                    }
+                }
+            });
+        });
+        if let Some(entry) = selected {
+            self.window_status.archive_choice = None;
+            self.load_rom_entry(&path, Some(&entry));
+        } else if !open {
+            self.window_status.archive_choice = None;
+        }
+    }
 }
 
 
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.gamepad.update();
         if !self.window_status.paused {
             // 接收新图像
             if self.current_time.elapsed().as_secs_f64() > 1.0 / self.sample_frq {
-                let new_frame = self.loop_to_frame();
+                // 按住倒放键时倒放，按住快进键时一次运行多帧
+                let keyboard_attached = self.emulator.expansion() == Expansion::FamilyBasicKeyboard;
+                let (rewinding, fast_forward) = ctx.input(|i| {
+                    (i.key_down(self.key_bindings.rewind), i.key_down(self.key_bindings.fast_forward))
+                });
+                let (rewinding, fast_forward) = (rewinding && !keyboard_attached, fast_forward && !keyboard_attached);
+                let new_frame = if rewinding {
+                    self.rewind_to_frame()
+                } else if fast_forward {
+                    for _ in 1..FAST_FORWARD_FRAMES {
+                        self.loop_to_frame();
+                    }
+                    self.loop_to_frame()
+                } else {
+                    self.loop_to_frame()
+                };
                 self.image = self.frame_to_color_image(&new_frame);
                 self.fps_history
                     .push_front(1.0 / self.current_time.elapsed().as_secs_f64());
@@ -255,6 +657,9 @@ impl eframe::App for MyApp {
             }
         }
 
+        self.show_archive_choice(ctx);
+        self.handle_hotkeys(ctx);
+
         // 增加暂停按钮
         egui::SidePanel::left("side_panel_left").show(ctx, |ui| {
             ui.heading("Controls");
@@ -263,14 +668,247 @@ impl eframe::App for MyApp {
                 ui.label(format!("ROM: {}",&self.window_status.rom_path));
                 if ui.button("Load").clicked() {
                     let files = FileDialog::new()
-                        .add_filter("nes", &["nes"])
+                        .add_filter("nes", &["nes", "unf", "unif", "fds", "zip", "gz"])
                         .set_directory("/")
-                        .pick_file().unwrap();
-                    self.window_status.rom_path = files.file_name().unwrap().to_str().unwrap().to_string();
-                    self.emulator.load_rom(files.to_str().unwrap());
-                    self.update_emulator_state();
+                        .pick_file();
+                    if let Some(files) = files {
+                        let path = files.to_string_lossy().to_string();
+                        if archive::is_archive(&path) {
+                            // 压缩包中有多个 ROM 时弹出选择窗口
+                            match archive::list_rom_entries(&path) {
+                                Ok(entries) if entries.len() > 1 => {
+                                    self.window_status.archive_choice = Some((path, entries));
+                                }
+                                Ok(_) => self.load_rom_entry(&path, None),
+                                Err(error) => self.window_status.load_error = Some(error.to_string()),
+                            }
+                        } else {
+                            self.load_rom_entry(&path, None);
+                        }
+                    }
                 }
             });
+            if let Some(error) = &self.window_status.load_error {
+                ui.colored_label(Color32::RED, format!("加载失败: {}", error));
+            }
+            if let Some(address) = self.emulator.cpu.jammed() {
+                ui.colored_label(Color32::RED, format!("CPU jammed at ${:04X}，需要复位", address));
+            }
+            // 游戏数据库匹配结果
+            if let Some(game_info) = self.emulator.game_info() {
+                ui.label(format!("{}\n{} {:?}", game_info.title, game_info.board, game_info.region));
+            }
+            // 加载用户数据库
+            ui.horizontal(|ui| {
+                ui.label(format!("游戏数据库: {}条", self.emulator.game_db.len()));
+                if ui.button("Load DB").clicked() {
+                    let files = FileDialog::new()
+                        .add_filter("nes20db", &["xml", "csv"])
+                        .pick_file();
+                    if let Some(files) = files {
+                        if let Err(error) = self.emulator.load_game_db(&files.to_string_lossy()) {
+                            self.window_status.load_error = Some(error.to_string());
+                        }
+                    }
+                }
+            });
+            // FDS BIOS
+            ui.horizontal(|ui| {
+                ui.label("FDS BIOS");
+                if ui.button("Load BIOS").clicked() {
+                    let files = FileDialog::new()
+                        .add_filter("disksys", &["rom", "bin"])
+                        .pick_file();
+                    if let Some(files) = files {
+                        if let Err(error) = self.emulator.load_fds_bios(&files.to_string_lossy()) {
+                            self.window_status.load_error = Some(error.to_string());
+                        }
+                    }
+                }
+            });
+            // FDS 磁盘插入/弹出/换面
+            let side_count = self.emulator.disk_side_count();
+            if side_count > 0 {
+                ui.horizontal(|ui| {
+                    let disk_name = |side: usize| format!("磁盘{} {}面", side / 2 + 1, if side % 2 == 0 { "A" } else { "B" });
+                    let inserted = self.emulator.inserted_disk_side();
+                    let mut selected = inserted;
+                    egui::ComboBox::from_id_source("disk_side")
+                        .selected_text(inserted.map_or("未插入".to_string(), disk_name))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut selected, None, "未插入");
+                            for side in 0..side_count {
+                                ui.selectable_value(&mut selected, Some(side), disk_name(side));
+                            }
+                        });
+                    let mut result = Ok(());
+                    if selected != inserted {
+                        result = self.emulator.insert_disk_side(selected);
+                    }
+                    if ui.button("弹出").clicked() {
+                        result = self.emulator.insert_disk_side(None);
+                    }
+                    if ui.button("换面").clicked() {
+                        result = self.emulator.swap_disk_side();
+                    }
+                    if let Err(error) = result {
+                        self.window_status.load_error = Some(error.to_string());
+                    }
+                });
+            }
+            // 端口接法和 2P 端口上的设备
+            ui.horizontal(|ui| {
+                ui.label("端口");
+                let current = self.emulator.port_config();
+                let mut selected = current;
+                egui::ComboBox::from_id_source("port_config")
+                    .selected_text(current.name())
+                    .show_ui(ui, |ui| {
+                        for config in PortConfig::ALL {
+                            ui.selectable_value(&mut selected, config, config.name());
+                        }
+                    });
+                if selected != current {
+                    self.emulator.set_port_config(selected);
+                }
+                if selected == PortConfig::TwoPlayers {
+                    ui.label("2P设备");
+                    let current = self.emulator.port_device(1);
+                    let mut selected = current;
+                    egui::ComboBox::from_id_source("port2_device")
+                        .selected_text(current.name())
+                        .show_ui(ui, |ui| {
+                            for device in PortDevice::ALL {
+                                ui.selectable_value(&mut selected, device, device.name());
+                            }
+                        });
+                    if selected != current {
+                        self.emulator.set_port_device(1, selected);
+                    }
+                }
+                ui.label("扩展口");
+                let current = self.emulator.expansion();
+                let mut selected = current;
+                egui::ComboBox::from_id_source("expansion")
+                    .selected_text(current.name())
+                    .show_ui(ui, |ui| {
+                        for expansion in Expansion::ALL {
+                            ui.selectable_value(&mut selected, expansion, expansion.name());
+                        }
+                    });
+                if selected != current {
+                    self.emulator.set_expansion(selected);
+                }
+            });
+            // Family BASIC 键盘上的数据记录器，磁带为 WAV 文件
+            if let Some((mode, (position, length))) = self.emulator.tape_status() {
+                ui.horizontal(|ui| {
+                    let mode = match mode {
+                        TapeMode::Stopped => "停止",
+                        TapeMode::Playing => "播放",
+                        TapeMode::Recording => "录音",
+                    };
+                    ui.label(format!("磁带: {} {:.1}/{:.1}秒", mode, position, length));
+                    let mut result = Ok(());
+                    if ui.button("播放").clicked() {
+                        if let Some(file) = FileDialog::new().add_filter("wav", &["wav"]).pick_file() {
+                            result = self.emulator.play_tape(&file.to_string_lossy());
+                        }
+                    }
+                    if ui.button("录音").clicked() {
+                        result = self.emulator.record_tape();
+                    }
+                    if ui.button("停止").clicked() {
+                        result = self.emulator.stop_tape();
+                    }
+                    if ui.button("保存").clicked() {
+                        if let Some(file) = FileDialog::new().add_filter("wav", &["wav"]).save_file() {
+                            result = self.emulator.save_tape(&file.to_string_lossy());
+                        }
+                    }
+                    if let Err(error) = result {
+                        self.window_status.load_error = Some(error.to_string());
+                    }
+                });
+            }
+            // 即时存档
+            ui.horizontal(|ui| {
+                ui.label("存档槽");
+                ui.add(egui::DragValue::new(&mut self.window_status.state_slot).clamp_range(0..=9));
+                if ui.button(format!("保存({})", self.key_bindings.save_state.name())).clicked() {
+                    self.save_state_slot();
+                }
+                if ui.button(format!("读取({})", self.key_bindings.load_state.name())).clicked() {
+                    self.load_state_slot();
+                }
+            });
+            // 输入宏，录制前端的按键序列，按热键时重放
+            ui.horizontal(|ui| {
+                let filter = self.emulator.input_filter();
+                let (recording, playing, length) =
+                    (filter.is_macro_recording(), filter.is_macro_playing(), filter.macro_frames().len());
+                ui.label(format!("宏: {}帧", length));
+                let record_text = if recording { "停止录制" } else { "录制" };
+                if ui.button(format!("{}({})", record_text, self.key_bindings.record_macro.name())).clicked() {
+                    self.toggle_macro_recording();
+                }
+                if ui
+                    .add_enabled(!playing && length > 0, egui::Button::new(format!("播放({})", self.key_bindings.play_macro.name())))
+                    .clicked()
+                {
+                    self.emulator.input_filter_mut().play_macro();
+                }
+            });
+            // 回溯设置，按住倒放键倒放
+            ui.horizontal(|ui| {
+                ui.label("回溯(秒)");
+                let seconds = ui.add(egui::DragValue::new(&mut self.window_status.rewind_seconds).clamp_range(1..=600));
+                ui.label("间隔(帧)");
+                let interval = ui.add(egui::DragValue::new(&mut self.window_status.rewind_interval).clamp_range(1..=60));
+                if seconds.changed() || interval.changed() {
+                    self.emulator.set_rewind_config(self.window_status.rewind_seconds, self.window_status.rewind_interval);
+                }
+            });
+            // 输入录像 (.fm2)
+            ui.horizontal(|ui| {
+                ui.label("录像");
+                if ui.button("录制").clicked() {
+                    self.start_recording(false);
+                }
+                if ui.button("从存档录制").clicked() {
+                    self.start_recording(true);
+                }
+                if ui.button("播放").clicked() {
+                    self.play_movie();
+                }
+                if ui.button("保存").clicked() {
+                    self.save_movie();
+                }
+                if ui.button("停止").clicked() {
+                    self.emulator.stop_movie();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut self.window_status.movie_read_only, "只读").changed() {
+                    self.emulator.set_movie_read_only(self.window_status.movie_read_only);
+                }
+                ui.label(format!("帧: {} 延迟: {}", self.emulator.frame_count, self.emulator.lag_count));
+            });
+            if let Some(movie) = self.emulator.movie() {
+                let mode = match movie.mode {
+                    MovieMode::Recording => "录制中",
+                    MovieMode::Playing => "播放中",
+                    MovieMode::Finished => "播放结束",
+                };
+                ui.label(format!(
+                    "{} {}/{}帧 重录: {}{}",
+                    mode,
+                    self.emulator.frame_count,
+                    movie.frames.len(),
+                    movie.rerecord_count,
+                    if movie.read_only { " (只读)" } else { "" }
+                ));
+            }
             // 重新加载
             ui.horizontal(|ui| {
                 if ui.button("Reset").clicked() {
@@ -283,6 +921,8 @@ impl eframe::App for MyApp {
                 }
             });
 
+            self.show_key_bindings(ui);
+
             // 是否记录日志
             ui.horizontal(|ui| {
                 ui.checkbox(&mut self.window_status.log_enabled, "Log");
@@ -301,10 +941,6 @@ impl eframe::App for MyApp {
                 self.update_emulator_state();
                 println!("{}",self.emulator.get_log());
             }
-            if ui.button("cpu时钟执行（clock）").clicked(){
-                self.emulator.cpu_clock_debug();
-                self.update_emulator_state();
-            }
             if ui.button("下一帧").clicked(){
                 let new_frame = self.loop_to_frame();
                 self.image = self.frame_to_color_image(&new_frame);
@@ -333,6 +969,9 @@ impl eframe::App for MyApp {
                 self.emulator_state.cpu_state.p,
                 self.emulator_state.cpu_state.p,
                 self.emulator_state.cpu_state.cycles));
+            if let Some(address) = self.emulator_state.cpu_state.jammed {
+                ui.colored_label(Color32::RED, format!("CPU jammed at ${:04X}", address));
+            }
 
             // 当前执行执行的指令反编译
             let disasm = self.emulator.cpu.disassemble_instruction_short();
@@ -359,21 +998,54 @@ impl eframe::App for MyApp {
                 "FPS: {:.2}, sample frq: {:.2}",
                 self.fps_show, self.sample_frq
             ));
-            ui.add(egui::Image::new(self.image.texture_id(ctx), {
+            let image = ui.add(egui::Image::new(self.image.texture_id(ctx), {
                 let min_x = 256.0;
                 let min_y = 240.0;
                 let (current_x, current_y) = (ui.available_size().x, ui.available_size().y);
                 let scale = ((current_x / min_x).min(current_y / min_y)).max(1.0);
                 egui::vec2(scale * min_x, scale * min_y)
-            }));
+            }).sense(egui::Sense::click()));
+            // 光标位置按画面缩放换算成 NES 坐标，给光枪使用
+            let (pointer_x, pointer_y) = image.hover_pos().map_or((-1, -1), |pos| {
+                let x = (pos.x - image.rect.min.x) / image.rect.width() * 256.0;
+                let y = (pos.y - image.rect.min.y) / image.rect.height() * 240.0;
+                (x as i32, y as i32)
+            });
+            let trigger = image.hovered() && ui.input(|i| i.pointer.primary_down());
+            self.emulator.set_pointer(pointer_x, pointer_y, trigger);
             // 接收输入
             let input_state = ui.input(|i| i.keys_down.clone());
             // println!("{:?}", input_state);
-            self.emulator.refresh_input(input_state);
+            let keyboard_attached = self.emulator.expansion() == Expansion::FamilyBasicKeyboard;
+            let keyboard = if keyboard_attached {
+                self.family_keyboard.take_input(ctx);
+                [0; 2]
+            } else {
+                self.key_bindings.controller_buttons(&input_state)
+            };
+            let gamepad = self.gamepad.buttons(&self.key_bindings.gamepad);
+            // 键盘只设置了 1P/2P，3P/4P 只能用手柄
+            for player in 0..4 {
+                let keyboard = keyboard.get(player).copied().unwrap_or(0);
+                self.emulator.set_buttons(player, keyboard | gamepad[player]);
+            }
+            self.emulator.set_mat_buttons(self.key_bindings.mat_buttons(&input_state));
 
         });
         ctx.request_repaint();
     }
+
+    // 按键设置由 eframe 保存到用户目录
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, bindings::STORAGE_KEY, &self.key_bindings);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // 退出时保存 FDS 磁盘写入
+        if let Err(error) = self.emulator.save_disk() {
+            println!("保存磁盘失败: {}", error);
+        }
+    }
 }
 
 // fn lorem_ipsum(ui: &mut egui::Ui) {